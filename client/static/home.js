@@ -2,11 +2,16 @@ if(!localStorage.getItem("token")){
     window.location.replace("https://budget.nos-web.dev/");
 }
 
-const moneyFormat = new Intl.NumberFormat('en-US', {
+let moneyFormat = new Intl.NumberFormat('en-US', {
     style: 'currency',
     currency: 'USD',
 });
 
+//formatIn(): formats an amount of cents in the given currency, the way the budget's locale writes it
+function formatIn(cents, currency, locale){
+    return new Intl.NumberFormat(locale, {style: 'currency', currency: currency}).format(cents/100);
+}
+
 window.onload = async () => {
     await fetch("/user", {
         method: "get",
@@ -28,7 +33,11 @@ window.onload = async () => {
 
 }
 
+//the command api version this page was written against
+const COMMAND_API_VERSION = 1;
+
 let sendCommand = async (body) => {
+    body.version = COMMAND_API_VERSION;
     let bodyJson = JSON.stringify(body).toLowerCase();
 
     //console.log("sending command: " + bodyJson);
@@ -48,6 +57,11 @@ let updateData = (newdata) => {
     //console.log("updating data:")
     //console.table(newdata);
 
+    //commands that went over a limit come back with warnings
+    if(newdata.warnings && newdata.warnings.length > 0){
+        alert(newdata.warnings.map((warning) => warning.message).join("\n"));
+    }
+
     let text = document.getElementById("data");
     let username = document.getElementById("username");
     let income = document.getElementById("income");
@@ -57,20 +71,48 @@ let updateData = (newdata) => {
     let expectedExpenses = document.getElementById("expectedExpenses");
     
     username.textContent = "Welcome, " + newdata.username + "!";
+    //totals are in the base currency, accounts can be held in others
+    let currency = newdata.currency || {base: "USD", locale: "en-US", rates: {}};
+    moneyFormat = new Intl.NumberFormat(currency.locale, {style: 'currency', currency: currency.base});
     income.textContent = moneyFormat.format(newdata.expected_income/100);
-    balance.textContent = moneyFormat.format(newdata.current_balance/100);
-    savings.textContent = moneyFormat.format(newdata.savings/100);
+    //balance is the primary account, savings is every savings account added up
+    let totalSavings = 0;
+    for(name in newdata.accounts){
+        let account = newdata.accounts[name];
+        if(account.kind == "savings"){
+            totalSavings += account.balance * (account.currency ? currency.rates[account.currency] : 1);
+        }
+    }
+    let primary = newdata.accounts[newdata.primary_account];
+    balance.textContent = formatIn(primary.balance, primary.currency || currency.base, currency.locale);
+    savings.textContent = moneyFormat.format(totalSavings/100);
     
     expectedExpenses.textContent = '';
+    //with envelopes on, each expense also shows what's left in its envelope
+    let envelopes = {};
+    for(envelope of newdata.envelope_balances || []){
+        envelopes[envelope.category] = envelope;
+    }
     for(el in newdata.expected_expenses){
+        //archived expenses aren't budgeted for anymore
+        let category = newdata.categories[el] || {};
+        if(category.archived){
+            continue;
+        }
         let data = document.createElement("li");
         let label = document.createElement("label");
         let value = document.createElement("div");
         value.classList.add("data-item");
 
         let labelText = el.charAt(0).toUpperCase() + el.substring(1);
+        if(category.automatic){
+            labelText += " (automatic)";
+        }
         label.textContent = labelText;
         value.textContent = moneyFormat.format(newdata.current_expenses[el]/100) + "/" + moneyFormat.format(newdata.expected_expenses[el]/100);
+        if(envelopes[el]){
+            value.textContent += " (" + moneyFormat.format(envelopes[el].available/100) + " available)";
+        }
         data.appendChild(label);
         data.appendChild(value);
         expectedExpenses.appendChild(data);
@@ -78,6 +120,17 @@ let updateData = (newdata) => {
     clearInputs();
 }
 
+//showError(): tells the user why a command failed, using the error body's message
+let showError = async (response) => {
+    let error = await response.json().catch(() => null);
+    if(error && error.message){
+        alert(error.field ? error.message + " (" + error.field + ")" : error.message);
+    }
+    else{
+        alert("bad command!");
+    }
+}
+
 let clearInputs = () => {
     document.getElementById("commandtarget").value = "";
     document.getElementById("commanddollarvalue").value = "";
@@ -96,7 +149,7 @@ let addNewExpense = async () => {
     let response = await sendCommand(body)
 
     if(response.status != 200){
-        alert("bad command!");
+        await showError(response);
     }
     else{
         let data = await response.json();
@@ -121,7 +174,7 @@ let payExpense = async () => {
     let response = await sendCommand(body)
 
     if(response.status != 200){
-        alert("bad command!");
+        await showError(response);
     }
     else{
         let data = await response.json();
@@ -144,7 +197,7 @@ let getPaid = async () => {
     let response = await sendCommand(body);
 
     if(response.status != 200){
-        alert("bad command!");
+        await showError(response);
     }
     else{
         let data = await response.json();
@@ -167,7 +220,7 @@ let setIncome = async () => {
     let response = await sendCommand(body);
 
     if(response.status != 200){
-        alert("bad command!");
+        await showError(response);
     }
     else{
         let data = await response.json();
@@ -189,7 +242,7 @@ let raiseIncome = async () => {
     let response = await sendCommand(body);
 
     if(response.status != 200){
-        alert("bad command!");
+        await showError(response);
     }
     else{
         let data = await response.json();
@@ -211,7 +264,7 @@ let save = async () => {
     let response = await sendCommand(body);
 
     if(response.status != 200){
-        alert("bad command!");
+        await showError(response);
     }
     else{
         let data = await response.json();
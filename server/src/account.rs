@@ -0,0 +1,129 @@
+use serde::{self, Deserialize, Serialize};
+
+//AccountKind: what sort of real-world account an Account tracks
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountKind {
+    Checking,
+    Savings,
+    #[serde(alias = "creditcard", alias = "credit")]
+    CreditCard,
+    Cash,
+}
+
+//Account: one named account belonging to a user, with its own balance in cents
+//credit card balances go negative as money is owed on them
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Account {
+    pub kind: AccountKind,
+    pub balance: i64,
+    //the currency the balance is held in, the budget's base currency if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+}
+impl Account {
+    //new(): creates an account of the given kind, holding the given opening balance
+    pub fn new(kind: AccountKind, balance: i64) -> Account {
+        Account { kind, balance, currency: None }
+    }
+
+    //can_afford(): whether the given amount can be taken out of the account
+    //credit cards can always be charged, everything else can't go below zero
+    pub fn can_afford(&self, cents: i64) -> bool {
+        self.kind == AccountKind::CreditCard || self.balance >= cents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::Budget;
+
+    //household(): a budget with money in checking, a credit card and some cash
+    fn household() -> Budget {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.get_paid_value(None, 10000).unwrap();
+        budget.add_account("Visa", AccountKind::CreditCard, 0, None).unwrap();
+        budget.add_account("wallet", AccountKind::Cash, 2000, None).unwrap();
+        budget
+    }
+
+    //balances(): every account's balance by name, read off the budget's json
+    fn balances(budget: &Budget) -> Vec<(String, i64)> {
+        let value = serde_json::to_value(budget).unwrap();
+        value["accounts"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(name, account)| (name.clone(), account["balance"].as_i64().unwrap()))
+            .collect()
+    }
+
+    //balance(): one account's balance
+    fn balance(budget: &Budget, name: &str) -> i64 {
+        balances(budget).into_iter().find(|(account, _)| account == name).unwrap().1
+    }
+
+    #[test]
+    fn only_credit_cards_go_below_zero() {
+        assert!(Account::new(AccountKind::CreditCard, 0).can_afford(500));
+        assert!(Account::new(AccountKind::Cash, 500).can_afford(500));
+        assert!(!Account::new(AccountKind::Checking, 499).can_afford(500));
+    }
+
+    #[test]
+    fn opens_accounts_by_lowercase_name() {
+        let mut budget = household();
+        let names: Vec<String> = balances(&budget).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["checking", "savings", "visa", "wallet"]);
+        assert_eq!(budget.add_account("VISA", AccountKind::Cash, 0, None), Err(String::from("account_already_exists")));
+        assert_eq!(budget.add_account("", AccountKind::Cash, 0, None), Err(String::from("invalid_account_name")));
+        assert_eq!(budget.total_balance(), 12000);
+
+        budget.set_primary_account("Wallet").unwrap();
+        assert_eq!(budget.resolve_account(None), Ok(String::from("wallet")));
+        assert_eq!(budget.set_primary_account("vault"), Err(String::from("account_not_found")));
+    }
+
+    #[test]
+    fn transfers_move_money_between_accounts() {
+        let mut budget = household();
+        budget.transfer(None, "wallet", 2500).unwrap();
+        assert_eq!((balance(&budget, "checking"), balance(&budget, "wallet")), (7500, 4500));
+
+        //paying a credit card off, then charging it again past zero
+        budget.transfer(Some("visa"), "checking", 3000).unwrap();
+        assert_eq!((balance(&budget, "visa"), balance(&budget, "checking")), (-3000, 10500));
+        budget.transfer_all(Some("wallet"), "savings").unwrap();
+        assert_eq!((balance(&budget, "wallet"), balance(&budget, "savings")), (0, 4500));
+        assert_eq!(budget.total_balance(), 12000);
+        assert_eq!(budget.close_period().saved, 4500);
+    }
+
+    #[test]
+    fn bad_transfers_change_nothing() {
+        let mut budget = household();
+        let before = serde_json::to_string(&budget).unwrap();
+        for (from, to, cents, code) in [
+            (None, "checking", 100, "same_account_transfer"),
+            (None, "wallet", -100, "negative_transfer_amount"),
+            (Some("wallet"), "checking", 2001, "insufficient_balance"),
+            (Some("vault"), "checking", 100, "account_not_found"),
+            (None, "vault", 100, "account_not_found"),
+        ] {
+            assert_eq!(budget.transfer(from, to, cents), Err(String::from(code)));
+        }
+        assert_eq!(serde_json::to_string(&budget).unwrap(), before);
+    }
+
+    #[test]
+    fn payments_come_out_of_the_named_account() {
+        let mut budget = household();
+        budget.add_expense("food", 5000, None);
+        budget.make_dynamic_payment(Some("visa"), "food", 1500).unwrap();
+        budget.make_dynamic_payment(Some("wallet"), "food", 500).unwrap();
+        assert_eq!((balance(&budget, "visa"), balance(&budget, "wallet"), balance(&budget, "checking")), (-1500, 1500, 10000));
+        assert_eq!(budget.make_dynamic_payment(Some("vault"), "food", 100), Err(String::from("account_not_found")));
+        assert_eq!(budget.make_dynamic_payment(Some("wallet"), "rent", 100), Err(String::from("expense_not_found")));
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{self, Deserialize, Serialize};
+
+//how many notifications are kept on a budget, the oldest are dropped first
+pub const MAX_NOTIFICATIONS: usize = 50;
+
+//LimitPolicy: what happens when a payment would break a limit
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitPolicy {
+    //let it through quietly
+    Allow,
+    //let it through, and warn the user
+    #[default]
+    Warn,
+    //turn the payment down
+    Block,
+}
+impl LimitPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == LimitPolicy::default()
+    }
+}
+
+//BalanceFloor: the lowest an account's balance should go, for every account except credit cards
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BalanceFloor {
+    pub amount: i64,
+    pub policy: LimitPolicy,
+}
+
+//Notification: a warning raised while changing a budget, kept until the user clears it
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Notification {
+    pub timestamp: i64,
+    //snake_case, like error codes (over_limit, below_balance_floor, payment_overdue)
+    pub code: String,
+    //the expense or account the warning is about
+    pub label: String,
+    pub message: String,
+}
+impl Notification {
+    //new(): creates a notification stamped with the current time
+    pub fn new(code: &str, label: &str, message: String) -> Notification {
+        Notification {
+            timestamp: chrono::Utc::now().timestamp(),
+            code: code.to_string(),
+            label: label.to_string(),
+            message,
+        }
+    }
+}
+
+//Breach: a limit a payment would break, and what to do about it
+pub enum Breach {
+    None,
+    Warn(Notification),
+    Block(String),
+}
+
+//check(): applies a policy to a limit that would be broken, if it would be
+pub fn check(policy: LimitPolicy, broken: bool, code: &str, label: &str, message: String) -> Breach {
+    match (broken, policy) {
+        (false, _) | (true, LimitPolicy::Allow) => Breach::None,
+        (true, LimitPolicy::Warn) => Breach::Warn(Notification::new(code, label, message)),
+        (true, LimitPolicy::Block) => Breach::Block(code.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::AccountKind;
+    use crate::budget::Budget;
+
+    //codes(): the codes of every notification on the budget, oldest first
+    fn codes(budget: &Budget) -> Vec<&str> {
+        budget.notifications().iter().map(|notification| notification.code.as_str()).collect()
+    }
+
+    //groceries(): a budget with food to spend on and nothing spent yet
+    fn groceries() -> Budget {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.get_paid_value(None, 10000).unwrap();
+        budget.add_expense("food", 3000, None);
+        budget
+    }
+
+    #[test]
+    fn policies_decide_what_happens_past_a_limit() {
+        assert!(matches!(check(LimitPolicy::Block, false, "over_limit", "food", String::new()), Breach::None));
+        assert!(matches!(check(LimitPolicy::Allow, true, "over_limit", "food", String::new()), Breach::None));
+        assert!(matches!(check(LimitPolicy::Warn, true, "over_limit", "food", String::new()), Breach::Warn(_)));
+        assert!(matches!(check(LimitPolicy::Block, true, "over_limit", "food", String::new()), Breach::Block(code) if code == "over_limit"));
+    }
+
+    #[test]
+    fn warns_about_or_blocks_spending_over_the_expected_amount() {
+        let mut budget = groceries();
+        budget.make_dynamic_payment(None, "food", 3000).unwrap();
+        assert!(budget.take_warnings().is_empty());
+        budget.make_dynamic_payment(None, "food", 1).unwrap();
+        assert_eq!(budget.take_warnings()[0].message, "Food is over budget: $30.01 of $30.00");
+
+        budget.set_policy("food", LimitPolicy::Block).unwrap();
+        assert_eq!(budget.make_dynamic_payment(None, "food", 1), Err(String::from("over_limit")));
+        budget.set_policy("food", LimitPolicy::Allow).unwrap();
+        budget.make_dynamic_payment(None, "food", 1).unwrap();
+        assert_eq!(codes(&budget), ["over_limit"]);
+        assert_eq!(budget.set_policy("gym", LimitPolicy::Block), Err(String::from("expense_not_found")));
+    }
+
+    #[test]
+    fn keeps_accounts_above_the_floor() {
+        let mut budget = groceries();
+        budget.add_account("visa", AccountKind::CreditCard, 0, None).unwrap();
+        budget.set_balance_floor(5000, Some(LimitPolicy::Block)).unwrap();
+        assert_eq!(budget.transfer(None, "savings", 5001), Err(String::from("below_balance_floor")));
+        assert_eq!(budget.make_dynamic_payment(None, "food", 5001), Err(String::from("below_balance_floor")));
+        budget.transfer(None, "savings", 5000).unwrap();
+        //credit cards have no floor
+        budget.make_dynamic_payment(Some("visa"), "food", 2000).unwrap();
+
+        budget.set_balance_floor(4500, Some(LimitPolicy::Warn)).unwrap();
+        budget.make_dynamic_payment(None, "food", 1000).unwrap();
+        assert_eq!(codes(&budget), ["below_balance_floor"]);
+        assert_eq!(budget.primary_balance(), 4000);
+    }
+
+    #[test]
+    fn keeps_only_the_newest_notifications() {
+        let mut budget = groceries();
+        budget.set_policy("food", LimitPolicy::Warn).unwrap();
+        budget.make_dynamic_payment(None, "food", 3000).unwrap();
+        for _ in 0..MAX_NOTIFICATIONS + 5 {
+            budget.make_dynamic_payment(None, "food", 1).unwrap();
+        }
+        assert_eq!(budget.notifications().len(), MAX_NOTIFICATIONS);
+        assert!(budget.notifications()[0].message.contains("$30.06"));
+
+        assert_eq!(budget.clear_notifications(), Ok(format!("{} notifications cleared", MAX_NOTIFICATIONS)));
+        assert!(budget.notifications().is_empty());
+    }
+}
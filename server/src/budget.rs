@@ -1,47 +1,528 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+use chrono::NaiveDate;
 use serde::{self, Deserialize, Serialize};
 
+use crate::account::{Account, AccountKind};
+use crate::alerts::{self, BalanceFloor, Breach, LimitPolicy, Notification, MAX_NOTIFICATIONS};
+use crate::category::CategoryInfo;
+use crate::currency::{self, CurrencySettings, Locale};
+use crate::envelope::{EnvelopeBalance, Envelopes};
+use crate::goal::{Goal, GoalProgress};
+use crate::import::{CsvMapping, ImportReport, ImportedRow, PayeeRule, StatementRow};
+use crate::history::{EntryKind, LedgerEntry};
+use crate::money::{add_cents, sub_cents};
+use crate::period::{CategorySnapshot, PayPeriod, PeriodSnapshot};
+use crate::schedule::{Overdue, Schedule, ScheduleKind};
+
+//expenses used to be made automatic by starting their name with this, they're flagged in categories now
 const AUTOMATIC_PAYMENT_PREFIX: char = '*';
 
+//the accounts every new budget starts out with
+const DEFAULT_PRIMARY_ACCOUNT: &str = "checking";
+const DEFAULT_SAVINGS_ACCOUNT: &str = "savings";
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Budget {
     username: String,
-    current_balance: i64,
     expected_income: i64,
     expected_expenses: HashMap<String, i64>,
     current_expenses: HashMap<String, i64>,
-    savings: i64,
+    //every account the user owns, by lowercase name
+    accounts: BTreeMap<String, Account>,
+    //the account that commands use when they don't name one
+    primary_account: String,
+    //the pay period currently open, closed and archived by close_period()
+    #[serde(default)]
+    period: PayPeriod,
+    //recurring incomes and expenses, posted by run_schedules()
+    #[serde(default)]
+    schedules: Vec<Schedule>,
+    //payments that came due but couldn't be afforded
+    #[serde(default)]
+    overdue: Vec<Overdue>,
+    //savings goals, by lowercase name
+    #[serde(default)]
+    goals: BTreeMap<String, Goal>,
+    //how the user's csv bank statements are laid out
+    #[serde(default)]
+    csv_mapping: CsvMapping,
+    //rules for sorting imported statement rows into expense categories, checked in order
+    #[serde(default)]
+    payee_rules: Vec<PayeeRule>,
+    //ids of every statement row already imported, so importing the same statement twice does nothing
+    #[serde(default)]
+    imported: BTreeSet<String>,
+    //groups and flags of expense categories, by lowercase name, for categories that have any
+    #[serde(default)]
+    categories: BTreeMap<String, CategoryInfo>,
+    //the lowest balance payments and transfers should leave an account at
+    #[serde(default)]
+    balance_floor: BalanceFloor,
+    //the base currency, number formatting and exchange rates
+    #[serde(default)]
+    currency: CurrencySettings,
+    //envelope budgeting, when the user has opted into it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    envelopes: Option<Envelopes>,
+    //warnings the user hasn't cleared yet, oldest first
+    #[serde(default)]
+    notifications: Vec<Notification>,
+    //warnings raised since the last take_warnings(), to be sent back with the command that raised them
+    #[serde(skip)]
+    warnings: Vec<Notification>,
+    //changes made since the last take_journal(), waiting to be recorded into the user's History
+    #[serde(skip)]
+    journal: Vec<LedgerEntry>,
 }
 impl Budget {
     //new(): factory method, returning a new Budget
     //TODO: SAVING/LOADING, USERNAMES
     pub fn new(name: String) -> Budget {
+        let mut accounts = BTreeMap::new();
+        accounts.insert(DEFAULT_PRIMARY_ACCOUNT.to_string(), Account::new(AccountKind::Checking, 0));
+        accounts.insert(DEFAULT_SAVINGS_ACCOUNT.to_string(), Account::new(AccountKind::Savings, 0));
+
         Budget {
             username: name,
             expected_income: 0,
-            current_balance: 0,
             expected_expenses: HashMap::new(),
             current_expenses: HashMap::new(),
-            savings: 0,
+            accounts,
+            primary_account: DEFAULT_PRIMARY_ACCOUNT.to_string(),
+            period: PayPeriod::default(),
+            schedules: Vec::new(),
+            overdue: Vec::new(),
+            goals: BTreeMap::new(),
+            csv_mapping: CsvMapping::default(),
+            payee_rules: Vec::new(),
+            imported: BTreeSet::new(),
+            categories: BTreeMap::new(),
+            balance_floor: BalanceFloor::default(),
+            currency: CurrencySettings::default(),
+            envelopes: None,
+            notifications: Vec::new(),
+            warnings: Vec::new(),
+            journal: Vec::new(),
+        }
+    }
+
+    //to_json(): json stringifies the budget for sending back to the user, along with each goal's progress
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        self.to_json_with(None, &[])
+    }
+
+    //to_json_with(): json stringifies the budget along with the message and warnings of the command that just ran
+    pub fn to_json_with(&self, message: Option<&str>, warnings: &[Notification]) -> Result<String, serde_json::Error> {
+        let today = chrono::Local::now().date_naive();
+        serde_json::to_string(&BudgetView {
+            budget: self,
+            goal_progress: self.goal_progress(today),
+            envelope_balances: self.envelope_balances(),
+            message,
+            warnings,
+        })
+    }
+
+    //from_json(): loads a Budget from its stored json,
+    //moving budgets saved before accounts existed (a single current_balance and savings) into accounts
+    pub fn from_json(data: &str) -> Result<Budget, serde_json::Error> {
+        let mut value: serde_json::Value = serde_json::from_str(data)?;
+
+        if let Some(obj) = value.as_object_mut() {
+            if !obj.contains_key("accounts") {
+                let balance = obj.remove("current_balance").and_then(|v| v.as_i64()).unwrap_or(0);
+                let savings = obj.remove("savings").and_then(|v| v.as_i64()).unwrap_or(0);
+
+                let mut accounts = BTreeMap::new();
+                accounts.insert(DEFAULT_PRIMARY_ACCOUNT.to_string(), Account::new(AccountKind::Checking, balance));
+                accounts.insert(DEFAULT_SAVINGS_ACCOUNT.to_string(), Account::new(AccountKind::Savings, savings));
+
+                obj.insert("accounts".into(), serde_json::to_value(accounts)?);
+                obj.insert("primary_account".into(), DEFAULT_PRIMARY_ACCOUNT.into());
+            }
+        }
+
+        let mut budget: Budget = serde_json::from_value(value)?;
+        budget.move_automatic_prefixes();
+        Ok(budget)
+    }
+
+    //move_automatic_prefixes(): turns expenses named with the old automatic prefix (*rent) into automatic expenses
+    //the prefix stays on when taking it off would clash with another expense
+    pub fn move_automatic_prefixes(&mut self) {
+        let prefixed: Vec<String> = self
+            .expected_expenses
+            .keys()
+            .filter(|name| name.starts_with(AUTOMATIC_PAYMENT_PREFIX))
+            .cloned()
+            .collect();
+
+        for name in prefixed {
+            let stripped = name.trim_start_matches(AUTOMATIC_PAYMENT_PREFIX).to_string();
+            let name = match self.rename_expense(&name, &stripped, false) {
+                Ok(_) => stripped,
+                Err(_) => name,
+            };
+            self.category_mut(&name).automatic = true;
+        }
+    }
+
+    //log_change(): notes down a change to the budget, with the resulting primary account balance
+    fn log_change(&mut self, kind: EntryKind, label: &str, cents: i64) {
+        let balance = self.primary_balance();
+        self.journal.push(LedgerEntry::new(kind, None, label, cents, balance));
+    }
+
+    //log_account_change(): notes down a change to one account, with that account's resulting balance
+    fn log_account_change(&mut self, kind: EntryKind, account: &str, label: &str, cents: i64) {
+        let balance = self.accounts.get(account).map_or(0, |account| account.balance);
+        let mut entry = LedgerEntry::new(kind, Some(account), label, cents, balance);
+        if self.currency_of(account) != self.currency.base {
+            entry.currency = Some(self.currency_of(account).to_string());
+        }
+        self.journal.push(entry);
+    }
+
+    //currency_of(): the currency an account's balance is held in
+    pub fn currency_of(&self, account: &str) -> &str {
+        self.accounts
+            .get(account)
+            .and_then(|account| account.currency.as_deref())
+            .unwrap_or(&self.currency.base)
+    }
+
+    //to_base(): converts an amount held in an account (or the primary account) into the base currency
+    //every account's currency has a rate, so this can't fail
+    pub fn to_base(&self, account: Option<&str>, amount: i64) -> i64 {
+        let from = self.currency_of(account.unwrap_or(&self.primary_account));
+        self.currency.convert(amount, from, &self.currency.base).unwrap_or(amount)
+    }
+
+    //to_account(): converts an amount in the base currency into an account's currency
+    fn to_account(&self, account: &str, amount: i64) -> i64 {
+        let to = self.currency_of(account);
+        self.currency.convert(amount, &self.currency.base, to).unwrap_or(amount)
+    }
+
+    //base_amount(): converts an amount in the given currency (the base currency if none) into the base currency,
+    //used for ledger entries, whose currency may have lost its rate since they were recorded
+    pub fn base_amount(&self, amount: i64, currency: Option<&str>) -> i64 {
+        let from = currency.unwrap_or(&self.currency.base);
+        self.currency.convert(amount, from, &self.currency.base).unwrap_or(amount)
+    }
+
+    //money(): formats an amount in the base currency
+    pub fn money(&self, amount: i64) -> String {
+        currency::format_money(amount, &self.currency.base, self.currency.locale)
+    }
+
+    //money_in(): formats an amount held in an account, in the account's currency
+    pub fn money_in(&self, account: &str, amount: i64) -> String {
+        currency::format_money(amount, self.currency_of(account), self.currency.locale)
+    }
+
+    //base_currency(): the currency every total is given in
+    pub fn base_currency(&self) -> &str {
+        &self.currency.base
+    }
+
+    //set_currency(): changes the base currency and how numbers are written
+    //amounts aren't converted, so the base can only change while every account is held in it
+    pub fn set_currency(&mut self, base: Option<&str>, locale: Option<Locale>) -> Result<String, String> {
+        if let Some(base) = base {
+            let base = currency::parse_code(base)?;
+            if self.accounts.keys().any(|account| self.currency_of(account) != self.currency.base) {
+                return Err(String::from("foreign_accounts_open"));
+            }
+            self.currency.rebase(&base)?;
+        }
+        if let Some(locale) = locale {
+            self.currency.locale = locale;
+        }
+        Ok(format!("Amounts are now in {}, like {}", self.currency.base, self.money(123456)))
+    }
+
+    //set_rate(): records how much of the base currency one of another currency is worth
+    pub fn set_rate(&mut self, code: &str, rate: f64) -> Result<String, String> {
+        self.currency.set_rate(code, rate)?;
+        let code = code.trim().to_ascii_uppercase();
+        Ok(format!("1 {} is now worth {} {}", code, rate, self.currency.base))
+    }
+
+    //import_rates(): records every rate in an exchange rate file, keeping rates it doesn't mention
+    pub fn import_rates(&mut self, data: &str) -> Result<String, String> {
+        let rates = currency::parse_rates(data)?;
+        let mut updated = self.currency.clone();
+        for (code, rate) in &rates {
+            updated.set_rate(code, *rate)?;
+        }
+        self.currency = updated;
+        Ok(format!("{} exchange rates imported", rates.len()))
+    }
+
+    //convert_into(): converts an amount in the given currency into an account's currency, for payments made in another currency
+    pub fn convert_into(&self, account: Option<&str>, amount: i64, code: &str) -> Result<i64, String> {
+        let account = self.resolve_account(account)?;
+        let code = currency::parse_code(code)?;
+        self.currency.convert(amount, &code, self.currency_of(&account))
+    }
+
+    //take_journal(): hands over every change noted since the last call
+    pub fn take_journal(&mut self) -> Vec<LedgerEntry> {
+        std::mem::take(&mut self.journal)
+    }
+
+    //warn(): raises a warning, keeping it as a notification until the user clears it
+    fn warn(&mut self, notification: Notification) {
+        self.notifications.push(notification.clone());
+        if self.notifications.len() > MAX_NOTIFICATIONS {
+            let extra = self.notifications.len() - MAX_NOTIFICATIONS;
+            self.notifications.drain(..extra);
+        }
+        self.warnings.push(notification);
+    }
+
+    //take_warnings(): hands over every warning raised since the last call
+    pub fn take_warnings(&mut self) -> Vec<Notification> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    //notifications(): every warning the user hasn't cleared, oldest first
+    pub fn notifications(&self) -> &[Notification] {
+        &self.notifications
+    }
+
+    //clear_notifications(): forgets every notification
+    pub fn clear_notifications(&mut self) -> Result<String, String> {
+        let count = self.notifications.len();
+        self.notifications.clear();
+        Ok(format!("{} notifications cleared", count))
+    }
+
+    //set_policy(): changes what happens to payments that would take an expense over its expected amount
+    pub fn set_policy(&mut self, name: &str, policy: LimitPolicy) -> Result<String, String> {
+        let name = name.to_ascii_lowercase();
+        if !self.expected_expenses.contains_key(&name) {
+            return Err(String::from("expense_not_found"));
+        }
+        self.category_mut(&name).policy = policy;
+        self.tidy_categories();
+        let verb = match policy {
+            LimitPolicy::Allow => "allowed",
+            LimitPolicy::Warn => "warned about",
+            LimitPolicy::Block => "blocked",
+        };
+        Ok(format!("Payments over {}'s limit will be {}", to_title_case(name), verb))
+    }
+
+    //set_balance_floor(): changes the lowest balance payments and transfers should leave an account at
+    pub fn set_balance_floor(&mut self, cents: i64, policy: Option<LimitPolicy>) -> Result<String, String> {
+        self.balance_floor.amount = cents;
+        if let Some(policy) = policy {
+            self.balance_floor.policy = policy;
+        }
+        Ok(format!("Balance floor set to {}", self.money(cents)))
+    }
+
+    //check_limit(): whether paying the given amount into an expense would take it over its expected amount,
+    //or with envelopes on, over what's left in its envelope (and the unassigned pool, when the payment can draw on it)
+    fn check_limit(&self, name: &str, cents: i64, pool: bool) -> Breach {
+        let expected = self.expected_expenses.get(name).copied().unwrap_or(0);
+        let current = self.current_expenses.get(name).copied().unwrap_or(0);
+        let policy = self.categories.get(name).map(|info| info.policy).unwrap_or_default();
+        if let Some(envelopes) = self.envelopes.as_ref() {
+            let pooled = if pool { envelopes.unassigned } else { 0 };
+            let available = envelopes.available(name, current).saturating_add(pooled);
+            return alerts::check(
+                policy,
+                cents > 0 && cents > available,
+                "envelope_empty",
+                name,
+                format!(
+                    "{}'s envelope only has {} left",
+                    to_title_case(name.to_string()),
+                    self.money(available)
+                ),
+            );
+        }
+        alerts::check(
+            policy,
+            cents > 0 && current + cents > expected,
+            "over_limit",
+            name,
+            format!(
+                "{} is over budget: {} of {}",
+                to_title_case(name.to_string()),
+                self.money(current + cents),
+                self.money(expected)
+            ),
+        )
+    }
+
+    //check_floor(): whether taking the given amount out of an account would leave it below the balance floor
+    //credit cards are left out, their balances go negative as they're used. the floor is in the base currency
+    fn check_floor(&self, account: &str, cents: i64) -> Breach {
+        let Some(held) = self.accounts.get(account) else {
+            return Breach::None;
+        };
+        let floor = self.balance_floor;
+        let after = self.to_base(Some(account), held.balance - cents);
+        alerts::check(
+            floor.policy,
+            held.kind != AccountKind::CreditCard && cents > 0 && after < floor.amount,
+            "below_balance_floor",
+            account,
+            format!(
+                "{} would drop to {}, below its floor of {}",
+                to_title_case(account.to_string()),
+                self.money_in(account, held.balance - cents),
+                self.money(floor.amount)
+            ),
+        )
+    }
+
+    //enforce(): turns down a change if any of the given checks blocks it, otherwise hands back the warnings to raise once it's made
+    fn enforce(checks: Vec<Breach>) -> Result<Vec<Notification>, String> {
+        let mut warnings = Vec::new();
+        for check in checks {
+            match check {
+                Breach::None => {}
+                Breach::Warn(notification) => warnings.push(notification),
+                Breach::Block(code) => return Err(code),
+            }
+        }
+        Ok(warnings)
+    }
+
+    //resolve_account(): turns an optional account name into the name of an existing account,
+    //defaulting to the primary account
+    pub fn resolve_account(&self, account: Option<&str>) -> Result<String, String> {
+        match account {
+            Some(name) => {
+                let name = name.to_ascii_lowercase();
+                if self.accounts.contains_key(&name) {
+                    Ok(name)
+                } else {
+                    Err(String::from("account_not_found"))
+                }
+            }
+            None => Ok(self.primary_account.clone()),
+        }
+    }
+
+    //balance_of(): the balance of the given account, or the primary account
+    pub fn balance_of(&self, account: Option<&str>) -> i64 {
+        match account {
+            Some(account) => self.accounts.get(account).map_or(0, |account| account.balance),
+            None => self.primary_balance(),
+        }
+    }
+
+    //primary_balance(): the balance of the primary account
+    pub fn primary_balance(&self) -> i64 {
+        self.accounts.get(&self.primary_account).map_or(0, |account| account.balance)
+    }
+
+    //total_balance(): every account's balance added together in the base currency (credit card debt counts against it)
+    pub fn total_balance(&self) -> i64 {
+        self.accounts
+            .iter()
+            .map(|(name, account)| self.to_base(Some(name), account.balance))
+            .sum()
+    }
+
+    //add_account(): opens a new account with the given opening balance, held in the given currency (or the base currency)
+    //accounts in other currencies need an exchange rate first
+    pub fn add_account(&mut self, name: &str, kind: AccountKind, cents: i64, code: Option<&str>) -> Result<String, String> {
+        let name = name.to_ascii_lowercase();
+        if name.is_empty() {
+            return Err(String::from("invalid_account_name"));
+        }
+        if self.accounts.contains_key(&name) {
+            return Err(String::from("account_already_exists"));
         }
+        let code = code.map(currency::parse_code).transpose()?;
+        let code = code.filter(|code| *code != self.currency.base);
+        if code.as_ref().is_some_and(|code| self.currency.rate(code).is_none()) {
+            return Err(String::from("missing_exchange_rate"));
+        }
+
+        let mut account = Account::new(kind, cents);
+        account.currency = code;
+        self.accounts.insert(name.clone(), account);
+        self.log_account_change(EntryKind::NewAccount, &name, &name, cents);
+        Ok(format!("{} opened!", to_title_case(name)))
+    }
+
+    //set_primary_account(): changes which account commands use by default
+    pub fn set_primary_account(&mut self, name: &str) -> Result<String, String> {
+        let name = self.resolve_account(Some(name))?;
+        self.primary_account = name.clone();
+        Ok(format!("{} is now your primary account", to_title_case(name)))
     }
 
     //set_income(): sets expected_income to the new value
-    pub fn set_income(&mut self, cents: i64) {
+    pub fn set_income(&mut self, cents: i64) -> Result<(), String> {
+        if cents < 0 {
+            return Err(String::from("negative_income"));
+        }
         self.expected_income = cents;
+        self.log_change(EntryKind::SetIncome, "income", cents);
+        Ok(())
     }
 
     //add_income(): adds new value to expected_income
-    pub fn add_income(&mut self, cents: i64) {
-        self.set_income(self.expected_income + cents);
+    pub fn add_income(&mut self, cents: i64) -> Result<(), String> {
+        self.expected_income = add_cents(self.expected_income, cents)?;
+        self.log_change(EntryKind::RaiseIncome, "income", cents);
+        Ok(())
     }
 
-    //get_paid(): adds expected_income to current_balance
-    pub fn get_paid(&mut self) -> Result<String, String> {
+    //close_period(): ends the current pay period, returning a snapshot of it to be archived,
+    //then resets current_expenses and opens the next period
+    pub fn close_period(&mut self) -> PeriodSnapshot {
+        let categories = self
+            .expected_expenses
+            .iter()
+            .filter(|(name, _)| !self.is_archived(name))
+            .map(|(name, expected)| {
+                let actual = self.current_expenses.get(name).copied().unwrap_or(0);
+                (name.clone(), CategorySnapshot { expected: *expected, actual })
+            })
+            .collect();
+
+        let snapshot = PeriodSnapshot {
+            number: self.period.number,
+            started: self.period.started,
+            ended: chrono::Utc::now().timestamp(),
+            income: self.period.income,
+            categories,
+            saved: self.period.saved,
+            ending_balance: self.total_balance(),
+        };
+
         self.refresh();
-        self.current_balance += self.expected_income;
-        match self.make_automatic_payments(self.current_balance) {
+        self.period = PayPeriod::open(self.period.number + 1);
+
+        snapshot
+    }
+
+    //get_paid(): adds expected_income to the given account (or the primary account)
+    pub fn get_paid(&mut self, account: Option<&str>) -> Result<String, String> {
+        let account = self.resolve_account(account)?;
+        let paycheck = self.to_account(&account, self.expected_income);
+
+        let balance = add_cents(self.accounts[&account].balance, paycheck)?;
+        let income = add_cents(self.period.income, self.expected_income)?;
+        self.receive_income(self.expected_income)?;
+        self.accounts.get_mut(&account).unwrap().balance = balance;
+        self.period.income = income;
+        self.log_account_change(EntryKind::Paycheck, &account, "income", paycheck);
+        self.retry_overdue();
+
+        let balance = self.accounts[&account].balance;
+        match self.make_automatic_payments(&account, balance) {
             Ok(n) => {
                 if n == -1 {
                     Ok(String::new())
@@ -49,139 +530,1015 @@ impl Budget {
                     Ok(String::from("Payments made!"))
                 }
             }
-            Err(n) => Err(String::from("You couldn't afford your automatic payments!")),
+            //the paycheck still went in, the payments that couldn't be made were marked overdue with a warning each
+            Err(_) => Ok(String::from("Paid, but not every automatic payment could be made")),
         }
     }
 
-    //get_paid_value(): adds given value to current_balance
-    pub fn get_paid_value(&mut self, cents: i64) {
-        self.current_balance += cents;
+    //get_paid_value(): adds given value to the given account (or the primary account)
+    pub fn get_paid_value(&mut self, account: Option<&str>, cents: i64) -> Result<String, String> {
+        let account = self.resolve_account(account)?;
+        if cents <= 0 {
+            return Err(String::from("invalid_deposit_amount"));
+        }
+        self.deposit(&account, "income", cents)?;
+        self.retry_overdue();
+        Ok(format!("{} deposited!", self.money_in(&account, cents)))
+    }
+
+    //deposit(): adds given value to an account, as income from the given source
+    fn deposit(&mut self, account: &str, label: &str, cents: i64) -> Result<(), String> {
+        let balance = self.accounts.get(account).map(|account| add_cents(account.balance, cents)).transpose()?;
+        let income = self.to_base(Some(account), cents);
+        let period_income = add_cents(self.period.income, income)?;
+        self.receive_income(income)?;
+        if let (Some(account), Some(balance)) = (self.accounts.get_mut(account), balance) {
+            account.balance = balance;
+        }
+        self.period.income = period_income;
+        self.log_account_change(EntryKind::Deposit, account, label, cents);
+        Ok(())
+    }
+
+    //receive_income(): puts income into the unassigned pool, when envelopes are on
+    fn receive_income(&mut self, cents: i64) -> Result<(), String> {
+        if let Some(envelopes) = self.envelopes.as_mut() {
+            envelopes.unassigned = add_cents(envelopes.unassigned, cents)?;
+        }
+        Ok(())
+    }
+
+    //set_envelopes(): turns envelope budgeting on or off
+    //turning it on puts the primary account's balance in the unassigned pool, turning it off forgets every envelope
+    pub fn set_envelopes(&mut self, enabled: bool) -> Result<String, String> {
+        match (enabled, self.envelopes.is_some()) {
+            (true, false) => {
+                self.envelopes = Some(Envelopes {
+                    unassigned: self.to_base(None, self.primary_balance()).max(0),
+                    ..Envelopes::default()
+                });
+                Ok(String::from("Envelope budgeting on, every dollar needs a category!"))
+            }
+            (false, true) => {
+                self.envelopes = None;
+                Ok(String::from("Envelope budgeting off"))
+            }
+            _ => Err(String::from(if enabled { "envelopes_already_on" } else { "envelopes_not_on" })),
+        }
     }
 
-    //refresh(): resets current_expenses
+    //assign(): moves money from the unassigned pool into an expense's envelope, or back out of it for a negative amount
+    pub fn assign(&mut self, name: &str, cents: i64) -> Result<String, String> {
+        let name = name.to_ascii_lowercase();
+        let Some(spent) = self.current_expenses.get(&name).copied() else {
+            return Err(String::from("expense_not_found"));
+        };
+        let Some(envelopes) = self.envelopes.as_mut() else {
+            return Err(String::from("envelopes_not_on"));
+        };
+        envelopes.assign(&name, cents, spent)?;
+        let unassigned = envelopes.unassigned;
+
+        self.journal
+            .push(LedgerEntry::new(EntryKind::Assign, None, &name, cents, unassigned));
+        Ok(format!(
+            "{} assigned to {}, {} left to assign",
+            self.money(cents),
+            to_title_case(name),
+            self.money(unassigned)
+        ))
+    }
+
+    //move_envelope(): moves money from one expense's envelope into another's
+    pub fn move_envelope(&mut self, from: &str, to: &str, cents: i64) -> Result<String, String> {
+        let from = from.to_ascii_lowercase();
+        let to = to.to_ascii_lowercase();
+        if cents < 0 || from == to {
+            return Err(String::from("invalid_envelope_move"));
+        }
+        if !self.current_expenses.contains_key(&to) {
+            return Err(String::from("expense_not_found"));
+        }
+        self.assign(&from, -cents)?;
+        self.assign(&to, cents)?;
+        Ok(format!("{} moved from {} to {}", self.money(cents), to_title_case(from), to_title_case(to)))
+    }
+
+    //fund_envelope(): assigns whatever an expense's envelope is overdrawn by out of the unassigned pool,
+    //as far as the pool goes. automatic payments are funded this way once they've been made
+    fn fund_envelope(&mut self, name: &str) {
+        let spent = self.current_expenses.get(name).copied().unwrap_or(0);
+        let Some(envelopes) = self.envelopes.as_ref() else {
+            return;
+        };
+        let short = envelopes.available(name, spent).saturating_neg().min(envelopes.unassigned);
+        if short > 0 {
+            let _ = self.assign(name, short);
+        }
+    }
+
+    //envelope_balances(): every envelope with what's been assigned, spent and is still available, when envelopes are on
+    fn envelope_balances(&self) -> Option<Vec<EnvelopeBalance>> {
+        let envelopes = self.envelopes.as_ref()?;
+        Some(
+            self.expense_rows()
+                .into_iter()
+                .map(|(category, _, spent)| {
+                    let envelope = envelopes.envelopes.get(&category).copied().unwrap_or_default();
+                    EnvelopeBalance {
+                        carried: envelope.carried,
+                        assigned: envelope.assigned,
+                        spent,
+                        available: envelope.available(spent),
+                        category,
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    //refresh(): resets current_expenses, carrying what's left in each envelope over to the next period
     pub fn refresh(&mut self) {
-        for (key, value) in self.current_expenses.iter_mut() {
+        if let Some(envelopes) = self.envelopes.as_mut() {
+            envelopes.roll_over(&self.current_expenses);
+        }
+        for value in self.current_expenses.values_mut() {
             *value = 0;
         }
     }
 
-    //make_automatic_payments(): adds up total of automatic payments, returns money left over (if positive -> Ok, if negative -> Err)
-    pub fn make_automatic_payments(&mut self, cents: i64) -> Result<i64, i64> {
-        let mut autos: Vec<String> = Vec::new();
-        let mut payment = 0;
-        for key in self.expected_expenses.iter() {
-            if key.0.chars().next().unwrap() == AUTOMATIC_PAYMENT_PREFIX {
-                autos.push(key.0.clone());
-                payment += key.1;
+    //make_automatic_payments(): pays each automatic expense the given amount (in the account's currency) can still cover,
+    //in order of name, from the given account, marking the rest overdue.
+    //returns money left over (if every payment was made -> Ok, if any are overdue -> Err)
+    pub fn make_automatic_payments(&mut self, account: &str, cents: i64) -> Result<i64, i64> {
+        let mut autos: Vec<(String, i64)> = self
+            .expected_expenses
+            .iter()
+            .filter(|(name, _)| self.is_automatic(name) && !self.is_archived(name))
+            .map(|(name, amount)| (name.clone(), *amount))
+            .collect();
+
+        if autos.is_empty() {
+            return Ok(-1);
+        }
+        autos.sort();
+
+        let today = chrono::Local::now().date_naive();
+        let mut remaining = cents;
+        let mut all_paid = true;
+        for (name, amount) in autos {
+            //payments turned down by a block policy are overdue too
+            let amount = self.to_account(account, amount);
+            if amount <= remaining && self.pay(Some(account), &name, amount, true).is_ok() {
+                self.fund_envelope(&name);
+                remaining -= amount;
+            } else {
+                self.mark_overdue(account, &name, amount, today);
+                all_paid = false;
             }
         }
 
-        if payment == 0 {
-            return Ok(-1);
+        if all_paid {
+            Ok(remaining)
+        } else {
+            Err(remaining)
         }
+    }
 
-        payment = cents - payment;
+    //add_schedule(): adds a recurring income or expense, replacing any schedule with the same label
+    pub fn add_schedule(&mut self, schedule: Schedule) -> Result<String, String> {
+        if schedule.kind == ScheduleKind::Expense && !self.expected_expenses.contains_key(&schedule.label) {
+            return Err(String::from("expense_not_found"));
+        }
+        self.resolve_account(schedule.account.as_deref())?;
 
-        if payment < 0 {
-            Err(payment)
+        self.schedules.retain(|existing| existing.label != schedule.label);
+        let msg = format!("{} scheduled, next due {}", to_title_case(schedule.label.clone()), schedule.next_due);
+        self.schedules.push(schedule);
+        Ok(msg)
+    }
+
+    //remove_schedule(): stops the schedule with the given label from recurring
+    pub fn remove_schedule(&mut self, label: &str) -> Result<String, String> {
+        let label = label.to_ascii_lowercase();
+        let count = self.schedules.len();
+        self.schedules.retain(|schedule| schedule.label != label);
+
+        if self.schedules.len() == count {
+            Err(String::from("schedule_not_found"))
         } else {
-            for name in autos {
-                let _ = self.make_static_payment(&name);
+            Ok(format!("{} unscheduled", to_title_case(label)))
+        }
+    }
+
+    //run_schedules(): posts every scheduled income and expense that has come due by the given date,
+    //oldest first. expenses that can't be afforded are marked overdue instead of being paid
+    pub fn run_schedules(&mut self, today: NaiveDate) {
+        self.retry_overdue();
+
+        let mut due: Vec<(NaiveDate, ScheduleKind, String, i64, Option<String>)> = Vec::new();
+        for schedule in self.schedules.iter_mut() {
+            while schedule.is_due(today) {
+                due.push((
+                    schedule.next_due,
+                    schedule.kind,
+                    schedule.label.clone(),
+                    schedule.amount,
+                    schedule.account.clone(),
+                ));
+                schedule.advance();
+            }
+        }
+        //incomes sort before expenses due on the same day
+        due.sort();
+
+        for (date, kind, label, amount, account) in due {
+            //fall back to the primary account if the scheduled one has since gone away
+            let account = self
+                .resolve_account(account.as_deref())
+                .unwrap_or_else(|_| self.primary_account.clone());
+
+            match kind {
+                ScheduleKind::Income => {
+                    if let Err(code) = self.deposit(&account, &label, amount) {
+                        let message = format!("{} couldn't be deposited, the balance would be too large", to_title_case(label.clone()));
+                        self.warn(Notification::new(&code, &label, message));
+                    }
+                }
+                ScheduleKind::Expense => {
+                    if !self.accounts[&account].can_afford(amount)
+                        || self.pay(Some(&account), &label, amount, false).is_err()
+                    {
+                        self.mark_overdue(&account, &label, amount, date);
+                    }
+                }
+            }
+        }
+    }
+
+    //mark_overdue(): records a payment that came due but wasn't made
+    fn mark_overdue(&mut self, account: &str, label: &str, cents: i64, due: NaiveDate) {
+        self.overdue.push(Overdue {
+            label: label.to_string(),
+            amount: cents,
+            due,
+            account: Some(account.to_string()),
+        });
+        self.log_account_change(EntryKind::Overdue, account, label, cents);
+        self.warn(Notification::new(
+            "payment_overdue",
+            label,
+            format!(
+                "{} of {} came due on {} but couldn't be paid",
+                self.money_in(account, cents),
+                to_title_case(label.to_string()),
+                due
+            ),
+        ));
+    }
+
+    //retry_overdue(): pays off overdue payments, oldest first, while their accounts can cover them
+    //overdue payments for expenses (or accounts) that no longer exist are dropped
+    fn retry_overdue(&mut self) {
+        let overdue = std::mem::take(&mut self.overdue);
+        for item in overdue {
+            let Ok(account) = self.resolve_account(item.account.as_deref()) else {
+                continue;
+            };
+            if !self.accounts[&account].can_afford(item.amount) {
+                self.overdue.push(item);
+                continue;
+            }
+            match self.pay(Some(&account), &item.label, item.amount, false) {
+                Ok(_) => {}
+                Err(code) if code == "expense_not_found" => {}
+                Err(_) => self.overdue.push(item),
             }
-            Ok(payment)
         }
     }
 
     //add_expense(): creates a new expense in both HashMaps, with the new value as the expected value in expected_expenses
-    pub fn add_expense(&mut self, name: &str, cents: i64) {
+    //adding an expense that already exists only changes its expected value (and automatic, if given), keeping what's been paid into it
+    pub fn add_expense(&mut self, name: &str, cents: i64, automatic: Option<bool>) {
         self.expected_expenses
             .insert(name.to_string().to_ascii_lowercase(), cents);
         self.current_expenses
-            .insert(name.to_string().to_ascii_lowercase(), 0);
+            .entry(name.to_string().to_ascii_lowercase())
+            .or_insert(0);
+        if let Some(automatic) = automatic {
+            self.category_mut(&name.to_ascii_lowercase()).automatic = automatic;
+            self.tidy_categories();
+        }
+        self.log_change(EntryKind::NewExpense, &name.to_ascii_lowercase(), cents);
     }
 
-    //make_static_payment(): makes a payment into current_expenses, with the value from expected_expenses
-    pub fn make_static_payment(&mut self, name: &str) -> Result<String, String> {
-        let amount = if let Some(n) = self.expected_expenses.get(name) {
-            n.clone()
-        } else {
+    //edit_expense(): changes an expense's expected amount and whether it's automatic, keeping what's been paid into it
+    pub fn edit_expense(&mut self, name: &str, cents: Option<i64>, automatic: Option<bool>) -> Result<String, String> {
+        let name = name.to_ascii_lowercase();
+        let Some(expected) = self.expected_expenses.get_mut(&name) else {
+            return Err(String::from("expense_not_found"));
+        };
+        if let Some(cents) = cents {
+            *expected = cents;
+        }
+        let expected = *expected;
+        if let Some(automatic) = automatic {
+            self.category_mut(&name).automatic = automatic;
+            self.tidy_categories();
+        }
+
+        self.log_change(EntryKind::EditExpense, &name, expected);
+        Ok(format!("{} is now {}", to_title_case(name), self.money(expected)))
+    }
+
+    //archive_expense(): stops (or with archived false, restarts) budgeting for an expense without deleting it
+    //archived expenses can't be paid into, so one that's still scheduled can't be archived
+    pub fn archive_expense(&mut self, name: &str, archived: bool) -> Result<String, String> {
+        let name = name.to_ascii_lowercase();
+        let Some(expected) = self.expected_expenses.get(&name).copied() else {
             return Err(String::from("expense_not_found"));
         };
+        if archived
+            && self
+                .schedules
+                .iter()
+                .any(|schedule| schedule.kind == ScheduleKind::Expense && schedule.label == name)
+        {
+            return Err(String::from("expense_scheduled"));
+        }
+
+        self.category_mut(&name).archived = archived;
+        self.tidy_categories();
+
+        let balance = self.primary_balance();
+        let mut entry = LedgerEntry::new(EntryKind::EditExpense, None, &name, expected, balance);
+        entry.memo = Some(String::from(if archived { "archived" } else { "unarchived" }));
+        self.journal.push(entry);
+
+        let verb = if archived { "archived" } else { "unarchived" };
+        Ok(format!("{} {}", to_title_case(name), verb))
+    }
+
+    //delete_expense(): removes an expense, along with its schedules, overdue payments and payee rules
+    //categories filed under it are taken out of the group
+    pub fn delete_expense(&mut self, name: &str) -> Result<String, String> {
+        let name = name.to_ascii_lowercase();
+        if !self.expected_expenses.contains_key(&name) {
+            return Err(String::from("expense_not_found"));
+        }
+        let spent = self.current_expenses.get(&name).copied().unwrap_or(0);
+        if let Some(envelopes) = self.envelopes.as_mut() {
+            envelopes.remove(&name, spent)?;
+        }
+        let expected = self.expected_expenses.remove(&name).unwrap_or(0);
+        self.current_expenses.remove(&name);
+
+        self.categories.remove(&name);
+        for info in self.categories.values_mut() {
+            if info.parent.as_deref() == Some(name.as_str()) {
+                info.parent = None;
+            }
+        }
+        self.tidy_categories();
+
+        self.schedules
+            .retain(|schedule| !(schedule.kind == ScheduleKind::Expense && schedule.label == name));
+        self.overdue.retain(|overdue| overdue.label != name);
+        self.payee_rules.retain(|rule| rule.category != name);
+
+        self.log_change(EntryKind::DeleteExpense, &name, expected);
+        Ok(format!("{} deleted", to_title_case(name)))
+    }
+
+    //is_automatic(): whether an expense is paid automatically when the user gets paid
+    pub fn is_automatic(&self, name: &str) -> bool {
+        self.categories.get(name).is_some_and(|info| info.automatic)
+    }
 
-        self.make_dynamic_payment(name, amount)
+    //is_archived(): whether an expense has been archived
+    pub fn is_archived(&self, name: &str) -> bool {
+        self.categories.get(name).is_some_and(|info| info.archived)
     }
 
-    //make_dynamic_payment(): makes a payment into current_expenses, with the given value
-    pub fn make_dynamic_payment(&mut self, name: &str, cents: i64) -> Result<String, String> {
+    //category_mut(): the metadata of a category, created empty if it has none yet
+    fn category_mut(&mut self, name: &str) -> &mut CategoryInfo {
+        self.categories.entry(name.to_string()).or_default()
+    }
+
+    //tidy_categories(): drops metadata that no longer has anything set
+    fn tidy_categories(&mut self) {
+        self.categories.retain(|_, info| !info.is_empty());
+    }
+
+    //set_parent(): files an expense category under a group (rent under housing), or takes it out of its group
+    pub fn set_parent(&mut self, name: &str, parent: Option<&str>) -> Result<String, String> {
         let name = name.to_ascii_lowercase();
-        if let Some(n) = self.current_expenses.get_mut(&name) {
-            self.current_balance -= cents;
-            *n += cents;
+        if !self.expected_expenses.contains_key(&name) {
+            return Err(String::from("expense_not_found"));
+        }
+
+        let Some(parent) = parent.map(str::to_ascii_lowercase) else {
+            self.category_mut(&name).parent = None;
+            self.tidy_categories();
+            return Ok(format!("{} removed from its group", to_title_case(name)));
+        };
+        if parent.is_empty() || parent == name {
+            return Err(String::from("invalid_parent"));
+        }
+        //only two levels: the parent can't be in a group, and the category can't be a group
+        if self.categories.get(&parent).is_some_and(|info| info.parent.is_some()) || self.is_parent(&name) {
+            return Err(String::from("category_too_deep"));
+        }
+
+        self.category_mut(&name).parent = Some(parent.clone());
+        Ok(format!("{} filed under {}", to_title_case(name), to_title_case(parent)))
+    }
+
+    //is_parent(): whether any category is filed under the given name
+    fn is_parent(&self, name: &str) -> bool {
+        self.categories
+            .values()
+            .any(|info| info.parent.as_deref() == Some(name))
+    }
+
+    //rename_expense(): renames an expense category, or with merge, folds it into another existing one,
+    //carrying its expected and current amounts, schedules, overdue payments, payee rules and group along with it
+    //a group that isn't an expense itself can be renamed too, which refiles its categories
+    pub fn rename_expense(&mut self, from: &str, to: &str, merge: bool) -> Result<String, String> {
+        let from = from.to_ascii_lowercase();
+        let to = to.to_ascii_lowercase();
+        let is_expense = self.expected_expenses.contains_key(&from);
+
+        if !is_expense && !self.is_parent(&from) {
+            return Err(String::from("expense_not_found"));
+        }
+        if to.is_empty() || to == from {
+            return Err(String::from("invalid_rename"));
+        }
+        let exists = self.expected_expenses.contains_key(&to) || self.is_parent(&to);
+        match (merge, exists) {
+            (false, true) => return Err(String::from("expense_exists")),
+            (true, false) => return Err(String::from("expense_not_found")),
+            _ => {}
+        }
+        //refiling from's categories under to can't push them three levels deep
+        if self.is_parent(&from) && self.categories.get(&to).is_some_and(|info| info.parent.is_some()) {
+            return Err(String::from("category_too_deep"));
+        }
+
+        //merged amounts are added up first, so one too large to hold stops the rename before anything moves
+        let moved = self.current_expenses.get(&from).copied().unwrap_or(0);
+        let totals = match self.expected_expenses.get(&from) {
+            Some(expected) => Some((
+                add_cents(self.expected_expenses.get(&to).copied().unwrap_or(0), *expected)?,
+                add_cents(self.current_expenses.get(&to).copied().unwrap_or(0), moved)?,
+            )),
+            None => None,
+        };
+        if let Some(envelopes) = self.envelopes.as_mut() {
+            envelopes.rename(&from, &to)?;
+        }
+        self.current_expenses.remove(&from);
+        if let Some((expected, current)) = totals {
+            self.expected_expenses.remove(&from);
+            self.expected_expenses.insert(to.clone(), expected);
+            self.current_expenses.insert(to.clone(), current);
+        }
+
+        //a merged category keeps its own group and flags, a renamed one takes the old one's
+        let info = self.categories.remove(&from).unwrap_or_default();
+        if !merge && !info.is_empty() {
+            self.categories.insert(to.clone(), info);
+        }
+        for info in self.categories.values_mut() {
+            if info.parent.as_deref() == Some(from.as_str()) {
+                info.parent = Some(to.clone());
+            }
+        }
+        //folding a category into its own group leaves nothing to file it under
+        if let Some(info) = self.categories.get_mut(&to) {
+            if info.parent.as_deref() == Some(to.as_str()) {
+                info.parent = None;
+            }
+        }
+        self.tidy_categories();
+
+        for schedule in self.schedules.iter_mut() {
+            if schedule.kind == ScheduleKind::Expense && schedule.label == from {
+                schedule.label = to.clone();
+            }
+        }
+        for overdue in self.overdue.iter_mut() {
+            if overdue.label == from {
+                overdue.label = to.clone();
+            }
+        }
+        for rule in self.payee_rules.iter_mut() {
+            if rule.category == from {
+                rule.category = to.clone();
+            }
+        }
+
+        let balance = self.primary_balance();
+        let mut entry = LedgerEntry::new(EntryKind::Rename, None, &from, moved, balance);
+        entry.memo = Some(to.clone());
+        self.journal.push(entry);
+
+        let verb = if merge { "merged into" } else { "renamed to" };
+        Ok(format!("{} {} {}", to_title_case(from), verb, to_title_case(to)))
+    }
+
+    //tag_last_entry(): puts tags on the change noted down last, like the payment just made
+    pub fn tag_last_entry(&mut self, tags: Vec<String>) {
+        if let Some(entry) = self.journal.last_mut() {
+            entry.tags = tags;
+        }
+    }
+
+    //make_static_payment(): makes a payment into current_expenses, with the value from expected_expenses
+    pub fn make_static_payment(&mut self, account: Option<&str>, name: &str) -> Result<String, String> {
+        let amount = if let Some(n) = self.expected_expenses.get(&name.to_ascii_lowercase()) {
+            *n
         } else {
             return Err(String::from("expense_not_found"));
         };
 
+        let account = self.resolve_account(account)?;
+        let amount = self.to_account(&account, amount);
+        self.make_dynamic_payment(Some(&account), name, amount)
+    }
+
+    //make_dynamic_payment(): makes a payment into current_expenses from the given account
+    //(or the primary account), with the given value in the account's currency, which has to be more than nothing
+    pub fn make_dynamic_payment(&mut self, account: Option<&str>, name: &str, cents: i64) -> Result<String, String> {
+        if cents <= 0 {
+            return Err(String::from("invalid_payment_amount"));
+        }
+        self.pay(account, name, cents, false)
+    }
+
+    //pay(): makes a payment the way make_dynamic_payment() does, for whatever amount came due. with envelopes on,
+    //it can go past what's left in the expense's envelope as far as the unassigned pool could cover, when pool is set
+    fn pay(&mut self, account: Option<&str>, name: &str, cents: i64, pool: bool) -> Result<String, String> {
+        let account = self.resolve_account(account)?;
+        let name = name.to_ascii_lowercase();
+        if self.is_archived(&name) {
+            return Err(String::from("expense_archived"));
+        }
+        if !self.current_expenses.contains_key(&name) {
+            return Err(String::from("expense_not_found"));
+        }
+        let spent = self.to_base(Some(&account), cents);
+        let warnings = Budget::enforce(vec![self.check_limit(&name, spent, pool), self.check_floor(&account, cents)])?;
+
+        let current = add_cents(self.current_expenses[&name], spent)?;
+        let balance = sub_cents(self.accounts[&account].balance, cents)?;
+        self.current_expenses.insert(name.clone(), current);
+        self.accounts.get_mut(&account).unwrap().balance = balance;
+        self.log_account_change(EntryKind::Payment, &account, &name, cents);
+        for warning in warnings {
+            self.warn(warning);
+        }
+
         Ok(format!(
             "Payment made: {} to {}",
-            format_dollars(&cents),
+            self.money_in(&account, cents),
             to_title_case(name)
         ))
     }
 
-    //save(): adds the given amount into savings
-    pub fn save(&mut self, cents: i64) -> Result<String, String> {
-        if self.current_balance < cents {
-            Err(String::from("Not enough in balance to save that much!"))
+    //transfer(): moves the given amount (in the from account's currency) from one account (or the primary account) into another,
+    //converting it into the other account's currency
+    pub fn transfer(&mut self, from: Option<&str>, to: &str, cents: i64) -> Result<String, String> {
+        let from = self.resolve_account(from)?;
+        let to = self.resolve_account(Some(to))?;
+
+        if from == to {
+            return Err(String::from("same_account_transfer"));
+        }
+        if cents < 0 {
+            return Err(String::from("negative_transfer_amount"));
+        }
+        if !self.accounts[&from].can_afford(cents) {
+            return Err(String::from("insufficient_balance"));
+        }
+        let warnings = Budget::enforce(vec![self.check_floor(&from, cents)])?;
+
+        let received = self.to_account(&to, self.to_base(Some(&from), cents));
+        let from_balance = sub_cents(self.accounts[&from].balance, cents)?;
+        let to_balance = add_cents(self.accounts[&to].balance, received)?;
+        let to_savings = self.accounts[&to].kind == AccountKind::Savings;
+        let saved = if to_savings {
+            add_cents(self.period.saved, self.to_base(Some(&to), received))?
+        } else {
+            self.period.saved
+        };
+        self.accounts.get_mut(&from).unwrap().balance = from_balance;
+        self.accounts.get_mut(&to).unwrap().balance = to_balance;
+        self.log_account_change(EntryKind::TransferOut, &from, &to, cents);
+        for warning in warnings {
+            self.warn(warning);
+        }
+
+        //money moving into a savings account counts as saved
+        if to_savings {
+            self.period.saved = saved;
+            self.log_account_change(EntryKind::Save, &to, &from, received);
         } else {
-            self.current_balance -= cents;
-            self.savings += cents;
-            Ok(format!("{} saved!", format_dollars(&cents)))
+            self.log_account_change(EntryKind::TransferIn, &to, &from, received);
         }
+
+        Ok(format!(
+            "{} moved from {} to {}",
+            self.money_in(&from, cents),
+            to_title_case(from),
+            to_title_case(to)
+        ))
     }
 
-    //save_all(): moves current_balance to savings
-    pub fn save_all(&mut self) -> Result<String, String> {
-        self.save(self.current_balance)
+    //transfer_all(): moves the whole balance of one account (or the primary account) into another
+    pub fn transfer_all(&mut self, from: Option<&str>, to: &str) -> Result<String, String> {
+        let from = self.resolve_account(from)?;
+        let balance = self.accounts[&from].balance.max(0);
+        self.transfer(Some(&from), to, balance)
     }
-}
 
-//format_dollars(): takes an amount of cents and formats it to ${X}+.XX
-pub fn format_dollars(cents: &i64) -> String {
-    let cents = { cents.to_string() };
-    let dollars = match cents.len() {
-        3.. => cents.split_at(cents.len() - 2),
-        2 => ("0", cents.as_str()),
-        1 => ("0", &format!("0{}", cents)[..]),
-        _ => ("0", "00"),
-    };
-    let mut output = String::from("$");
-    output.push_str(dollars.0);
-    output.push('.');
-    output.push_str(dollars.1);
-    output.to_string()
-}
+    //savings_account(): the first savings account, which save() moves money into
+    fn savings_account(&self) -> Result<String, String> {
+        self.accounts
+            .iter()
+            .find(|(_, account)| account.kind == AccountKind::Savings)
+            .map(|(name, _)| name.clone())
+            .ok_or(String::from("no_savings_account"))
+    }
+
+    //save(): transfers the given amount from the given account (or the primary account) into savings,
+    //counting it towards the given goal if there is one
+    pub fn save(&mut self, from: Option<&str>, cents: i64, goal: Option<&str>) -> Result<String, String> {
+        let from = self.resolve_account(from)?;
+        let savings = self.savings_account()?;
+        let goal = goal.map(|name| self.open_goal(name)).transpose()?;
+
+        self.transfer(Some(&from), &savings, cents)?;
+        let saved = self.money_in(&from, cents);
+        match goal {
+            Some(goal) => {
+                self.allocate_to_goal(&goal, self.to_base(Some(&from), cents))?;
+                Ok(format!("{} saved towards {}!", saved, to_title_case(goal)))
+            }
+            None => Ok(format!("{} saved!", saved)),
+        }
+    }
+
+    //save_all(): transfers the whole balance of the given account (or the primary account) into savings,
+    //counting it towards the given goal if there is one
+    pub fn save_all(&mut self, from: Option<&str>, goal: Option<&str>) -> Result<String, String> {
+        let from = self.resolve_account(from)?;
+        let balance = self.accounts[&from].balance.max(0);
+        self.save(Some(&from), balance, goal)
+    }
+
+    //open_goal(): turns a goal name into the name of an existing goal that can still be saved into
+    fn open_goal(&self, name: &str) -> Result<String, String> {
+        let name = name.to_ascii_lowercase();
+        match self.goals.get(&name) {
+            Some(goal) if goal.closed => Err(String::from("goal_closed")),
+            Some(_) => Ok(name),
+            None => Err(String::from("goal_not_found")),
+        }
+    }
+
+    //allocate_to_goal(): counts money that's already been saved towards a goal
+    fn allocate_to_goal(&mut self, name: &str, cents: i64) -> Result<(), String> {
+        let Some(goal) = self.goals.get_mut(name) else {
+            return Ok(());
+        };
+        goal.saved = add_cents(goal.saved, cents)?;
+        let saved = goal.saved;
+        self.journal
+            .push(LedgerEntry::new(EntryKind::GoalSave, None, name, cents, saved));
+        Ok(())
+    }
+
+    //add_goal(): starts a new savings goal
+    pub fn add_goal(&mut self, name: &str, target: i64, deadline: Option<NaiveDate>) -> Result<String, String> {
+        let name = name.to_ascii_lowercase();
+        if name.is_empty() {
+            return Err(String::from("invalid_goal_name"));
+        }
+        if target <= 0 {
+            return Err(String::from("invalid_goal_target_value"));
+        }
+        if self.goals.contains_key(&name) {
+            return Err(String::from("goal_already_exists"));
+        }
+
+        self.goals.insert(name.clone(), Goal::new(target, deadline));
+        self.journal
+            .push(LedgerEntry::new(EntryKind::NewGoal, None, &name, target, 0));
+        Ok(format!("Saving {} for {}!", self.money(target), to_title_case(name)))
+    }
+
+    //edit_goal(): changes a goal's target and/or deadline, Some(None) removes the deadline
+    pub fn edit_goal(
+        &mut self,
+        name: &str,
+        target: Option<i64>,
+        deadline: Option<Option<NaiveDate>>,
+    ) -> Result<String, String> {
+        let name = self.open_goal(name)?;
+        if target.is_some_and(|target| target <= 0) {
+            return Err(String::from("invalid_goal_target_value"));
+        }
+
+        let goal = self.goals.get_mut(&name).unwrap();
+        if let Some(target) = target {
+            goal.target = target;
+        }
+        if let Some(deadline) = deadline {
+            goal.deadline = deadline;
+        }
+        Ok(format!("{} updated", to_title_case(name)))
+    }
+
+    //close_goal(): closes a goal so nothing more is saved into it, keeping what it saved on record
+    pub fn close_goal(&mut self, name: &str) -> Result<String, String> {
+        let name = self.open_goal(name)?;
+        let goal = self.goals.get_mut(&name).unwrap();
+        goal.closed = true;
+        let saved = goal.saved;
+        self.journal
+            .push(LedgerEntry::new(EntryKind::CloseGoal, None, &name, saved, saved));
+        Ok(format!("{} closed with {} saved", to_title_case(name), self.money(saved)))
+    }
+
+    //leftover_income(): expected income left over each pay period once every expected expense is paid
+    pub fn leftover_income(&self) -> i64 {
+        self.expected_income
+            - self
+                .expected_expenses
+                .iter()
+                .filter(|(name, _)| !self.is_archived(name))
+                .map(|(_, expected)| expected)
+                .sum::<i64>()
+    }
 
-//dollars_to_cents(): takes a decimal amount of dollars and returns it in integer cents
-pub fn dollars_to_cents(dollars: f64) -> i64 {
-    (dollars * 100.0) as i64
+    //goal_progress(): projects every goal as of the given day
+    pub fn goal_progress(&self, today: NaiveDate) -> Vec<GoalProgress> {
+        let leftover = self.leftover_income();
+        self.goals
+            .iter()
+            .map(|(name, goal)| goal.progress(name, today, leftover))
+            .collect()
+    }
 }
 
-//parse_dollar_string(): takes a string literal and returns an integer cent amount if valid, or error message if not
-pub fn parse_dollar_string(s: &str) -> Result<i64, String> {
-    if s.is_empty() {
-        return Err(String::from("empty_dollar_string"));
+impl Budget {
+    //username(): the name of the user the budget belongs to
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    //set_username(): hands the budget over to another user, used when restoring an export
+    pub fn set_username(&mut self, name: &str) {
+        self.username = name.to_string();
+    }
+
+    //is_fresh(): whether nothing has been put into the budget since Budget::new(),
+    //so no income, expenses, schedules, goals or money in any account, and no accounts beyond the default ones
+    pub fn is_fresh(&self) -> bool {
+        self.expected_income == 0
+            && self.expected_expenses.is_empty()
+            && self.current_expenses.is_empty()
+            && self.schedules.is_empty()
+            && self.overdue.is_empty()
+            && self.goals.is_empty()
+            && self.accounts.iter().all(|(name, account)| {
+                (name == DEFAULT_PRIMARY_ACCOUNT || name == DEFAULT_SAVINGS_ACCOUNT) && account.balance == 0
+            })
+    }
+
+    //accounts(): every account, by name
+    pub fn accounts(&self) -> &BTreeMap<String, Account> {
+        &self.accounts
+    }
+
+    //primary_account(): the name of the primary account
+    pub fn primary_account(&self) -> &str {
+        &self.primary_account
+    }
+
+    //expected_income(): the paycheck the user expects each pay period
+    pub fn expected_income(&self) -> i64 {
+        self.expected_income
+    }
+
+    //period(): the pay period that's currently open
+    pub fn period(&self) -> &PayPeriod {
+        &self.period
+    }
+
+    //schedules(): every recurring income and expense
+    pub fn schedules(&self) -> &[Schedule] {
+        &self.schedules
+    }
+
+    //savings_balance(): every savings account's balance added together, in the base currency
+    pub fn savings_balance(&self) -> i64 {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.kind == AccountKind::Savings)
+            .map(|(name, account)| self.to_base(Some(name), account.balance))
+            .sum()
+    }
+
+    //overdue_total(): everything still owed on overdue payments, in the base currency
+    pub fn overdue_total(&self) -> i64 {
+        self.overdue
+            .iter()
+            .map(|overdue| self.to_base(overdue.account.as_deref(), overdue.amount))
+            .sum()
+    }
+
+    //categories(): the group of every expense category that's in one
+    pub fn categories(&self) -> &BTreeMap<String, CategoryInfo> {
+        &self.categories
+    }
+
+    //expense_rows(): every expense category that isn't archived, with its expected and current amounts, sorted by name
+    pub fn expense_rows(&self) -> Vec<(String, i64, i64)> {
+        let mut rows: Vec<(String, i64, i64)> = self
+            .expected_expenses
+            .iter()
+            .filter(|(name, _)| !self.is_archived(name))
+            .map(|(name, expected)| {
+                let current = self.current_expenses.get(name).copied().unwrap_or(0);
+                (name.clone(), *expected, current)
+            })
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    //validate(): checks that everything the budget refers to by name actually exists
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.accounts.contains_key(&self.primary_account) {
+            return Err(String::from("primary_account_not_found"));
+        }
+
+        let schedule_accounts = self.schedules.iter().filter_map(|schedule| schedule.account.as_ref());
+        let overdue_accounts = self.overdue.iter().filter_map(|overdue| overdue.account.as_ref());
+        if schedule_accounts
+            .chain(overdue_accounts)
+            .any(|account| !self.accounts.contains_key(account))
+        {
+            return Err(String::from("account_not_found"));
+        }
+
+        if self
+            .payee_rules
+            .iter()
+            .any(|rule| !self.expected_expenses.contains_key(&rule.category))
+        {
+            return Err(String::from("expense_not_found"));
+        }
+
+        let envelope_names = self.envelopes.iter().flat_map(|envelopes| envelopes.envelopes.keys());
+        if self
+            .categories
+            .keys()
+            .chain(envelope_names)
+            .any(|name| !self.expected_expenses.contains_key(name))
+        {
+            return Err(String::from("expense_not_found"));
+        }
+        if self.categories.values().any(|info| {
+            info.parent
+                .as_ref()
+                .is_some_and(|parent| self.categories.get(parent).is_some_and(|info| info.parent.is_some()))
+        }) {
+            return Err(String::from("category_too_deep"));
+        }
+
+        //accounts are converted into the base currency for every total, so each one's currency needs a rate
+        if self
+            .accounts
+            .values()
+            .filter_map(|account| account.currency.as_deref())
+            .any(|code| self.currency.rate(code).is_none())
+        {
+            return Err(String::from("missing_exchange_rate"));
+        }
+
+        Ok(())
     }
-    let mut s = s;
-    if s.chars().into_iter().next().unwrap() == '$' {
-        s = &s[1..];
+
+    //csv_mapping(): how the user's csv statements are laid out
+    pub fn csv_mapping(&self) -> &CsvMapping {
+        &self.csv_mapping
     }
-    match s.parse::<i64>() {
-        Ok(n) => Ok(n * 100),
-        Err(er) => match s.parse::<f64>() {
-            Ok(m) => Ok(dollars_to_cents(m)),
-            Err(err) => Err(String::from("not_a_number")),
-        },
+
+    //set_csv_mapping(): changes how the user's csv statements are read
+    pub fn set_csv_mapping(&mut self, mapping: CsvMapping) {
+        self.csv_mapping = mapping;
     }
+
+    //add_payee_rule(): sorts payees containing the pattern into the given expense category
+    pub fn add_payee_rule(&mut self, pattern: &str, category: &str) -> Result<String, String> {
+        let pattern = pattern.to_ascii_lowercase();
+        let category = category.to_ascii_lowercase();
+        if pattern.is_empty() {
+            return Err(String::from("invalid_rule_pattern"));
+        }
+        if !self.expected_expenses.contains_key(&category) {
+            return Err(String::from("expense_not_found"));
+        }
+
+        self.payee_rules.retain(|rule| rule.pattern != pattern);
+        self.payee_rules.push(PayeeRule { pattern: pattern.clone(), category: category.clone() });
+        Ok(format!("\"{}\" will be paid to {}", pattern, to_title_case(category)))
+    }
+
+    //remove_payee_rule(): removes the rule with the given pattern
+    pub fn remove_payee_rule(&mut self, pattern: &str) -> Result<String, String> {
+        let pattern = pattern.to_ascii_lowercase();
+        let before = self.payee_rules.len();
+        self.payee_rules.retain(|rule| rule.pattern != pattern);
+        if self.payee_rules.len() == before {
+            return Err(String::from("rule_not_found"));
+        }
+        Ok(format!("\"{}\" rule removed", pattern))
+    }
+
+    //categorize(): the expense category the first matching payee rule sends a payee to
+    fn categorize(&self, payee: &str) -> Option<String> {
+        let payee = payee.to_ascii_lowercase();
+        self.payee_rules
+            .iter()
+            .find(|rule| payee.contains(&rule.pattern))
+            .map(|rule| rule.category.clone())
+    }
+
+    //sort_statement(): works out which category each new statement row of the given account (or the primary account)
+    //would be paid into, without paying anything, the rows to pay are left in the report's imported list
+    pub fn sort_statement(&self, account: Option<&str>, rows: Vec<StatementRow>) -> Result<ImportReport, String> {
+        self.resolve_account(account)?;
+        let mut report = ImportReport::default();
+        let mut seen = BTreeSet::new();
+
+        for row in rows {
+            if self.imported.contains(&row.id) || !seen.insert(row.id.clone()) {
+                report.duplicates += 1;
+                continue;
+            }
+            if row.amount <= 0 {
+                report.credits += 1;
+                continue;
+            }
+            let Some(category) = self.categorize(&row.payee) else {
+                report.unmatched.push(row);
+                continue;
+            };
+            report.imported.push(ImportedRow {
+                id: row.id,
+                payee: row.payee,
+                category,
+                amount: row.amount,
+            });
+        }
+
+        Ok(report)
+    }
+
+    //import_row(): pays one statement row out of the given account (or the primary account) into an expense category,
+    //remembering its id so the same row is never imported twice
+    pub fn import_row(&mut self, account: Option<&str>, category: &str, cents: i64, payee: &str, id: &str) -> Result<String, String> {
+        if self.imported.contains(id) {
+            return Err(String::from("row_already_imported"));
+        }
+        let message = self.make_dynamic_payment(account, category, cents)?;
+        //keep the payee on the ledger, so reports can tell merchants apart
+        if let Some(entry) = self.journal.last_mut() {
+            entry.memo = Some(payee.to_string());
+        }
+        self.imported.insert(id.to_string());
+        Ok(message)
+    }
+}
+
+//BudgetView: a Budget as it's sent back to the user, with computed sections added alongside the stored data
+#[derive(Serialize)]
+struct BudgetView<'a> {
+    #[serde(flatten)]
+    budget: &'a Budget,
+    goal_progress: Vec<GoalProgress>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    envelope_balances: Option<Vec<EnvelopeBalance>>,
+    //what the command that was just run said about itself
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[Notification]>::is_empty")]
+    warnings: &'a [Notification],
 }
 
 //to_title_case(): takes a String and returns a new String with the first letter uppercase, and the rest lowercase
@@ -197,4 +1554,95 @@ pub fn to_title_case(s: String) -> String {
         }
     }
     out.clone()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::Frequency;
+
+    //bills(): a budget with a paycheck in it, rent paid automatically and a gym membership
+    fn bills() -> Budget {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.set_income(100000).unwrap();
+        budget.add_expense("rent", 60000, Some(true));
+        budget.add_expense("gym", 5000, None);
+        budget
+    }
+
+    #[test]
+    fn edits_keep_what_was_paid() {
+        let mut budget = bills();
+        budget.get_paid(None).unwrap();
+        budget.make_dynamic_payment(None, "gym", 2000).unwrap();
+        budget.edit_expense("GYM", Some(4000), Some(true)).unwrap();
+        assert_eq!(budget.expense_rows()[0], (String::from("gym"), 4000, 2000));
+        assert!(budget.is_automatic("gym"));
+
+        //adding an expense that exists only changes its amount
+        budget.add_expense("gym", 3000, None);
+        assert_eq!(budget.expense_rows()[0], (String::from("gym"), 3000, 2000));
+        assert!(budget.is_automatic("gym"));
+        assert_eq!(budget.edit_expense("pool", Some(1), None), Err(String::from("expense_not_found")));
+    }
+
+    #[test]
+    fn automatic_expenses_are_paid_from_each_paycheck() {
+        let mut budget = bills();
+        assert_eq!(budget.get_paid(None), Ok(String::from("Payments made!")));
+        assert_eq!((budget.primary_balance(), budget.overdue_total()), (40000, 0));
+
+        //a paycheck too small for the rent marks it overdue
+        budget.close_period();
+        budget.set_income(10000).unwrap();
+        assert_eq!(budget.get_paid(None), Ok(String::from("Paid, but not every automatic payment could be made")));
+        assert_eq!((budget.primary_balance(), budget.overdue_total()), (50000, 60000));
+        assert_eq!(budget.notifications().last().map(|warning| warning.code.as_str()), Some("payment_overdue"));
+    }
+
+    #[test]
+    fn archived_expenses_stay_but_cant_be_paid() {
+        let mut budget = bills();
+        budget.archive_expense("rent", true).unwrap();
+        budget.get_paid(None).unwrap();
+        assert_eq!(budget.primary_balance(), 100000);
+        assert_eq!(budget.make_dynamic_payment(None, "rent", 100), Err(String::from("expense_archived")));
+        assert_eq!(budget.leftover_income(), 95000);
+
+        budget.archive_expense("rent", false).unwrap();
+        budget.make_static_payment(None, "rent").unwrap();
+        assert_eq!(budget.primary_balance(), 40000);
+
+        let schedule = Schedule::new("gym", ScheduleKind::Expense, 5000, Frequency::Weekly, NaiveDate::from_ymd_opt(2999, 1, 1).unwrap(), None);
+        budget.add_schedule(schedule).unwrap();
+        assert_eq!(budget.archive_expense("gym", true), Err(String::from("expense_scheduled")));
+        assert_eq!(budget.archive_expense("pool", true), Err(String::from("expense_not_found")));
+    }
+
+    #[test]
+    fn deleting_an_expense_takes_everything_about_it_along() {
+        let mut budget = bills();
+        budget.set_parent("gym", Some("rent")).unwrap();
+        budget.add_payee_rule("landlord", "rent").unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        budget.add_schedule(Schedule::new("rent", ScheduleKind::Expense, 60000, Frequency::Weekly, day, None)).unwrap();
+        budget.run_schedules(day);
+        assert_eq!(budget.overdue_total(), 60000);
+
+        budget.delete_expense("Rent").unwrap();
+        assert_eq!(budget.expense_rows(), [(String::from("gym"), 5000, 0)]);
+        assert!(budget.schedules().is_empty() && budget.categories().is_empty());
+        assert_eq!((budget.overdue_total(), budget.remove_payee_rule("landlord")), (0, Err(String::from("rule_not_found"))));
+        assert_eq!(budget.delete_expense("rent"), Err(String::from("expense_not_found")));
+    }
+
+    #[test]
+    fn old_automatic_prefixes_become_flags() {
+        let stored = r#"{"username": "sam", "expected_income": 0, "current_balance": 700, "savings": 50,
+            "expected_expenses": {"*rent": 600, "*gym": 50, "gym": 40}, "current_expenses": {"*rent": 600, "*gym": 0, "gym": 0}}"#;
+        let budget = Budget::from_json(stored).unwrap();
+        assert_eq!((budget.primary_balance(), budget.savings_balance()), (700, 50));
+        assert_eq!(budget.expense_rows().iter().map(|(name, _, _)| name.as_str()).collect::<Vec<_>>(), ["*gym", "gym", "rent"]);
+        assert!(budget.is_automatic("rent") && budget.is_automatic("*gym") && !budget.is_automatic("gym"));
+    }
+}
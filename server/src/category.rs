@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+use serde::{self, Deserialize, Serialize};
+
+use crate::alerts::LimitPolicy;
+use crate::history::{EntryKind, LedgerEntry};
+
+//the most tags a single payment can carry
+pub const MAX_TAGS: usize = 10;
+
+//CategoryInfo: what's known about an expense category beyond its amounts, only stored for categories that have anything set
+//categories only go two levels deep: a category with a parent can't be a parent itself
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct CategoryInfo {
+    //the group the category rolls up into (housing for rent), which doesn't have to be an expense itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    //paid in full out of every paycheck
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub automatic: bool,
+    //no longer budgeted for or paid into, but kept along with what was paid into it this period
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    //what happens to payments that would take the category over its expected amount
+    #[serde(default, skip_serializing_if = "LimitPolicy::is_default")]
+    pub policy: LimitPolicy,
+}
+impl CategoryInfo {
+    pub fn is_empty(&self) -> bool {
+        *self == CategoryInfo::default()
+    }
+}
+
+//group_of(): the group a category rolls up into, which is the category itself when it has no parent
+pub fn group_of(categories: &BTreeMap<String, CategoryInfo>, name: &str) -> String {
+    categories
+        .get(name)
+        .and_then(|info| info.parent.clone())
+        .unwrap_or_else(|| name.to_string())
+}
+
+//parse_tags(): lowercases and trims the tags sent with a payment, dropping repeats
+pub fn parse_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut parsed: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_ascii_lowercase();
+        if tag.is_empty() {
+            return Err(String::from("invalid_tag"));
+        }
+        if !parsed.contains(&tag) {
+            parsed.push(tag);
+        }
+    }
+    if parsed.len() > MAX_TAGS {
+        return Err(String::from("too_many_tags"));
+    }
+    Ok(parsed)
+}
+
+//current_labels(): the name each ledger entry's category goes by now, following every rename and merge made after it
+//walks the ledger backwards, so a rename only applies to the entries recorded before it
+//renames that were undone are skipped
+pub fn current_labels(ledger: &[LedgerEntry]) -> Vec<String> {
+    let mut renamed: BTreeMap<String, String> = BTreeMap::new();
+    let mut undone: Vec<(String, String)> = Vec::new();
+    let mut labels: Vec<String> = Vec::with_capacity(ledger.len());
+
+    let resolve = |renamed: &BTreeMap<String, String>, label: &str| {
+        renamed.get(label).cloned().unwrap_or_else(|| label.to_string())
+    };
+
+    for entry in ledger.iter().rev() {
+        if entry.kind == EntryKind::Rename {
+            let to = entry.memo.clone().unwrap_or_default();
+            if let Some(index) = undone.iter().position(|(from, into)| *from == entry.label && *into == to) {
+                undone.remove(index);
+            } else {
+                let to = resolve(&renamed, &to);
+                renamed.insert(entry.label.clone(), to);
+            }
+        } else if entry.kind == EntryKind::RenameUndone {
+            undone.push((entry.label.clone(), entry.memo.clone().unwrap_or_default()));
+        }
+        labels.push(resolve(&renamed, &entry.label));
+    }
+
+    labels.reverse();
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::Budget;
+    use crate::import::StatementRow;
+
+    //groceries(): a budget with food filed under a group, paid into and paid by a payee rule
+    fn groceries() -> Budget {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.get_paid_value(None, 100000).unwrap();
+        budget.add_expense("food", 30000, None);
+        budget.add_expense("snacks", 5000, None);
+        budget.set_parent("food", Some("living")).unwrap();
+        budget.add_payee_rule("grocer", "food").unwrap();
+        budget.make_dynamic_payment(None, "food", 1000).unwrap();
+        budget.make_dynamic_payment(None, "snacks", 200).unwrap();
+        budget
+    }
+
+    //entry(): a ledger entry with the given kind and label, renames carrying where to in their memo
+    fn entry(kind: EntryKind, label: &str, to: Option<&str>) -> LedgerEntry {
+        let mut entry = LedgerEntry::new(kind, None, label, 0, 0);
+        entry.memo = to.map(str::to_string);
+        entry
+    }
+
+    #[test]
+    fn tidies_tags() {
+        let tags = vec![String::from(" Work "), String::from("work"), String::from("Lunch")];
+        assert_eq!(parse_tags(tags), Ok(vec![String::from("work"), String::from("lunch")]));
+        assert_eq!(parse_tags(vec![String::from(" ")]), Err(String::from("invalid_tag")));
+        let tags = (0..=MAX_TAGS).map(|tag| tag.to_string()).collect();
+        assert_eq!(parse_tags(tags), Err(String::from("too_many_tags")));
+    }
+
+    #[test]
+    fn groups_only_go_two_levels_deep() {
+        let mut budget = groceries();
+        assert_eq!(group_of(budget.categories(), "food"), "living");
+        assert_eq!(group_of(budget.categories(), "snacks"), "snacks");
+        assert_eq!(budget.set_parent("snacks", Some("food")), Err(String::from("category_too_deep")));
+        assert_eq!(budget.set_parent("snacks", Some("snacks")), Err(String::from("invalid_parent")));
+        assert_eq!(budget.set_parent("gym", Some("living")), Err(String::from("expense_not_found")));
+
+        budget.set_parent("food", None).unwrap();
+        assert!(budget.categories().is_empty());
+        budget.set_parent("food", Some("snacks")).unwrap();
+        assert_eq!(budget.set_parent("snacks", Some("living")), Err(String::from("category_too_deep")));
+    }
+
+    #[test]
+    fn renames_carry_everything_along() {
+        let mut budget = groceries();
+        budget.rename_expense("Food", "Groceries", false).unwrap();
+        assert_eq!(budget.expense_rows(), [(String::from("groceries"), 30000, 1000), (String::from("snacks"), 5000, 200)]);
+        assert_eq!(group_of(budget.categories(), "groceries"), "living");
+        budget.make_dynamic_payment(None, "groceries", 500).unwrap();
+
+        //renaming the group refiles what's in it
+        budget.rename_expense("living", "household", false).unwrap();
+        assert_eq!(group_of(budget.categories(), "groceries"), "household");
+
+        //merging adds both categories' amounts together, and the rule follows
+        budget.rename_expense("groceries", "snacks", true).unwrap();
+        assert_eq!(budget.expense_rows(), [(String::from("snacks"), 35000, 1700)]);
+        let row = StatementRow { id: String::from("1"), date: None, payee: String::from("GROCER 12"), amount: 100 };
+        let report = budget.sort_statement(None, vec![row]).unwrap();
+        assert_eq!(report.imported[0].category, "snacks");
+        assert!(budget.categories().is_empty());
+    }
+
+    #[test]
+    fn turns_down_bad_renames() {
+        let mut budget = groceries();
+        let before = serde_json::to_string(&budget).unwrap();
+        assert_eq!(budget.rename_expense("food", "snacks", false), Err(String::from("expense_exists")));
+        assert_eq!(budget.rename_expense("food", "gym", true), Err(String::from("expense_not_found")));
+        assert_eq!(budget.rename_expense("gym", "food", false), Err(String::from("expense_not_found")));
+        assert_eq!(budget.rename_expense("food", "FOOD", false), Err(String::from("invalid_rename")));
+        assert_eq!(budget.rename_expense("food", "", false), Err(String::from("invalid_rename")));
+        assert_eq!(serde_json::to_string(&budget).unwrap(), before);
+    }
+
+    #[test]
+    fn labels_follow_later_renames() {
+        let ledger = [
+            entry(EntryKind::Payment, "food", None),
+            entry(EntryKind::Rename, "food", Some("groceries")),
+            entry(EntryKind::Payment, "groceries", None),
+            entry(EntryKind::Payment, "snacks", None),
+            entry(EntryKind::Rename, "groceries", Some("living")),
+            //snacks merged into living, then the merge undone
+            entry(EntryKind::Rename, "snacks", Some("living")),
+            entry(EntryKind::RenameUndone, "snacks", Some("living")),
+        ];
+        assert_eq!(current_labels(&ledger)[..4], ["living", "living", "living", "snacks"]);
+    }
+}
@@ -0,0 +1,969 @@
+use chrono::NaiveDate;
+use serde::{self, Deserialize, Serialize};
+
+use crate::account::AccountKind;
+use crate::alerts::{LimitPolicy, Notification};
+use crate::budget::Budget;
+use crate::category;
+use crate::currency::Locale;
+use crate::goal;
+use crate::history::History;
+use crate::http_utils::ErrorBody;
+use crate::import::{FailedRow, ImportReport, StatementRow};
+use crate::money::{self, Money, RawMoney};
+use crate::period::PeriodSnapshot;
+use crate::schedule::{Frequency, Schedule, ScheduleKind};
+
+//the version of the command api this server speaks
+//commands without a version are read as the current version
+pub const COMMAND_API_VERSION: u64 = 1;
+
+//the most commands a single batch can hold
+const MAX_BATCH_COMMANDS: usize = 500;
+
+//AmountOrAll: an amount, or "all" of an account's balance
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "RawMoney")]
+pub enum AmountOrAll {
+    All,
+    Amount(Money),
+}
+impl TryFrom<RawMoney> for AmountOrAll {
+    type Error = String;
+
+    fn try_from(raw: RawMoney) -> Result<AmountOrAll, String> {
+        match raw {
+            RawMoney::Text(text) if text.trim() == "all" => Ok(AmountOrAll::All),
+            raw => Money::try_from(raw).map(AmountOrAll::Amount),
+        }
+    }
+}
+
+//Command: every command a user can send to POST /user, picked by its "command" field
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum Command {
+    //new: adds an expected expense, automatic ones are paid out of every paycheck
+    New { label: String, amount: Money, automatic: Option<bool> },
+    //edit: changes an expense's expected amount or whether it's automatic, keeping what's been paid into it
+    Edit { label: String, amount: Option<Money>, automatic: Option<bool> },
+    Archive { label: String },
+    Unarchive { label: String },
+    Delete { label: String },
+    //getpaid: closes the pay period and deposits a paycheck (expected_income if no amount)
+    GetPaid { amount: Option<Money> },
+    SetIncome { amount: Money },
+    RaiseIncome { amount: Money },
+    //pay: pays an expense, its whole expected amount if no amount is given
+    //an amount in another currency (a receipt from abroad) is converted into the account's currency
+    Pay { label: String, amount: Option<Money>, tags: Option<Vec<String>>, currency: Option<String> },
+    Save { amount: AmountOrAll, goal: Option<String> },
+    NewGoal { label: String, amount: Money, deadline: Option<String> },
+    //editgoal: deadline "none" removes the deadline
+    EditGoal { label: String, amount: Option<Money>, deadline: Option<String> },
+    CloseGoal { label: String },
+    //csvmapping: every column is optional, falling back to the current mapping, id "none" removes the id column
+    CsvMapping {
+        date: Option<String>,
+        payee: Option<String>,
+        amount: Option<String>,
+        dateformat: Option<String>,
+        id: Option<String>,
+        debitsnegative: Option<bool>,
+    },
+    AddRule { label: String, category: String },
+    RemoveRule { label: String },
+    //transfer: moves money out of "from" (or the command's account) into "to"
+    Transfer { to: String, from: Option<String>, amount: AmountOrAll },
+    //newaccount: accounts are held in the base currency unless another one (with an exchange rate) is given
+    NewAccount { label: String, kind: AccountKind, amount: Option<Money>, currency: Option<String> },
+    SetPrimary { label: String },
+    //schedule: start defaults to today, day only applies to monthly schedules
+    Schedule {
+        label: String,
+        amount: Money,
+        kind: ScheduleKind,
+        frequency: String,
+        start: Option<String>,
+        day: Option<u32>,
+    },
+    Unschedule { label: String },
+    //setparent: files an expense under a group, parent "none" takes it out of its group
+    SetParent { label: String, parent: String },
+    //rename: renames an expense (or a group), merge: folds one expense into another, both keep what's been paid
+    Rename { label: String, to: String },
+    Merge { label: String, into: String },
+    //policy: what happens to payments over an expense's expected amount (allow, warn or block)
+    Policy { label: String, policy: LimitPolicy },
+    //floor: the lowest balance payments and transfers should leave an account at, and what happens below it
+    Floor { amount: Money, policy: Option<LimitPolicy> },
+    ClearNotifications,
+    //envelopes: turns envelope budgeting on or off
+    Envelopes { enabled: bool },
+    //assign: moves money from the unassigned pool into an expense's envelope (out of it when negative)
+    Assign { label: String, amount: Money },
+    //move: moves money from one envelope to another
+    Move { label: String, to: String, amount: Money },
+    //setcurrency: changes the base currency every total is given in, and how amounts are written
+    SetCurrency { currency: Option<String>, locale: Option<Locale> },
+    //rate: how much of the base currency one of another currency is worth
+    Rate { currency: String, rate: f64 },
+    //importrates: reads an exchange rate file's contents, one "code,rate" line per currency
+    ImportRates { data: String },
+    //importrow: pays one bank statement row into an expense category, sent in the batches statement imports are run as
+    ImportRow { label: String, amount: Money, payee: String, id: String },
+    //undo: reverts the last command, redo: runs the last undone command again
+    Undo,
+    Redo,
+}
+
+//every command's name, with the fields it can't go without
+const COMMAND_FIELDS: [(&str, &[&str]); 36] = [
+    ("new", &["label", "amount"]),
+    ("edit", &["label"]),
+    ("archive", &["label"]),
+    ("unarchive", &["label"]),
+    ("delete", &["label"]),
+    ("getpaid", &[]),
+    ("setincome", &["amount"]),
+    ("raiseincome", &["amount"]),
+    ("pay", &["label"]),
+    ("save", &["amount"]),
+    ("newgoal", &["label", "amount"]),
+    ("editgoal", &["label"]),
+    ("closegoal", &["label"]),
+    ("csvmapping", &[]),
+    ("addrule", &["label", "category"]),
+    ("removerule", &["label"]),
+    ("transfer", &["to", "amount"]),
+    ("newaccount", &["label", "kind"]),
+    ("setprimary", &["label"]),
+    ("schedule", &["label", "amount", "kind", "frequency"]),
+    ("unschedule", &["label"]),
+    ("setparent", &["label", "parent"]),
+    ("rename", &["label", "to"]),
+    ("merge", &["label", "into"]),
+    ("policy", &["label", "policy"]),
+    ("floor", &["amount"]),
+    ("clearnotifications", &[]),
+    ("envelopes", &["enabled"]),
+    ("assign", &["label", "amount"]),
+    ("move", &["label", "to", "amount"]),
+    ("setcurrency", &[]),
+    ("rate", &["currency", "rate"]),
+    ("importrates", &["data"]),
+    ("importrow", &["label", "amount", "payee", "id"]),
+    ("undo", &[]),
+    ("redo", &[]),
+];
+
+//CommandRequest: a parsed POST /user body
+#[derive(Debug)]
+pub struct CommandRequest {
+    //the command's name, as sent
+    pub name: String,
+    //the account the command targets (the primary account if not given)
+    pub account: Option<String>,
+    pub command: Command,
+}
+impl CommandRequest {
+    //parse(): reads a command request out of a json body
+    //version and account are shared by every command, so they're pulled out before reading the command itself
+    pub fn parse(body: &str) -> Result<CommandRequest, ErrorBody> {
+        match serde_json::from_str::<serde_json::Value>(body) {
+            Ok(value) => CommandRequest::from_value(value),
+            Err(_err) => Err(ErrorBody::new("invalid_json", None, "the request body must be a json object")),
+        }
+    }
+
+    //from_value(): reads a command request out of an already parsed json value
+    pub fn from_value(value: serde_json::Value) -> Result<CommandRequest, ErrorBody> {
+        let serde_json::Value::Object(mut obj) = value else {
+            return Err(ErrorBody::new("invalid_json", None, "a command must be a json object"));
+        };
+
+        let version = match obj.remove("version") {
+            Some(version) => version
+                .as_u64()
+                .ok_or(ErrorBody::new("invalid_field", Some("version"), "version must be a whole number"))?,
+            None => COMMAND_API_VERSION,
+        };
+        if version == 0 || version > COMMAND_API_VERSION {
+            return Err(ErrorBody::new(
+                "unsupported_version",
+                Some("version"),
+                &format!("this server speaks command api version {}", COMMAND_API_VERSION),
+            ));
+        }
+
+        let account = match obj.remove("account") {
+            Some(serde_json::Value::String(account)) => Some(account),
+            Some(_) => return Err(ErrorBody::new("invalid_field", Some("account"), "account must be a string")),
+            None => None,
+        };
+
+        //a null field is the same as one that's left out
+        obj.retain(|_, value| !value.is_null());
+
+        let name = match obj.get("command") {
+            Some(serde_json::Value::String(name)) => name.clone(),
+            Some(_) => return Err(ErrorBody::new("invalid_field", Some("command"), "command must be a string")),
+            None => return Err(ErrorBody::new("missing_command", Some("command"), "a command is required")),
+        };
+        let Some((_, required)) = COMMAND_FIELDS.iter().find(|(command, _)| *command == name) else {
+            return Err(ErrorBody::new("unknown_command", Some("command"), &format!("there's no {:?} command", name)));
+        };
+        if let Some(field) = required.iter().find(|field| !obj.contains_key(**field)) {
+            return Err(ErrorBody::new("missing_field", Some(field), &format!("{} needs a {}", name, field)));
+        }
+        for (field, value) in &obj {
+            check_field(&name, field, value)?;
+        }
+
+        //every field has been checked, so this only fails if COMMAND_FIELDS and Command disagree
+        let command = serde_json::from_value(serde_json::Value::Object(obj))
+            .map_err(|err| ErrorBody::new("invalid_command", None, &err.to_string()))?;
+
+        Ok(CommandRequest { name, account, command })
+    }
+}
+
+//check_field(): makes sure a field of a command holds the right sort of value, so a bad one is reported by name
+//fields no command has are left alone
+fn check_field(command: &str, field: &str, value: &serde_json::Value) -> Result<(), ErrorBody> {
+    let invalid = |what: &str| Err(ErrorBody::new("invalid_field", Some(field), &format!("{} must be {}", field, what)));
+    match field {
+        //csvmapping's amount is the name of a column, so it's checked as a string below
+        "amount" if command != "csvmapping" => {
+            let Ok(raw) = RawMoney::deserialize(value) else {
+                return invalid("a number of dollars");
+            };
+            match raw {
+                RawMoney::Text(text) if text.trim() == "all" && (command == "save" || command == "transfer") => Ok(()),
+                raw => match Money::try_from(raw) {
+                    Ok(_) => Ok(()),
+                    Err(code) if code == money::AMOUNT_TOO_LARGE => {
+                        Err(ErrorBody::new(money::AMOUNT_TOO_LARGE, Some(field), "amount is too large"))
+                    }
+                    Err(_) => invalid("a number of dollars"),
+                },
+            }
+        }
+        "automatic" | "debitsnegative" | "enabled" if !value.is_boolean() => invalid("true or false"),
+        "day" if value.as_u64().is_none_or(|day| u32::try_from(day).is_err()) => invalid("a whole number"),
+        "rate" if !value.is_number() => invalid("a number"),
+        "tags" if !value.as_array().is_some_and(|tags| tags.iter().all(serde_json::Value::is_string)) => {
+            invalid("a list of strings")
+        }
+        "kind" if command == "newaccount" && AccountKind::deserialize(value).is_err() => {
+            invalid("checking, savings, credit_card or cash")
+        }
+        "kind" if command == "schedule" && ScheduleKind::deserialize(value).is_err() => invalid("income or expense"),
+        "policy" if LimitPolicy::deserialize(value).is_err() => invalid("allow, warn or block"),
+        "locale" if Locale::deserialize(value).is_err() => invalid("a supported locale"),
+        "label" | "to" | "from" | "into" | "parent" | "category" | "currency" | "goal" | "deadline" | "frequency"
+        | "start" | "data" | "date" | "payee" | "amount" | "dateformat" | "id"
+            if !value.is_string() =>
+        {
+            invalid("a string")
+        }
+        _ => Ok(()),
+    }
+}
+
+//budget_error(): turns an error code from the budget into an ErrorBody, naming the field of the command it's about
+//fields maps codes to the command's own field names, codes it leaves out get the field they're usually about
+fn budget_error(code: String, fields: &[(&str, &str)]) -> ErrorBody {
+    let field = fields
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map(|(_, field)| *field)
+        .or_else(|| usual_field(&code));
+    ErrorBody::from_code(code, field)
+}
+
+//usual_field(): the field an error code from the budget is about, for most commands
+fn usual_field(code: &str) -> Option<&'static str> {
+    match code {
+        "account_not_found" | "same_account_transfer" | "no_savings_account" => Some("account"),
+        "expense_not_found" | "expense_archived" | "expense_exists" | "expense_scheduled" | "invalid_rename"
+        | "category_too_deep" | "goal_not_found" | "goal_closed" | "goal_already_exists" | "invalid_goal_name"
+        | "schedule_not_found" | "rule_not_found" | "invalid_rule_pattern" | "invalid_account_name"
+        | "account_already_exists" => Some("label"),
+        "negative_transfer_amount" | "insufficient_balance" | "over_limit" | "envelope_empty" | "below_balance_floor"
+        | "invalid_goal_target_value" | "invalid_envelope_move" | "not_enough_unassigned" | "not_enough_in_envelope"
+        | "amount_too_large" | "invalid_payment_amount" | "invalid_deposit_amount" | "negative_income" => Some("amount"),
+        "invalid_currency" | "missing_exchange_rate" | "foreign_accounts_open" | "base_currency_rate" => Some("currency"),
+        "invalid_exchange_rate" => Some("rate"),
+        "invalid_parent" => Some("parent"),
+        "invalid_tag" | "too_many_tags" => Some("tags"),
+        "invalid_goal_deadline_value" => Some("deadline"),
+        "invalid_schedule_frequency_value" => Some("frequency"),
+        "invalid_schedule_day_value" => Some("day"),
+        _ => None,
+    }
+}
+
+//CommandOutcome: what a command that ran had to say, sent back alongside the budget
+#[derive(Debug, Default)]
+pub struct CommandOutcome {
+    //the message the budget gave back for the command, if it gave one
+    pub message: Option<String>,
+    //warnings raised by the command, which are also kept as notifications
+    pub warnings: Vec<Notification>,
+}
+
+//execute(): runs a command against a user's budget and history, remembering it so it can be undone
+//the command runs against a copy of the budget, which is only swapped in if it succeeds, so a failed one changes nothing
+pub fn execute(request: CommandRequest, budget: &mut Budget, history: &mut History) -> Result<CommandOutcome, ErrorBody> {
+    match request.command {
+        Command::Undo => history
+            .undo(budget)
+            .map(|message| CommandOutcome { message: Some(message), warnings: Vec::new() })
+            .map_err(|code| ErrorBody::from_code(code, None)),
+        Command::Redo => history
+            .redo(budget)
+            .map(|message| CommandOutcome { message: Some(message), warnings: Vec::new() })
+            .map_err(|code| ErrorBody::from_code(code, None)),
+        command => {
+            //anything already noted down belongs to earlier commands
+            history.record(budget.take_journal());
+            budget.take_warnings();
+
+            let mut after = budget.clone();
+            let (message, closed) = run(command, request.account.as_deref(), &mut after)?;
+            let before = std::mem::replace(budget, after);
+            if let Some(snapshot) = &closed {
+                history.archive_period(snapshot.clone());
+            }
+
+            let entries = budget.take_journal();
+            history.record(entries.clone());
+            history.checkpoint(&request.name, &before, budget, closed.into_iter().collect(), entries);
+            Ok(CommandOutcome {
+                message: message.filter(|message| !message.is_empty()),
+                warnings: budget.take_warnings(),
+            })
+        }
+    }
+}
+
+//run(): does whatever a command asks of the budget, handing back the budget's message about it,
+//and the pay period it closed if it closed one, to be archived once the command has succeeded
+fn run(command: Command, account: Option<&str>, budget: &mut Budget) -> Result<(Option<String>, Option<PeriodSnapshot>), ErrorBody> {
+    let mut closed = None;
+    let message = match command {
+        Command::New { label, amount, automatic } => {
+            budget.add_expense(&label, amount.0, automatic);
+            None
+        }
+        Command::Edit { label, amount, automatic } => Some(
+            budget
+                .edit_expense(&label, amount.map(|amount| amount.0), automatic)
+                .map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::Archive { label } => Some(
+            budget.archive_expense(&label, true).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::Unarchive { label } => Some(
+            budget.archive_expense(&label, false).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::Delete { label } => Some(
+            budget.delete_expense(&label).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::GetPaid { amount } => {
+            //getting paid closes out the current pay period
+            closed = Some(budget.close_period());
+            Some(
+                match amount {
+                    Some(amount) => budget.get_paid_value(account, amount.0),
+                    None => budget.get_paid(account),
+                }
+                .map_err(|code| budget_error(code, &[("amount_too_large", "account")]))?,
+            )
+        }
+        Command::SetIncome { amount } => {
+            budget.set_income(amount.0).map_err(|code| budget_error(code, &[]))?;
+            None
+        }
+        Command::RaiseIncome { amount } => {
+            budget.add_income(amount.0).map_err(|code| budget_error(code, &[]))?;
+            None
+        }
+        Command::Pay { label, amount, tags, currency } => {
+            let tags = category::parse_tags(tags.unwrap_or_default())
+                .map_err(|code| ErrorBody::from_code(code, Some("tags")))?;
+            let message = match (amount, currency) {
+                (Some(amount), Some(currency)) => budget
+                    .convert_into(account, amount.0, &currency)
+                    .and_then(|amount| budget.make_dynamic_payment(account, &label, amount)),
+                (Some(amount), None) => budget.make_dynamic_payment(account, &label, amount.0),
+                (None, _) => budget.make_static_payment(account, &label),
+            }
+            .map_err(|code| budget_error(code, &[]))?;
+            budget.tag_last_entry(tags);
+            Some(message)
+        }
+        Command::Save { amount, goal } => Some(
+            match amount {
+                AmountOrAll::Amount(amount) => budget.save(account, amount.0, goal.as_deref()),
+                AmountOrAll::All => budget.save_all(account, goal.as_deref()),
+            }
+            .map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::NewGoal { label, amount, deadline } => {
+            let deadline = deadline
+                .map(|deadline| goal::parse_deadline(&deadline))
+                .transpose()
+                .map_err(|code| ErrorBody::from_code(code, Some("deadline")))?;
+            Some(
+                budget
+                    .add_goal(&label, amount.0, deadline)
+                    .map_err(|code| budget_error(code, &[]))?,
+            )
+        }
+        Command::EditGoal { label, amount, deadline } => {
+            let deadline = match deadline.as_deref() {
+                Some("none") => Some(None),
+                Some(deadline) => Some(Some(
+                    goal::parse_deadline(deadline).map_err(|code| ErrorBody::from_code(code, Some("deadline")))?,
+                )),
+                None => None,
+            };
+            Some(
+                budget
+                    .edit_goal(&label, amount.map(|amount| amount.0), deadline)
+                    .map_err(|code| budget_error(code, &[]))?,
+            )
+        }
+        Command::CloseGoal { label } => Some(
+            budget.close_goal(&label).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::CsvMapping { date, payee, amount, dateformat, id, debitsnegative } => {
+            let mut mapping = budget.csv_mapping().clone();
+            if let Some(date) = date {
+                mapping.date = date;
+            }
+            if let Some(payee) = payee {
+                mapping.payee = payee;
+            }
+            if let Some(amount) = amount {
+                mapping.amount = amount;
+            }
+            if let Some(dateformat) = dateformat {
+                mapping.date_format = dateformat;
+            }
+            if let Some(id) = id {
+                mapping.id = if id == "none" { None } else { Some(id) };
+            }
+            if let Some(debitsnegative) = debitsnegative {
+                mapping.debits_negative = debitsnegative;
+            }
+            budget.set_csv_mapping(mapping);
+            None
+        }
+        Command::AddRule { label, category } => Some(
+            budget
+                .add_payee_rule(&label, &category)
+                .map_err(|code| budget_error(code, &[("expense_not_found", "category")]))?,
+        ),
+        Command::RemoveRule { label } => Some(
+            budget.remove_payee_rule(&label).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::Transfer { to, from, amount } => {
+            budget
+                .resolve_account(Some(&to))
+                .map_err(|code| budget_error(code, &[("account_not_found", "to")]))?;
+            //from is named by its own field, or by the command's account
+            let from_field = if from.is_some() { "from" } else { "account" };
+            let from = from.as_deref().or(account);
+            Some(
+                match amount {
+                    AmountOrAll::Amount(amount) => budget.transfer(from, &to, amount.0),
+                    AmountOrAll::All => budget.transfer_all(from, &to),
+                }
+                .map_err(|code| budget_error(code, &[("account_not_found", from_field), ("same_account_transfer", "to")]))?,
+            )
+        }
+        Command::NewAccount { label, kind, amount, currency } => Some(
+            budget
+                .add_account(&label, kind, amount.map_or(0, |amount| amount.0), currency.as_deref())
+                .map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::SetPrimary { label } => Some(
+            budget
+                .set_primary_account(&label)
+                .map_err(|code| budget_error(code, &[("account_not_found", "label")]))?,
+        ),
+        Command::Schedule { label, amount, kind, frequency, start, day } => {
+            let today = chrono::Local::now().date_naive();
+            let start = match start {
+                Some(start) => NaiveDate::parse_from_str(&start, "%Y-%m-%d")
+                    .map_err(|_err| ErrorBody::from_code("invalid_schedule_start_value".into(), Some("start")))?,
+                None => today,
+            };
+            let frequency = Frequency::parse(&frequency, start, day).map_err(|code| budget_error(code, &[]))?;
+
+            let schedule = Schedule::new(&label, kind, amount.0, frequency, start, account);
+            let message = budget.add_schedule(schedule).map_err(|code| budget_error(code, &[]))?;
+
+            //the first occurrence may already be due
+            budget.run_schedules(today);
+            Some(message)
+        }
+        Command::Unschedule { label } => Some(
+            budget.remove_schedule(&label).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::SetParent { label, parent } => {
+            let parent = if parent == "none" { None } else { Some(parent.as_str()) };
+            Some(
+                budget
+                    .set_parent(&label, parent)
+                    .map_err(|code| budget_error(code, &[("category_too_deep", "parent")]))?,
+            )
+        }
+        Command::Rename { label, to } => Some(
+            budget
+                .rename_expense(&label, &to, false)
+                .map_err(|code| budget_error(code, &[("expense_exists", "to")]))?,
+        ),
+        Command::Merge { label, into } => Some(
+            budget.rename_expense(&label, &into, true).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::Policy { label, policy } => Some(
+            budget.set_policy(&label, policy).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::Floor { amount, policy } => Some(
+            budget.set_balance_floor(amount.0, policy).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::ClearNotifications => Some(
+            budget.clear_notifications().map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::Envelopes { enabled } => Some(
+            budget.set_envelopes(enabled).map_err(|code| ErrorBody::from_code(code, Some("enabled")))?,
+        ),
+        Command::Assign { label, amount } => Some(
+            budget.assign(&label, amount.0).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::Move { label, to, amount } => Some(
+            budget.move_envelope(&label, &to, amount.0).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::SetCurrency { currency, locale } => Some(
+            budget
+                .set_currency(currency.as_deref(), locale)
+                .map_err(|code| ErrorBody::from_code(code, Some("currency")))?,
+        ),
+        Command::Rate { currency, rate } => Some(
+            budget.set_rate(&currency, rate).map_err(|code| budget_error(code, &[]))?,
+        ),
+        Command::ImportRates { data } => Some(
+            budget.import_rates(&data).map_err(|code| ErrorBody::from_code(code, Some("data")))?,
+        ),
+        Command::ImportRow { label, amount, payee, id } => Some(
+            budget
+                .import_row(account, &label, amount.0, &payee, &id)
+                .map_err(|code| budget_error(code, &[("row_already_imported", "id")]))?,
+        ),
+        Command::Undo | Command::Redo => unreachable!("undo and redo are handled by execute()"),
+    };
+
+    Ok((message, closed))
+}
+
+//BatchResult: what happened to one command of a batch
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchResult {
+    Ok {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Notification>,
+    },
+    Failed { error: ErrorBody },
+    //commands after a failed one aren't run
+    Skipped,
+}
+
+//BatchReport: the outcome of a whole batch, committed only if every command succeeded
+#[derive(Debug, Serialize)]
+pub struct BatchReport {
+    pub committed: bool,
+    pub results: Vec<BatchResult>,
+}
+
+//execute_batch(): runs a json array of commands against copies of the budget and history,
+//then swaps the copies in only if every command succeeded
+pub fn execute_batch(body: &str, budget: &mut Budget, history: &mut History) -> Result<BatchReport, ErrorBody> {
+    let Ok(serde_json::Value::Array(commands)) = serde_json::from_str::<serde_json::Value>(body) else {
+        return Err(ErrorBody::new("invalid_json", None, "a batch must be a json array of commands"));
+    };
+    if commands.is_empty() {
+        return Err(ErrorBody::new("empty_batch", None, "a batch needs at least one command"));
+    }
+    if commands.len() > MAX_BATCH_COMMANDS {
+        return Err(ErrorBody::new(
+            "batch_too_large",
+            None,
+            &format!("a batch can hold at most {} commands", MAX_BATCH_COMMANDS),
+        ));
+    }
+
+    let mut batch_budget = budget.clone();
+    let mut batch_history = history.clone();
+    let mut results = Vec::with_capacity(commands.len());
+    let mut failed = false;
+
+    for command in commands {
+        if failed {
+            results.push(BatchResult::Skipped);
+            continue;
+        }
+
+        match CommandRequest::from_value(command)
+            .and_then(|request| execute(request, &mut batch_budget, &mut batch_history))
+        {
+            Ok(outcome) => results.push(BatchResult::Ok {
+                message: outcome.message,
+                warnings: outcome.warnings,
+            }),
+            Err(error) => {
+                failed = true;
+                results.push(BatchResult::Failed { error });
+            }
+        }
+    }
+
+    if !failed {
+        *budget = batch_budget;
+        *history = batch_history;
+    }
+
+    Ok(BatchReport { committed: !failed, results })
+}
+
+//import_statement(): pays every new row of a bank statement into the category its payee rule picks,
+//as a batch of importrow commands, so either every row is paid or none are
+//a row that couldn't be paid is moved from the report's imported list to its failed one
+pub fn import_statement(
+    account: Option<&str>,
+    rows: Vec<StatementRow>,
+    budget: &mut Budget,
+    history: &mut History,
+) -> Result<ImportReport, ErrorBody> {
+    let mut report = budget.sort_statement(account, rows).map_err(|code| budget_error(code, &[]))?;
+    if report.imported.is_empty() {
+        return Ok(report);
+    }
+
+    let batch = execute_batch(&report.batch(account), budget, history)?;
+    if !batch.committed {
+        let failed = batch.results.into_iter().zip(std::mem::take(&mut report.imported)).find_map(|(result, row)| match result {
+            BatchResult::Failed { error } => Some(FailedRow { payee: row.payee, amount: row.amount, error: error.code }),
+            _ => None,
+        });
+        report.failed.extend(failed);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    //a value that fits the field, for building commands that should parse
+    fn sample(command: &str, field: &str) -> serde_json::Value {
+        match (command, field) {
+            (_, "amount") => json!("10"),
+            ("newaccount", "kind") => json!("savings"),
+            (_, "kind") => json!("expense"),
+            (_, "frequency") => json!("monthly"),
+            (_, "policy") => json!("warn"),
+            (_, "enabled") => json!(true),
+            (_, "currency") => json!("EUR"),
+            (_, "rate") => json!(1.1),
+            (_, "data") => json!("EUR,1.1"),
+            _ => json!("rent"),
+        }
+    }
+
+    fn parse(value: serde_json::Value) -> Result<CommandRequest, ErrorBody> {
+        CommandRequest::from_value(value)
+    }
+
+    //send(): parses and executes one command, like a POST /user body
+    fn send(budget: &mut Budget, history: &mut History, value: serde_json::Value) -> Result<CommandOutcome, ErrorBody> {
+        execute(parse(value)?, budget, history)
+    }
+
+    #[test]
+    fn every_command_parses_with_its_required_fields() {
+        for (name, required) in COMMAND_FIELDS {
+            let mut obj = serde_json::Map::new();
+            obj.insert("command".into(), json!(name));
+            for field in required {
+                obj.insert(field.to_string(), sample(name, field));
+            }
+            let request = parse(serde_json::Value::Object(obj.clone()));
+            assert!(request.is_ok(), "{} didn't parse: {:?}", name, request.err());
+
+            for field in required {
+                let mut missing = obj.clone();
+                missing.remove(*field);
+                let error = parse(serde_json::Value::Object(missing)).unwrap_err();
+                assert_eq!((error.code.as_str(), error.field.as_deref()), ("missing_field", Some(*field)), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn every_command_is_in_the_field_table() {
+        //serde lists every variant of Command when it's handed one it doesn't know
+        let error = serde_json::from_value::<Command>(json!({"command": "?"})).unwrap_err().to_string();
+        let (_, expected) = error.split_once("expected one of ").unwrap();
+        let names: Vec<&str> = expected.split(", ").map(|name| name.trim_matches('`')).collect();
+        for name in &names {
+            assert!(COMMAND_FIELDS.iter().any(|(command, _)| command == name), "{} is missing from COMMAND_FIELDS", name);
+        }
+        assert_eq!(names.len(), COMMAND_FIELDS.len());
+    }
+
+    #[test]
+    fn names_the_command_at_fault() {
+        let error = parse(json!({"label": "rent"})).unwrap_err();
+        assert_eq!((error.code.as_str(), error.field.as_deref()), ("missing_command", Some("command")));
+        let error = parse(json!({"command": "fly"})).unwrap_err();
+        assert_eq!((error.code.as_str(), error.field.as_deref()), ("unknown_command", Some("command")));
+        let error = parse(json!({"command": 5})).unwrap_err();
+        assert_eq!((error.code.as_str(), error.field.as_deref()), ("invalid_field", Some("command")));
+        let error = parse(json!(["new"])).unwrap_err();
+        assert_eq!(error.code, "invalid_json");
+    }
+
+    #[test]
+    fn names_the_field_at_fault() {
+        let cases = [
+            (json!({"command": "new", "label": "rent", "amount": "ten"}), "invalid_field", "amount"),
+            (json!({"command": "new", "label": "rent", "amount": "2000000000000"}), "amount_too_large", "amount"),
+            (json!({"command": "new", "label": "rent", "amount": 5, "automatic": "yes"}), "invalid_field", "automatic"),
+            (json!({"command": "new", "label": 5, "amount": 5}), "invalid_field", "label"),
+            (json!({"command": "pay", "label": "rent", "amount": "all"}), "invalid_field", "amount"),
+            (json!({"command": "pay", "label": "rent", "tags": "a,b"}), "invalid_field", "tags"),
+            (json!({"command": "newaccount", "label": "x", "kind": "income"}), "invalid_field", "kind"),
+            (json!({"command": "schedule", "label": "x", "amount": 1, "kind": "savings", "frequency": "weekly"}), "invalid_field", "kind"),
+            (json!({"command": "schedule", "label": "x", "amount": 1, "kind": "income", "frequency": "monthly", "day": -1}), "invalid_field", "day"),
+            (json!({"command": "policy", "label": "rent", "policy": "never"}), "invalid_field", "policy"),
+            (json!({"command": "rate", "currency": "EUR", "rate": "high"}), "invalid_field", "rate"),
+            (json!({"command": "setcurrency", "locale": "xx"}), "invalid_field", "locale"),
+            (json!({"command": "new", "label": "rent", "amount": 5, "version": 9}), "unsupported_version", "version"),
+            (json!({"command": "new", "amount": 5}), "missing_field", "label"),
+            (json!({"command": "new", "label": "rent", "amount": 5, "version": "1"}), "invalid_field", "version"),
+            (json!({"command": "new", "label": "rent", "amount": 5, "account": 1}), "invalid_field", "account"),
+        ];
+        for (value, code, field) in cases {
+            let error = parse(value.clone()).unwrap_err();
+            assert_eq!((error.code.as_str(), error.field.as_deref()), (code, Some(field)), "{}", value);
+        }
+    }
+
+    #[test]
+    fn reads_the_shared_fields() {
+        let request = parse(json!({"command": "save", "amount": "all", "account": "cash", "goal": null})).unwrap();
+        assert_eq!(request.name, "save");
+        assert_eq!(request.account.as_deref(), Some("cash"));
+        assert!(matches!(request.command, Command::Save { amount: AmountOrAll::All, goal: None }));
+
+        //csvmapping's amount is a column name, not money
+        let request = parse(json!({"command": "csvmapping", "amount": "Debit"})).unwrap();
+        assert!(matches!(request.command, Command::CsvMapping { amount: Some(_), .. }));
+    }
+
+    //household(): a budget with a bit of everything in it
+    fn household() -> (Budget, History) {
+        let mut budget = Budget::new(String::from("sam"));
+        let mut history = History::default();
+        history.act_as(Some("sam"));
+        for command in [
+            json!({"command": "setincome", "amount": 100}),
+            json!({"command": "getpaid"}),
+            json!({"command": "new", "label": "rent", "amount": 50}),
+            json!({"command": "new", "label": "food", "amount": 30}),
+            json!({"command": "newaccount", "label": "wallet", "kind": "cash", "amount": 20}),
+            json!({"command": "newgoal", "label": "car", "amount": 500}),
+            json!({"command": "addrule", "label": "landlord", "category": "rent"}),
+            json!({"command": "schedule", "label": "pay", "amount": 100, "kind": "income", "frequency": "weekly", "start": "2999-01-01"}),
+        ] {
+            send(&mut budget, &mut history, command).unwrap();
+        }
+        (budget, history)
+    }
+
+    #[test]
+    fn every_command_succeeds_and_fails() {
+        //each command once as it should go (after any commands it needs first), and once as it shouldn't,
+        //with the error code and field the failure should come back with
+        let cases = [
+            (json!([]), json!({"command": "new", "label": "gym", "amount": 20}), json!({"command": "new", "amount": 20}), "missing_field", Some("label")),
+            (json!([]), json!({"command": "edit", "label": "rent", "amount": 60}), json!({"command": "edit", "label": "gym", "amount": 60}), "expense_not_found", Some("label")),
+            (json!([]), json!({"command": "archive", "label": "rent"}), json!({"command": "archive", "label": "gym"}), "expense_not_found", Some("label")),
+            (json!([{"command": "archive", "label": "rent"}]), json!({"command": "unarchive", "label": "rent"}), json!({"command": "unarchive", "label": "gym"}), "expense_not_found", Some("label")),
+            (json!([]), json!({"command": "delete", "label": "food"}), json!({"command": "delete", "label": "gym"}), "expense_not_found", Some("label")),
+            (json!([]), json!({"command": "getpaid", "amount": 5}), json!({"command": "getpaid", "account": "vault"}), "account_not_found", Some("account")),
+            (json!([]), json!({"command": "setincome", "amount": 200}), json!({"command": "setincome", "amount": "ten"}), "invalid_field", Some("amount")),
+            (json!([]), json!({"command": "getpaid", "amount": 5}), json!({"command": "getpaid", "amount": 0}), "invalid_deposit_amount", Some("amount")),
+            (json!([]), json!({"command": "setincome", "amount": 0}), json!({"command": "setincome", "amount": "-1"}), "negative_income", Some("amount")),
+            (json!([]), json!({"command": "raiseincome", "amount": 5}), json!({"command": "raiseincome", "amount": "1000000000000"}), "amount_too_large", Some("amount")),
+            (json!([]), json!({"command": "pay", "label": "rent"}), json!({"command": "pay", "label": "gym", "amount": 5}), "expense_not_found", Some("label")),
+            (json!([]), json!({"command": "pay", "label": "rent", "amount": 5}), json!({"command": "pay", "label": "rent", "amount": -5}), "invalid_payment_amount", Some("amount")),
+            (json!([]), json!({"command": "save", "amount": "all", "goal": "car"}), json!({"command": "save", "amount": 1000}), "insufficient_balance", Some("amount")),
+            (json!([]), json!({"command": "newgoal", "label": "boat", "amount": 900, "deadline": "2999-06"}), json!({"command": "newgoal", "label": "car", "amount": 900}), "goal_already_exists", Some("label")),
+            (json!([]), json!({"command": "editgoal", "label": "car", "deadline": "none"}), json!({"command": "editgoal", "label": "boat", "amount": 5}), "goal_not_found", Some("label")),
+            (json!([]), json!({"command": "closegoal", "label": "car"}), json!({"command": "closegoal", "label": "boat"}), "goal_not_found", Some("label")),
+            (json!([]), json!({"command": "csvmapping", "date": "Posted", "id": "none"}), json!({"command": "csvmapping", "debitsnegative": "yes"}), "invalid_field", Some("debitsnegative")),
+            (json!([]), json!({"command": "addrule", "label": "grocer", "category": "food"}), json!({"command": "addrule", "label": "gymco", "category": "gym"}), "expense_not_found", Some("category")),
+            (json!([]), json!({"command": "removerule", "label": "landlord"}), json!({"command": "removerule", "label": "grocer"}), "rule_not_found", Some("label")),
+            (json!([]), json!({"command": "transfer", "to": "wallet", "amount": 10}), json!({"command": "transfer", "to": "vault", "amount": 10}), "account_not_found", Some("to")),
+            (json!([]), json!({"command": "newaccount", "label": "visa", "kind": "credit_card"}), json!({"command": "newaccount", "label": "wallet", "kind": "cash"}), "account_already_exists", Some("label")),
+            (json!([]), json!({"command": "setprimary", "label": "wallet"}), json!({"command": "setprimary", "label": "vault"}), "account_not_found", Some("label")),
+            (json!([]), json!({"command": "schedule", "label": "rent", "amount": 50, "kind": "expense", "frequency": "monthly", "day": 1}), json!({"command": "schedule", "label": "rent", "amount": 50, "kind": "expense", "frequency": "daily"}), "invalid_schedule_frequency_value", Some("frequency")),
+            (json!([]), json!({"command": "unschedule", "label": "pay"}), json!({"command": "unschedule", "label": "rent"}), "schedule_not_found", Some("label")),
+            (json!([]), json!({"command": "setparent", "label": "rent", "parent": "housing"}), json!({"command": "setparent", "label": "rent", "parent": "rent"}), "invalid_parent", Some("parent")),
+            (json!([]), json!({"command": "rename", "label": "food", "to": "groceries"}), json!({"command": "rename", "label": "food", "to": "rent"}), "expense_exists", Some("to")),
+            (json!([]), json!({"command": "merge", "label": "food", "into": "rent"}), json!({"command": "merge", "label": "gym", "into": "rent"}), "expense_not_found", Some("label")),
+            (json!([]), json!({"command": "policy", "label": "rent", "policy": "block"}), json!({"command": "policy", "label": "gym", "policy": "block"}), "expense_not_found", Some("label")),
+            (json!([]), json!({"command": "floor", "amount": 10, "policy": "warn"}), json!({"command": "floor", "amount": "low"}), "invalid_field", Some("amount")),
+            (json!([]), json!({"command": "clearnotifications"}), json!({"command": "clearnotifications", "version": 2}), "unsupported_version", Some("version")),
+            (json!([]), json!({"command": "envelopes", "enabled": true}), json!({"command": "envelopes", "enabled": false}), "envelopes_not_on", Some("enabled")),
+            (json!([{"command": "envelopes", "enabled": true}]), json!({"command": "assign", "label": "rent", "amount": 50}), json!({"command": "assign", "label": "rent", "amount": 101}), "not_enough_unassigned", Some("amount")),
+            (json!([{"command": "envelopes", "enabled": true}, {"command": "assign", "label": "rent", "amount": 20}]), json!({"command": "move", "label": "rent", "to": "food", "amount": 5}), json!({"command": "move", "label": "rent", "to": "food", "amount": 21}), "not_enough_in_envelope", Some("amount")),
+            (json!([]), json!({"command": "setcurrency", "currency": "eur", "locale": "de-DE"}), json!({"command": "setcurrency", "currency": "dollars"}), "invalid_currency", Some("currency")),
+            (json!([]), json!({"command": "rate", "currency": "EUR", "rate": 1.1}), json!({"command": "rate", "currency": "USD", "rate": 1.1}), "base_currency_rate", Some("currency")),
+            (json!([]), json!({"command": "importrates", "data": "EUR,1.1\nGBP,1.3"}), json!({"command": "importrates", "data": "EUR"}), "invalid_rate_line", Some("data")),
+            (json!([{"command": "importrow", "label": "rent", "amount": 5, "payee": "LANDLORD", "id": "csv:1"}]), json!({"command": "importrow", "label": "rent", "amount": 5, "payee": "LANDLORD", "id": "csv:2"}), json!({"command": "importrow", "label": "rent", "amount": 5, "payee": "LANDLORD", "id": "csv:1"}), "row_already_imported", Some("id")),
+            (json!([]), json!({"command": "undo"}), json!({"command": "redo"}), "nothing_to_redo", None),
+            (json!([{"command": "undo"}]), json!({"command": "redo"}), json!({"command": "undo", "version": "1"}), "invalid_field", Some("version")),
+        ];
+
+        let mut covered: Vec<String> = Vec::new();
+        for (first, ok, failing, code, field) in cases {
+            let (mut budget, mut history) = household();
+            for command in first.as_array().unwrap() {
+                send(&mut budget, &mut history, command.clone()).unwrap();
+            }
+            let before = serde_json::to_string(&budget).unwrap();
+            let error = send(&mut budget, &mut history, failing.clone()).unwrap_err();
+            assert_eq!((error.code.as_str(), error.field.as_deref()), (code, field), "{}", failing);
+            assert_eq!(serde_json::to_string(&budget).unwrap(), before);
+            if let Err(error) = send(&mut budget, &mut history, ok.clone()) {
+                panic!("{} failed: {:?}", ok, error);
+            }
+            covered.push(ok["command"].as_str().unwrap().to_string());
+        }
+        for (name, _) in COMMAND_FIELDS {
+            assert!(covered.iter().any(|command| command == name), "{} isn't covered", name);
+        }
+    }
+
+    //balance(): an account's balance, read off the budget's json
+    fn balance(budget: &Budget, account: &str) -> i64 {
+        serde_json::to_value(budget).unwrap()["accounts"][account]["balance"].as_i64().unwrap()
+    }
+
+    #[test]
+    fn batches_commit_only_when_every_command_succeeds() {
+        let (mut budget, mut history) = household();
+        let body = json!([
+            {"command": "pay", "label": "rent", "amount": 20},
+            {"command": "transfer", "to": "wallet", "amount": 30},
+        ]);
+        let report = execute_batch(&body.to_string(), &mut budget, &mut history).unwrap();
+        assert!(report.committed);
+        assert!(matches!(report.results.as_slice(), [BatchResult::Ok { .. }, BatchResult::Ok { .. }]));
+        assert_eq!((balance(&budget, "checking"), balance(&budget, "wallet")), (5000, 5000));
+
+        //the second command fails, so the first is taken back and the third never runs
+        let before = serde_json::to_string(&budget).unwrap();
+        let (periods, entries) = (history.periods().len(), history.unsaved().len());
+        let body = json!([
+            {"command": "getpaid"},
+            {"command": "pay", "label": "gym", "amount": 5},
+            {"command": "pay", "label": "food", "amount": 5},
+        ]);
+        let report = execute_batch(&body.to_string(), &mut budget, &mut history).unwrap();
+        assert!(!report.committed);
+        let [BatchResult::Ok { .. }, BatchResult::Failed { error }, BatchResult::Skipped] = report.results.as_slice() else {
+            panic!("unexpected results {:?}", report.results);
+        };
+        assert_eq!(error.code, "expense_not_found");
+        assert_eq!(serde_json::to_string(&budget).unwrap(), before);
+        assert_eq!((history.periods().len(), history.unsaved().len()), (periods, entries));
+
+        //a command that doesn't parse fails the batch the same way
+        let body = json!([{"command": "pay", "label": "rent", "amount": 5}, {"command": "fly"}]);
+        let report = execute_batch(&body.to_string(), &mut budget, &mut history).unwrap();
+        assert!(!report.committed);
+        assert_eq!(serde_json::to_string(&budget).unwrap(), before);
+    }
+
+    #[test]
+    fn turns_down_batches_that_arent_lists_of_commands() {
+        let (mut budget, mut history) = household();
+        let code = |body: String, budget: &mut Budget, history: &mut History| execute_batch(&body, budget, history).unwrap_err().code;
+        assert_eq!(code(json!({"command": "getpaid"}).to_string(), &mut budget, &mut history), "invalid_json");
+        assert_eq!(code(String::from("[{"), &mut budget, &mut history), "invalid_json");
+        assert_eq!(code(json!([]).to_string(), &mut budget, &mut history), "empty_batch");
+        let too_many = vec![json!({"command": "getpaid"}); MAX_BATCH_COMMANDS + 1];
+        assert_eq!(code(json!(too_many).to_string(), &mut budget, &mut history), "batch_too_large");
+    }
+
+    #[test]
+    fn failed_commands_change_nothing() {
+        let mut budget = Budget::new(String::from("sam"));
+        let mut history = History::default();
+        send(&mut budget, &mut history, json!({"command": "setincome", "amount": 100})).unwrap();
+        let before = serde_json::to_string(&budget).unwrap();
+
+        //getting paid into an account that doesn't exist fails after the pay period would have closed
+        let error = send(&mut budget, &mut history, json!({"command": "getpaid", "account": "nowhere"})).unwrap_err();
+        assert_eq!((error.code.as_str(), error.field.as_deref()), ("account_not_found", Some("account")));
+        assert_eq!(serde_json::to_string(&budget).unwrap(), before);
+        assert_eq!(history.periods().len(), 0);
+        assert!(budget.take_journal().is_empty());
+
+        send(&mut budget, &mut history, json!({"command": "getpaid"})).unwrap();
+        assert_eq!(history.periods().len(), 1);
+        assert_eq!(budget.primary_balance(), 10000);
+    }
+
+    #[test]
+    fn totals_past_the_largest_amount_are_turned_down() {
+        let mut budget = Budget::new(String::from("sam"));
+        let mut history = History::default();
+        send(&mut budget, &mut history, json!({"command": "setincome", "amount": "1000000000000"})).unwrap();
+        send(&mut budget, &mut history, json!({"command": "getpaid"})).unwrap();
+        let before = serde_json::to_string(&budget).unwrap();
+
+        let error = send(&mut budget, &mut history, json!({"command": "getpaid"})).unwrap_err();
+        assert_eq!((error.code.as_str(), error.field.as_deref()), ("amount_too_large", Some("account")));
+        let error = send(&mut budget, &mut history, json!({"command": "raiseincome", "amount": 1})).unwrap_err();
+        assert_eq!((error.code.as_str(), error.field.as_deref()), ("amount_too_large", Some("amount")));
+        assert_eq!(serde_json::to_string(&budget).unwrap(), before);
+
+        //paying out of a balance that's already as low as it can go
+        send(&mut budget, &mut history, json!({"command": "new", "label": "rent", "amount": "1000000000000"})).unwrap();
+        send(&mut budget, &mut history, json!({"command": "pay", "label": "rent", "account": "savings"})).unwrap();
+        let error = send(&mut budget, &mut history, json!({"command": "pay", "label": "rent", "account": "savings"})).unwrap_err();
+        assert_eq!((error.code.as_str(), error.field.as_deref()), ("amount_too_large", Some("amount")));
+    }
+
+    #[test]
+    fn budget_errors_name_their_field() {
+        let error = budget_error(String::from("insufficient_balance"), &[]);
+        assert_eq!((error.code.as_str(), error.field.as_deref()), ("insufficient_balance", Some("amount")));
+        let error = budget_error(String::from("account_not_found"), &[("account_not_found", "to")]);
+        assert_eq!(error.field.as_deref(), Some("to"));
+        let error = budget_error(String::from("nothing_to_undo"), &[]);
+        assert_eq!((error.code.as_str(), error.field), ("nothing_to_undo", None));
+    }
+}
@@ -0,0 +1,372 @@
+use std::env;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::{self, Deserialize};
+
+//the config file read when no other is given, if it exists
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+//the config file copied to DEFAULT_CONFIG_FILE the first time the server is run without one,
+//with a random secret in place of this placeholder, so a fresh checkout starts without any setup
+const EXAMPLE_CONFIG_FILE: &str = "config.example.toml";
+const PLACEHOLDER_SECRET: &str = "REPLACE_ME";
+
+//the environment variable (and command line flag, --config) naming a config file to read instead
+const CONFIG_FILE_VAR: &str = "CONFIG_FILE";
+
+//every setting, with what it's for. each one can be set in the config file by this name,
+//in the environment by the same name in capitals, or on the command line as --name-with-dashes
+pub const SETTINGS: [(&str, &str); 21] = [
+    ("server_host", "the address to listen on (default 127.0.0.1)"),
+    ("server_port", "the port to listen on (default 3000)"),
+    ("secret", "the secret login tokens are signed with (required)"),
+    ("token_minutes", "how long a login token lasts, in minutes (default 60)"),
+    ("hash_cost", "the bcrypt cost passwords are hashed with, 4 to 31 (default 7)"),
+    ("db_path", "the folder the database is kept in (default db)"),
+    ("client_path", "the folder the client's files are served from (default ../client/static)"),
+    ("do_caching", "whether the client's files are cached in memory (default false)"),
+    ("worker_threads", "the number of worker threads reading connections (default 8)"),
+    ("max_connections", "the number of connections that can be open at once (default 256)"),
+    ("max_header_bytes", "the biggest request head accepted, in bytes (default 4096)"),
+    ("max_body_bytes", "the biggest request body accepted, in bytes (default 4MB)"),
+    ("read_timeout", "how long a connection can stall sending a request, in seconds (default 10)"),
+    ("write_timeout", "how long a connection can stall accepting a response, in seconds (default 10)"),
+    ("keep_alive_timeout", "how long a kept-alive connection can sit idle, in seconds (default 5)"),
+    ("session_timeout", "how long a logged-in user's session lasts without any requests, in seconds (default 1800)"),
+    ("timeout_interval", "how often idle sessions are checked for, in seconds (default 60)"),
+    ("schedule_interval", "how often scheduled incomes and expenses are posted, in seconds (default 900)"),
+    ("tls_cert", "a PEM certificate chain to serve HTTPS with, reloaded on SIGHUP"),
+    ("tls_key", "the PEM private key for tls_cert"),
+    ("http_redirect_port", "a port to redirect plain HTTP to HTTPS on, when HTTPS is served"),
+];
+
+//the settings the server was started with, set once by main() before anything else runs
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+//Config: every setting the server can be deployed with
+//read from the config file first, then the environment (and .env), then the command line, each overriding the last
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server_host: String,
+    pub server_port: u16,
+    pub secret: String,
+    pub token_minutes: u32,
+    pub hash_cost: u32,
+    pub db_path: PathBuf,
+    pub client_path: PathBuf,
+    pub do_caching: bool,
+    pub worker_threads: usize,
+    pub max_connections: usize,
+    pub max_header_bytes: usize,
+    pub max_body_bytes: usize,
+    pub read_timeout: u64,
+    pub write_timeout: u64,
+    pub keep_alive_timeout: u64,
+    pub session_timeout: u64,
+    pub timeout_interval: u64,
+    pub schedule_interval: u64,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub http_redirect_port: Option<u16>,
+}
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            server_host: String::from("127.0.0.1"),
+            server_port: 3000,
+            secret: String::new(),
+            token_minutes: 60,
+            hash_cost: 7,
+            db_path: PathBuf::from("db"),
+            client_path: PathBuf::from("../client/static"),
+            do_caching: false,
+            worker_threads: 8,
+            max_connections: 256,
+            max_header_bytes: 4096,
+            max_body_bytes: 4 * 1024 * 1024,
+            read_timeout: 10,
+            write_timeout: 10,
+            keep_alive_timeout: 5,
+            session_timeout: 30 * 60,
+            timeout_interval: 60,
+            schedule_interval: 15 * 60,
+            tls_cert: None,
+            tls_key: None,
+            http_redirect_port: None,
+        }
+    }
+}
+impl Config {
+    //load(): reads the config file, the environment and the given command line arguments (without the program name),
+    //returning every problem with them at once
+    pub fn load(args: impl IntoIterator<Item = String>) -> Result<Config, String> {
+        Config::read(args, |key| env::var(key).ok())
+    }
+
+    //read(): load(), with the environment looked up through the given function
+    fn read(args: impl IntoIterator<Item = String>, env: impl Fn(&str) -> Option<String>) -> Result<Config, String> {
+        let flags = parse_flags(args)?;
+
+        //the config file can be picked on the command line or in the environment, and doesn't have to exist unless it's picked
+        let chosen = flags
+            .iter()
+            .rev()
+            .find(|(key, _)| key == "config")
+            .map(|(_, path)| path.clone())
+            .or_else(|| env(CONFIG_FILE_VAR));
+        let mut config = match &chosen {
+            Some(path) => Config::from_file(Path::new(path))?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None if Path::new(EXAMPLE_CONFIG_FILE).exists() => {
+                generate_config()?;
+                Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+
+        let mut errors = Vec::new();
+        for (key, _) in SETTINGS {
+            if let Some(value) = env(&key.to_ascii_uppercase()) {
+                if let Err(why) = config.set(key, &value) {
+                    errors.push(format!("{} (from the environment)", why));
+                }
+            }
+        }
+        for (key, value) in flags.iter().filter(|(key, _)| key != "config") {
+            if let Err(why) = config.set(key, value) {
+                errors.push(format!("{} (from the command line)", why));
+            }
+        }
+        errors.extend(config.validate());
+
+        if !errors.is_empty() {
+            return Err(format!("invalid configuration:\n\t{}", errors.join("\n\t")));
+        }
+        Ok(config)
+    }
+
+    //from_file(): reads settings from a TOML file, anything left out keeping its default
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|why| format!("failed to read {}: {}", path.display(), why))?;
+        toml::from_str(&text).map_err(|why| format!("invalid config file {}: {}", path.display(), why))
+    }
+
+    //set(): sets one setting from text, as given in the environment or on the command line
+    //an empty value unsets the optional ones
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let optional = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        match key {
+            "server_host" => self.server_host = value.to_string(),
+            "server_port" => self.server_port = parse(key, value)?,
+            "secret" => self.secret = value.to_string(),
+            "token_minutes" => self.token_minutes = parse(key, value)?,
+            "hash_cost" => self.hash_cost = parse(key, value)?,
+            "db_path" => self.db_path = PathBuf::from(value),
+            "client_path" => self.client_path = PathBuf::from(value),
+            "do_caching" => self.do_caching = parse(key, value)?,
+            "worker_threads" => self.worker_threads = parse(key, value)?,
+            "max_connections" => self.max_connections = parse(key, value)?,
+            "max_header_bytes" => self.max_header_bytes = parse(key, value)?,
+            "max_body_bytes" => self.max_body_bytes = parse(key, value)?,
+            "read_timeout" => self.read_timeout = parse(key, value)?,
+            "write_timeout" => self.write_timeout = parse(key, value)?,
+            "keep_alive_timeout" => self.keep_alive_timeout = parse(key, value)?,
+            "session_timeout" => self.session_timeout = parse(key, value)?,
+            "timeout_interval" => self.timeout_interval = parse(key, value)?,
+            "schedule_interval" => self.schedule_interval = parse(key, value)?,
+            "tls_cert" => self.tls_cert = optional(value).map(PathBuf::from),
+            "tls_key" => self.tls_key = optional(value).map(PathBuf::from),
+            "http_redirect_port" => self.http_redirect_port = optional(value).map(|port| parse(key, &port)).transpose()?,
+            _ => return Err(format!("unknown setting {}", key)),
+        }
+        Ok(())
+    }
+
+    //validate(): everything wrong with the settings, once they've all been read
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.server_host.parse::<IpAddr>().is_err() {
+            errors.push(format!("server_host has to be an IP address, not {:?}", self.server_host));
+        }
+        if self.secret.is_empty() {
+            errors.push(String::from("secret is required"));
+        }
+        if !(4..=31).contains(&self.hash_cost) {
+            errors.push(format!("hash_cost has to be from 4 to 31, not {}", self.hash_cost));
+        }
+        let positive = [
+            ("token_minutes", self.token_minutes as u64),
+            ("worker_threads", self.worker_threads as u64),
+            ("max_connections", self.max_connections as u64),
+            ("max_header_bytes", self.max_header_bytes as u64),
+            ("read_timeout", self.read_timeout),
+            ("write_timeout", self.write_timeout),
+            ("keep_alive_timeout", self.keep_alive_timeout),
+            ("session_timeout", self.session_timeout),
+            ("timeout_interval", self.timeout_interval),
+            ("schedule_interval", self.schedule_interval),
+        ];
+        for (key, value) in positive {
+            if value == 0 {
+                errors.push(format!("{} has to be more than 0", key));
+            }
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            errors.push(String::from("tls_cert and tls_key have to be set together"));
+        }
+        match self.http_redirect_port {
+            Some(_) if self.tls_cert.is_none() => errors.push(String::from("http_redirect_port needs tls_cert and tls_key")),
+            Some(port) if port == self.server_port => errors.push(String::from("http_redirect_port has to differ from server_port")),
+            _ => {}
+        }
+        errors
+    }
+
+    //address(): where the server listens
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.server_host, self.server_port)
+    }
+
+    //redirect_address(): where plain HTTP is redirected to HTTPS from, if anywhere
+    pub fn redirect_address(&self) -> Option<String> {
+        self.http_redirect_port.map(|port| format!("{}:{}", self.server_host, port))
+    }
+}
+
+//parse(): reads a setting's value, saying which setting it was if it can't be
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_err| format!("invalid value for {}: {:?}", key, value))
+}
+
+//parse_flags(): reads "--name value" and "--name=value" pairs off the command line, names turned back into setting names
+fn parse_flags(args: impl IntoIterator<Item = String>) -> Result<Vec<(String, String)>, String> {
+    let mut flags = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(format!("unexpected argument {:?}, settings are given as --name value\n\n{}", arg, usage()));
+        };
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name, value.to_string()),
+            None => match args.next() {
+                Some(value) => (flag, value),
+                None => return Err(format!("no value given for --{}", flag)),
+            },
+        };
+        flags.push((name.replace('-', "_"), value));
+    }
+    Ok(flags)
+}
+
+//generate_config(): writes DEFAULT_CONFIG_FILE from EXAMPLE_CONFIG_FILE, with a freshly made random secret
+fn generate_config() -> Result<(), String> {
+    let example = fs::read_to_string(EXAMPLE_CONFIG_FILE)
+        .map_err(|why| format!("failed to read {}: {}", EXAMPLE_CONFIG_FILE, why))?;
+    let secret = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    fs::write(DEFAULT_CONFIG_FILE, example.replace(PLACEHOLDER_SECRET, &secret))
+        .map_err(|why| format!("failed to write {}: {}", DEFAULT_CONFIG_FILE, why))?;
+    eprintln!("{} generated from {}, with a random secret", DEFAULT_CONFIG_FILE, EXAMPLE_CONFIG_FILE);
+    Ok(())
+}
+
+//usage(): what can be given on the command line, printed for --help
+pub fn usage() -> String {
+    let mut usage = format!(
+        "usage: server [--config <file>] [--<setting> <value>]...\n\n\
+        settings are read from {} (or the file given by --config or {}), then the environment and .env, then the command line:\n",
+        DEFAULT_CONFIG_FILE, CONFIG_FILE_VAR
+    );
+    for (key, description) in SETTINGS {
+        usage.push_str(&format!("  --{:<20} {}\n", key.replace('_', "-"), description));
+    }
+    usage
+}
+
+//init(): makes the loaded settings available everywhere, through get()
+pub fn init(config: Config) {
+    CONFIG.set(config).expect("config should only be set once");
+}
+
+//get(): the settings the server was started with
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("config should be loaded before the server starts")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //file(): a config file with the given text, in its own spot in the temp folder
+    fn file(text: &str) -> String {
+        let path = env::temp_dir().join(format!("config_{}.toml", uuid::Uuid::new_v4().simple()));
+        fs::write(&path, text).unwrap();
+        path.display().to_string()
+    }
+
+    //read(): loads the given config file and command line, with the given environment instead of the real one
+    fn read(path: &str, args: &[&str], vars: &[(&str, &str)]) -> Result<Config, String> {
+        let args: Vec<String> = ["--config", path].iter().chain(args).map(|arg| arg.to_string()).collect();
+        Config::read(args, |key| vars.iter().find(|(name, _)| *name == key).map(|(_, value)| value.to_string()))
+    }
+
+    #[test]
+    fn command_line_beats_environment_beats_file() {
+        let path = file("secret = \"from_file\"\nserver_port = 1000\ntoken_minutes = 5\nhash_cost = 8\n");
+        let vars = [("SERVER_PORT", "2000"), ("TOKEN_MINUTES", "6")];
+        let config = read(&path, &["--server-port", "3001"], &vars).unwrap();
+        assert_eq!(
+            (config.secret.as_str(), config.server_port, config.token_minutes, config.hash_cost),
+            ("from_file", 3001, 6, 8)
+        );
+        //anything not set anywhere keeps its default
+        assert_eq!((config.worker_threads, config.do_caching), (8, false));
+
+        let config = read(&path, &["--max-connections=4", "--do-caching", "true"], &[]).unwrap();
+        assert_eq!((config.server_port, config.max_connections, config.do_caching), (1000, 4, true));
+    }
+
+    #[test]
+    fn reports_every_invalid_setting_at_once() {
+        let path = file("server_host = \"localhost\"\nhash_cost = 3\ntls_cert = \"cert.pem\"\n");
+        let why = read(&path, &["--worker-threads", "0", "--server-port", "http"], &[("HASH_COST", "lots")]).unwrap_err();
+        for error in [
+            "invalid value for hash_cost: \"lots\" (from the environment)",
+            "invalid value for server_port: \"http\" (from the command line)",
+            "server_host has to be an IP address, not \"localhost\"",
+            "secret is required",
+            "hash_cost has to be from 4 to 31, not 3",
+            "worker_threads has to be more than 0",
+            "tls_cert and tls_key have to be set together",
+        ] {
+            assert!(why.contains(error), "{:?} missing from {:?}", error, why);
+        }
+    }
+
+    #[test]
+    fn redirect_port_needs_https_on_another_port() {
+        let path = file("secret = \"s\"\nserver_port = 3000\n");
+        let why = read(&path, &["--http-redirect-port", "8080"], &[]).unwrap_err();
+        assert!(why.contains("http_redirect_port needs tls_cert and tls_key"));
+
+        let https = |port| ["--tls-cert", "cert.pem", "--tls-key", "key.pem", "--http-redirect-port", port];
+        let why = read(&path, &https("3000"), &[]).unwrap_err();
+        assert!(why.contains("http_redirect_port has to differ from server_port"));
+        assert!(read(&path, &https("8080"), &[]).is_ok());
+        //an empty value unsets it again
+        assert!(read(&path, &["--http-redirect-port", ""], &[("HTTP_REDIRECT_PORT", "8080")]).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_settings_and_missing_files() {
+        let path = file("secret = \"s\"\nport = 3000\n");
+        assert!(read(&path, &[], &[]).unwrap_err().starts_with("invalid config file"));
+        let path = file("secret = \"s\"\n");
+        assert!(read(&path, &["--port", "3000"], &[]).unwrap_err().contains("unknown setting port (from the command line)"));
+        assert!(read(&path, &["--server-port"], &[]).unwrap_err().contains("no value given for --server-port"));
+        assert!(read("no_such_config.toml", &[], &[]).unwrap_err().starts_with("failed to read no_such_config.toml"));
+    }
+}
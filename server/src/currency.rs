@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+
+use serde::{self, Deserialize, Serialize};
+
+//the currency budgets start out in
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+//CurrencyInfo: how a currency is written
+struct CurrencyInfo {
+    code: &'static str,
+    symbol: &'static str,
+    //digits written after the decimal point. amounts are always stored in hundredths (cents for USD),
+    //like every other amount in a budget, so currencies without minor units are rounded when written
+    decimals: u32,
+}
+
+//currencies with their own symbol, any other three letter code is written with the code and two decimals
+const CURRENCIES: &[CurrencyInfo] = &[
+    CurrencyInfo { code: "USD", symbol: "$", decimals: 2 },
+    CurrencyInfo { code: "EUR", symbol: "€", decimals: 2 },
+    CurrencyInfo { code: "GBP", symbol: "£", decimals: 2 },
+    CurrencyInfo { code: "CAD", symbol: "CA$", decimals: 2 },
+    CurrencyInfo { code: "AUD", symbol: "A$", decimals: 2 },
+    CurrencyInfo { code: "MXN", symbol: "MX$", decimals: 2 },
+    CurrencyInfo { code: "CHF", symbol: "CHF", decimals: 2 },
+    CurrencyInfo { code: "INR", symbol: "₹", decimals: 2 },
+    CurrencyInfo { code: "JPY", symbol: "¥", decimals: 0 },
+    CurrencyInfo { code: "KRW", symbol: "₩", decimals: 0 },
+];
+
+//symbol_of(): the symbol a currency is written with
+fn symbol_of(code: &str) -> &str {
+    CURRENCIES
+        .iter()
+        .find(|info| info.code == code)
+        .map_or(code, |info| info.symbol)
+}
+
+//decimals_of(): how many digits a currency is written with after the decimal point
+fn decimals_of(code: &str) -> u32 {
+    CURRENCIES
+        .iter()
+        .find(|info| info.code == code)
+        .map_or(2, |info| info.decimals)
+}
+
+//parse_code(): reads a three letter currency code (eur, USD)
+pub fn parse_code(code: &str) -> Result<String, String> {
+    let code = code.trim().to_ascii_uppercase();
+    if code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code)
+    } else {
+        Err(String::from("invalid_currency"))
+    }
+}
+
+//Locale: how numbers are written, which the user picks for their budget
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    //$1,234.56
+    #[default]
+    #[serde(rename = "en-US", alias = "en-us")]
+    EnUs,
+    //£1,234.56
+    #[serde(rename = "en-GB", alias = "en-gb")]
+    EnGb,
+    //1.234,56 €
+    #[serde(rename = "de-DE", alias = "de-de")]
+    DeDe,
+    //1 234,56 €
+    #[serde(rename = "fr-FR", alias = "fr-fr")]
+    FrFr,
+    //1'234.56 CHF
+    #[serde(rename = "de-CH", alias = "de-ch")]
+    DeCh,
+}
+impl Locale {
+    //separators(): the thousands separator and the decimal point
+    fn separators(self) -> (&'static str, &'static str) {
+        match self {
+            Locale::EnUs | Locale::EnGb => (",", "."),
+            Locale::DeDe => (".", ","),
+            Locale::FrFr => ("\u{202f}", ","),
+            Locale::DeCh => ("'", "."),
+        }
+    }
+
+    //symbol_after(): whether the currency symbol goes after the number
+    fn symbol_after(self) -> bool {
+        matches!(self, Locale::DeDe | Locale::FrFr | Locale::DeCh)
+    }
+}
+
+//format_money(): formats an amount in hundredths of a currency (cents) the way the given locale writes it
+pub fn format_money(amount: i64, currency: &str, locale: Locale) -> String {
+    let decimals = decimals_of(currency);
+    //round away the hundredths a currency doesn't write, half away from zero
+    let drop = 10u64.pow(2 - decimals);
+    let units = (amount.unsigned_abs() + drop / 2) / drop;
+    let scale = 10u64.pow(decimals);
+    let whole = (units / scale).to_string();
+    let fraction = units % scale;
+    let (thousands, point) = locale.separators();
+
+    //group the whole part into threes from the right
+    let mut number = String::new();
+    for (index, digit) in whole.chars().enumerate() {
+        if index > 0 && (whole.len() - index).is_multiple_of(3) {
+            number.push_str(thousands);
+        }
+        number.push(digit);
+    }
+    if decimals > 0 {
+        number.push_str(point);
+        number.push_str(&format!("{:0width$}", fraction, width = decimals as usize));
+    }
+
+    let sign = if amount < 0 { "-" } else { "" };
+    let symbol = symbol_of(currency);
+    if locale.symbol_after() {
+        format!("{}{} {}", sign, number, symbol)
+    } else {
+        format!("{}{}{}", sign, symbol, number)
+    }
+}
+
+//CurrencySettings: the user's base currency, how they write numbers, and their exchange rates
+//every total, expense, goal and envelope is in the base currency. accounts can hold other currencies,
+//and every account's currency always has a rate, so converting between them can't fail
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CurrencySettings {
+    pub base: String,
+    #[serde(default)]
+    pub locale: Locale,
+    //how much of the base currency one of each other currency is worth (EUR: 1.08 with a USD base)
+    #[serde(default)]
+    pub rates: BTreeMap<String, f64>,
+}
+impl Default for CurrencySettings {
+    fn default() -> CurrencySettings {
+        CurrencySettings {
+            base: DEFAULT_CURRENCY.to_string(),
+            locale: Locale::default(),
+            rates: BTreeMap::new(),
+        }
+    }
+}
+impl CurrencySettings {
+    //rate(): how much of the base currency one of the given currency is worth, if it's known
+    pub fn rate(&self, currency: &str) -> Option<f64> {
+        if currency == self.base {
+            Some(1.0)
+        } else {
+            self.rates.get(currency).copied()
+        }
+    }
+
+    //convert(): converts an amount in hundredths of one currency into hundredths of another, rounding to the nearest
+    pub fn convert(&self, amount: i64, from: &str, to: &str) -> Result<i64, String> {
+        if from == to {
+            return Ok(amount);
+        }
+        let (Some(from_rate), Some(to_rate)) = (self.rate(from), self.rate(to)) else {
+            return Err(String::from("missing_exchange_rate"));
+        };
+        Ok((amount as f64 * from_rate / to_rate).round() as i64)
+    }
+
+    //set_rate(): records how much of the base currency one of the given currency is worth
+    pub fn set_rate(&mut self, currency: &str, rate: f64) -> Result<(), String> {
+        let currency = parse_code(currency)?;
+        if currency == self.base {
+            return Err(String::from("base_currency_rate"));
+        }
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(String::from("invalid_exchange_rate"));
+        }
+        self.rates.insert(currency, rate);
+        Ok(())
+    }
+
+    //rebase(): changes the base currency, restating every rate against the new one
+    //needs a rate for the new base, unless there are no rates at all yet
+    pub fn rebase(&mut self, currency: &str) -> Result<(), String> {
+        let currency = parse_code(currency)?;
+        if currency == self.base {
+            return Ok(());
+        }
+        if !self.rates.is_empty() {
+            let Some(new_rate) = self.rates.remove(&currency) else {
+                return Err(String::from("missing_exchange_rate"));
+            };
+            for rate in self.rates.values_mut() {
+                *rate /= new_rate;
+            }
+            self.rates.insert(self.base.clone(), 1.0 / new_rate);
+        }
+        self.base = currency;
+        Ok(())
+    }
+}
+
+//parse_rates(): reads an exchange rate file, one "code,rate" line per currency
+//blank lines, # comments and a "currency,rate" header are skipped
+pub fn parse_rates(data: &str) -> Result<Vec<(String, f64)>, String> {
+    let mut rates = Vec::new();
+    for line in data.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.eq_ignore_ascii_case("currency,rate") {
+            continue;
+        }
+        let Some((code, rate)) = line.split_once([',', ';', '\t']) else {
+            return Err(String::from("invalid_rate_line"));
+        };
+        let code = parse_code(code)?;
+        let rate = rate
+            .trim()
+            .parse::<f64>()
+            .map_err(|_err| String::from("invalid_exchange_rate"))?;
+        rates.push((code, rate));
+    }
+    if rates.is_empty() {
+        return Err(String::from("no_exchange_rates"));
+    }
+    Ok(rates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::AccountKind;
+    use crate::budget::Budget;
+
+    //euros(): usd settings where a euro is worth 1.25 dollars
+    fn euros() -> CurrencySettings {
+        let mut settings = CurrencySettings::default();
+        settings.set_rate("eur", 1.25).unwrap();
+        settings
+    }
+
+    #[test]
+    fn reads_codes_and_rate_files() {
+        assert_eq!(parse_code(" eur "), Ok(String::from("EUR")));
+        assert_eq!(parse_code("euro"), Err(String::from("invalid_currency")));
+        assert_eq!(parse_code("e1r"), Err(String::from("invalid_currency")));
+
+        let rates = parse_rates("currency,rate\n# from the bank\n\neur,1.08\nGBP;1.27\n").unwrap();
+        assert_eq!(rates, [(String::from("EUR"), 1.08), (String::from("GBP"), 1.27)]);
+        assert_eq!(parse_rates("eur 1.08"), Err(String::from("invalid_rate_line")));
+        assert_eq!(parse_rates("eur,lots"), Err(String::from("invalid_exchange_rate")));
+        assert_eq!(parse_rates("# nothing yet\n"), Err(String::from("no_exchange_rates")));
+    }
+
+    #[test]
+    fn converts_and_rebases_through_the_rates() {
+        let mut settings = euros();
+        assert_eq!(settings.convert(1000, "EUR", "USD"), Ok(1250));
+        assert_eq!(settings.convert(1250, "USD", "EUR"), Ok(1000));
+        assert_eq!(settings.convert(1000, "GBP", "USD"), Err(String::from("missing_exchange_rate")));
+        assert_eq!(settings.set_rate("usd", 2.0), Err(String::from("base_currency_rate")));
+        assert_eq!(settings.set_rate("gbp", 0.0), Err(String::from("invalid_exchange_rate")));
+        assert_eq!(settings.set_rate("gbp", f64::NAN), Err(String::from("invalid_exchange_rate")));
+
+        assert_eq!(settings.rebase("gbp"), Err(String::from("missing_exchange_rate")));
+        settings.rebase("eur").unwrap();
+        assert_eq!((settings.base.as_str(), settings.rate("USD")), ("EUR", Some(0.8)));
+        assert_eq!(settings.convert(1000, "EUR", "USD"), Ok(1250));
+    }
+
+    #[test]
+    fn writes_money_the_way_each_locale_does() {
+        assert_eq!(format_money(123456, "USD", Locale::EnUs), "$1,234.56");
+        assert_eq!(format_money(-5, "GBP", Locale::EnGb), "-£0.05");
+        assert_eq!(format_money(123456, "EUR", Locale::DeDe), "1.234,56 €");
+        assert_eq!(format_money(123456, "EUR", Locale::FrFr), "1\u{202f}234,56 €");
+        assert_eq!(format_money(12345678, "CHF", Locale::DeCh), "123'456.78 CHF");
+        assert_eq!(format_money(123450, "JPY", Locale::EnUs), "¥1,235");
+        assert_eq!(format_money(100, "NOK", Locale::EnUs), "NOK1.00");
+    }
+
+    #[test]
+    fn keeps_the_base_while_foreign_accounts_are_open() {
+        let mut budget = Budget::new(String::from("sam"));
+        assert_eq!(
+            budget.add_account("travel", AccountKind::Cash, 1000, Some("eur")),
+            Err(String::from("missing_exchange_rate"))
+        );
+        budget.set_rate("eur", 1.25).unwrap();
+        budget.add_account("travel", AccountKind::Cash, 1000, Some("eur")).unwrap();
+        assert_eq!(budget.convert_into(Some("travel"), 1250, "usd"), Ok(1000));
+        assert_eq!(budget.convert_into(Some("travel"), 1250, "dollars"), Err(String::from("invalid_currency")));
+
+        assert_eq!(budget.set_currency(Some("eur"), None), Err(String::from("foreign_accounts_open")));
+        budget.set_currency(None, Some(Locale::DeDe)).unwrap();
+        assert_eq!(budget.money_in("travel", 1000), "10,00 €");
+        assert_eq!(budget.import_rates("eur,1.10\ngbp,-1"), Err(String::from("invalid_exchange_rate")));
+        assert_eq!(budget.convert_into(Some("travel"), 1250, "usd"), Ok(1000));
+    }
+}
@@ -1,23 +1,26 @@
+use std::path::Path;
 use std::sync::{LazyLock, RwLock};
 
 use r2d2_sqlite;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::config;
+
 //this static variable IS THE DATABASE
 //im statically initializing a RwLock-ed reference
 //to a pool of database connections, to the file
 //... YES, HAVING ONE STATIC GLOBAL DATABASE REFERENCE IS BAD. TODO: MOVE DATABASE INTO SERVER INSTANCE
 pub static USER_DB: LazyLock<RwLock<Database>> =
-    LazyLock::new(|| RwLock::new(Database::new("db")));
+    LazyLock::new(|| RwLock::new(Database::new(&config::get().db_path, "db")));
 
 pub struct Database {
     connection_pool: r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>,
 }
 impl Database {
-    pub fn new(name: &str) -> Database {
-        let _ = std::fs::create_dir("db"); //if err, nothing changes
-        let manager = r2d2_sqlite::SqliteConnectionManager::file(format!("db/{}.db", name));
+    pub fn new(folder: &Path, name: &str) -> Database {
+        let _ = std::fs::create_dir_all(folder); //if err, nothing changes
+        let manager = r2d2_sqlite::SqliteConnectionManager::file(folder.join(format!("{}.db", name)));
         let pool = r2d2::Pool::new(manager)
             .expect(&format!("error creating r2d2 sqlite pool for {}", name)[..]);
 
@@ -41,6 +44,21 @@ impl Database {
     }
 }
 
+//for_tests(): points the database at a fresh file in the temp folder, with every table made, the first time a test asks
+//every test shares it, so they keep apart by using their own random ids
+#[cfg(test)]
+pub fn for_tests() {
+    static READY: std::sync::Once = std::sync::Once::new();
+    READY.call_once(|| {
+        let folder = std::env::temp_dir().join(format!("budget_tests_{}", Uuid::new_v4().simple()));
+        config::init(config::Config {
+            db_path: folder,
+            ..config::Config::default()
+        });
+        crate::server::create_tables();
+    });
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserAuthRow {
     pub uuid: uuid::Uuid,
@@ -1,6 +1,10 @@
 
 use crate::budget::Budget;
 use crate::db;
+use crate::history::{History, HistoryPage, LedgerEntry, HISTORY_PAGE_SIZE};
+use crate::members::{Invite, Member, Role};
+use crate::shares::{Scope, Share};
+use crate::undo::UndoStacks;
 use uuid::Uuid;
 
 pub fn save_user_data(uuid: Uuid, budget: &Budget) -> Result<String, String>{
@@ -8,7 +12,7 @@ pub fn save_user_data(uuid: Uuid, budget: &Budget) -> Result<String, String>{
 
     let conn = db::USER_DB.read().unwrap().connection();
 
-    let mut stmt = conn.prepare("UPDATE users SET jsondata = ? WHERE uuid = ?").unwrap();
+    let mut stmt = conn.prepare("UPDATE budgets SET jsondata = ? WHERE uuid = ?").unwrap();
 
     let result = stmt.query_row(rusqlite::params![budget, uuid], |row| {
         Ok(row.get("jsondata")?)
@@ -19,4 +23,492 @@ pub fn save_user_data(uuid: Uuid, budget: &Budget) -> Result<String, String>{
         Err(_) => Err(String::from("not found"))
     }
     
-}
\ No newline at end of file
+}
+
+//save_user_history(): appends the history's unsaved entries to the ledger table, and saves whatever else changed:
+//the archived periods into jsonhistory and each member's undo stacks into the undo table
+pub fn save_user_history(uuid: Uuid, history: &mut History) -> Result<(), String> {
+    let mut conn = db::USER_DB.read().unwrap().connection();
+    let tx = conn.transaction().map_err(|why| why.to_string())?;
+
+    append_ledger(&tx, uuid, history.saved_len(), history.unsaved())?;
+
+    if history.periods_changed() {
+        let jsonhistory = serde_json::to_string(history).unwrap();
+        match tx.execute("UPDATE budgets SET jsonhistory = ? WHERE uuid = ?", rusqlite::params![jsonhistory, uuid]) {
+            Ok(1) => {}
+            Ok(_) => return Err(String::from("not found")),
+            Err(why) => return Err(why.to_string()),
+        }
+    }
+
+    for (member, stacks) in history.changed_undo() {
+        let result = match stacks {
+            Some(stacks) => tx.execute(
+                "INSERT OR REPLACE INTO undo(budget, member, jsonstacks) VALUES (?, ?, ?)",
+                rusqlite::params![uuid, member, serde_json::to_string(stacks).unwrap()],
+            ),
+            None => tx.execute("DELETE FROM undo WHERE budget = ? AND member = ?", rusqlite::params![uuid, member]),
+        };
+        result.map_err(|why| why.to_string())?;
+    }
+    tx.commit().map_err(|why| why.to_string())?;
+
+    history.mark_saved();
+    Ok(())
+}
+
+//append_ledger(): inserts entries into a budget's ledger, numbered on from first
+fn append_ledger(tx: &rusqlite::Transaction, budget: Uuid, first: usize, entries: &[LedgerEntry]) -> Result<(), String> {
+    let mut stmt = tx
+        .prepare("INSERT INTO ledger(budget, seq, timestamp, jsonentry) VALUES (?, ?, ?, ?)")
+        .map_err(|why| why.to_string())?;
+    for (seq, entry) in (first..).zip(entries) {
+        stmt.execute(rusqlite::params![budget, seq as i64, entry.timestamp, serde_json::to_string(entry).unwrap()])
+            .map_err(|why| why.to_string())?;
+    }
+    Ok(())
+}
+
+//get_undo_stacks(): every member's undo stacks for a budget, by username
+pub fn get_undo_stacks(budget: Uuid) -> Vec<(String, UndoStacks)> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let mut stmt = conn.prepare("SELECT member, jsonstacks FROM undo WHERE budget = ?").unwrap();
+
+    stmt.query_map(rusqlite::params![budget], |row| Ok((row.get::<usize, String>(0)?, row.get::<usize, String>(1)?)))
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter_map(|(member, stacks)| Some((member, serde_json::from_str(&stacks).ok()?)))
+        .collect()
+}
+
+//the ledger table holds every budget's ledger, a row per entry numbered from 0 by seq in the order they were recorded
+
+//get_ledger_len(): how many entries a budget's ledger holds
+pub fn get_ledger_len(budget: Uuid) -> usize {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    conn.query_row("SELECT COUNT(*) FROM ledger WHERE budget = ?", rusqlite::params![budget], |row| row.get::<usize, i64>(0))
+        .map_or(0, |count| count as usize)
+}
+
+//get_ledger(): a budget's ledger, oldest first, from the given time on if there is one
+pub fn get_ledger(budget: Uuid, since: Option<i64>) -> Vec<LedgerEntry> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let mut stmt = conn
+        .prepare("SELECT jsonentry FROM ledger WHERE budget = ?1 AND (?2 IS NULL OR timestamp >= ?2) ORDER BY seq")
+        .unwrap();
+
+    stmt.query_map(rusqlite::params![budget, since], |row| row.get::<usize, String>(0))
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter_map(|entry| serde_json::from_str(&entry).ok())
+        .collect()
+}
+
+//get_ledger_page(): one page of a budget's ledger, newest entries first
+pub fn get_ledger_page(budget: Uuid, page: usize) -> HistoryPage {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let offset = i64::try_from(page.saturating_mul(HISTORY_PAGE_SIZE)).unwrap_or(i64::MAX);
+    let mut stmt = conn
+        .prepare("SELECT jsonentry FROM ledger WHERE budget = ? ORDER BY seq DESC LIMIT ? OFFSET ?")
+        .unwrap();
+    let entries = stmt
+        .query_map(rusqlite::params![budget, HISTORY_PAGE_SIZE as i64, offset], |row| row.get::<usize, String>(0))
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter_map(|entry| serde_json::from_str(&entry).ok())
+        .collect();
+
+    HistoryPage {
+        page,
+        page_size: HISTORY_PAGE_SIZE,
+        total: get_ledger_len(budget),
+        entries,
+    }
+}
+
+//migrate_histories(): moves the ledgers saved inside jsonhistory, before the ledger table existed, into the ledger table,
+//and drops the undo stacks saved there, which held whole budgets and can't be turned into per-member changes
+pub fn migrate_histories() {
+    let mut conn = db::USER_DB.read().unwrap().connection();
+
+    let histories: Vec<(Uuid, String)> = {
+        let mut stmt = conn
+            .prepare("SELECT uuid, jsonhistory FROM budgets WHERE jsonhistory LIKE '%\"ledger\"%' OR jsonhistory LIKE '%\"undo\"%'")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .filter_map(Result::ok)
+            .collect()
+    };
+
+    for (uuid, jsonhistory) in histories {
+        let Ok(serde_json::Value::Object(mut value)) = serde_json::from_str::<serde_json::Value>(&jsonhistory) else {
+            continue;
+        };
+        let ledger = value.remove("ledger").map(serde_json::from_value::<Vec<LedgerEntry>>);
+        let undo = value.remove("undo");
+        let ledger = match ledger {
+            Some(Ok(ledger)) => ledger,
+            Some(Err(_)) => continue,
+            None if undo.is_some() => Vec::new(),
+            None => continue,
+        };
+
+        let first = get_ledger_len(uuid);
+        let Ok(tx) = conn.transaction() else {
+            continue;
+        };
+        let migrated = append_ledger(&tx, uuid, first, &ledger).and_then(|()| {
+            tx.execute(
+                "UPDATE budgets SET jsonhistory = ? WHERE uuid = ?",
+                rusqlite::params![serde_json::Value::Object(value).to_string(), uuid],
+            )
+            .map_err(|why| why.to_string())
+        });
+        if migrated.is_ok() {
+            let _ = tx.commit();
+        }
+    }
+}
+
+//move_budgets_out_of_users(): copies the budgets kept in the old users table, a row per user under the user's uuid,
+//into the budgets table under the same uuid, then drops the users table
+pub fn move_budgets_out_of_users() {
+    let mut conn = db::USER_DB.read().unwrap().connection();
+
+    let exists = conn
+        .query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'users'", [], |row| row.get::<usize, i64>(0))
+        .is_ok_and(|count| count > 0);
+    if !exists {
+        return;
+    }
+
+    let Ok(tx) = conn.transaction() else {
+        return;
+    };
+    let moved = tx
+        .execute("INSERT OR IGNORE INTO budgets(uuid, jsondata, jsonhistory) SELECT uuid, jsondata, jsonhistory FROM users", [])
+        .and_then(|_| tx.execute("DROP TABLE users", []));
+    if moved.is_ok() {
+        let _ = tx.commit();
+    }
+}
+
+//get_budget_ids(): the id of every budget in the db
+pub fn get_budget_ids() -> Vec<Uuid> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let mut stmt = conn.prepare("SELECT uuid FROM budgets").unwrap();
+
+    stmt.query_map([], |row| row.get::<usize, Uuid>(0))
+        .unwrap()
+        .filter_map(Result::ok)
+        .collect()
+}
+
+//the members and invites tables link users to the budgets (rows of the budgets table) they can open
+//budgets have their own uuid, except those from before the budgets table, which kept the uuid of the user who registered them
+
+//adopt_existing_budgets(): makes every budget without members owned by the user it was registered for
+pub fn adopt_existing_budgets() {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let _ = conn.execute(
+        "INSERT OR IGNORE INTO members(budget, user, role) SELECT uuid, uuid, 'owner' FROM budgets WHERE uuid NOT IN (SELECT budget FROM members) AND uuid IN (SELECT uuid FROM auth)",
+        [],
+    );
+}
+
+//member_from_row(): reads a member out of a row with user, username and role columns
+fn member_from_row(row: &rusqlite::Row) -> rusqlite::Result<Member> {
+    let role: String = row.get("role")?;
+    Ok(Member {
+        user: row.get("user")?,
+        username: row.get("username")?,
+        role: Role::parse(&role).unwrap_or(Role::Viewer),
+    })
+}
+
+//get_member(): a user's membership of a budget, if they have one
+pub fn get_member(budget: Uuid, user: Uuid) -> Option<Member> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    conn.query_row(
+        "SELECT members.user, auth.username, members.role FROM members JOIN auth ON auth.uuid = members.user WHERE members.budget = ? AND members.user = ?",
+        rusqlite::params![budget, user],
+        member_from_row,
+    )
+    .ok()
+}
+
+//get_members(): everyone with access to a budget, the owner first
+pub fn get_members(budget: Uuid) -> Vec<Member> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let mut stmt = conn
+        .prepare("SELECT members.user, auth.username, members.role FROM members JOIN auth ON auth.uuid = members.user WHERE members.budget = ? ORDER BY members.role != 'owner', auth.username")
+        .unwrap();
+
+    stmt.query_map(rusqlite::params![budget], member_from_row)
+        .unwrap()
+        .filter_map(Result::ok)
+        .collect()
+}
+
+//get_memberships(): every budget a user belongs to, with its owner's username and the user's role
+pub fn get_memberships(user: Uuid) -> Vec<(Uuid, String, Role)> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let mut stmt = conn
+        .prepare("SELECT mine.budget, auth.username, mine.role FROM members AS mine JOIN members AS owner ON owner.budget = mine.budget AND owner.role = 'owner' JOIN auth ON auth.uuid = owner.user WHERE mine.user = ? ORDER BY mine.role != 'owner', auth.username")
+        .unwrap();
+
+    stmt.query_map(rusqlite::params![user], |row| {
+        let role: String = row.get(2)?;
+        Ok((row.get(0)?, row.get(1)?, Role::parse(&role).unwrap_or(Role::Viewer)))
+    })
+    .unwrap()
+    .filter_map(Result::ok)
+    .collect()
+}
+
+//default_budget(): the budget a user works on when they log in, the one they own if there is one
+pub fn default_budget(user: Uuid) -> Result<Uuid, String> {
+    get_memberships(user)
+        .first()
+        .map(|(budget, _, _)| *budget)
+        .ok_or_else(|| String::from("budget_not_found"))
+}
+
+//add_member(): gives a user access to a budget
+pub fn add_member(budget: Uuid, user: Uuid, role: Role) -> Result<(), String> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    conn.execute(
+        "INSERT OR REPLACE INTO members(budget, user, role) VALUES (?, ?, ?)",
+        rusqlite::params![budget, user, role.as_str()],
+    )
+    .map(|_| ())
+    .map_err(|why| why.to_string())
+}
+
+//remove_member(): takes a user's access to a budget away
+pub fn remove_member(budget: Uuid, user: Uuid) -> Result<(), String> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    match conn.execute("DELETE FROM members WHERE budget = ? AND user = ?", rusqlite::params![budget, user]) {
+        Ok(1) => Ok(()),
+        Ok(_) => Err(String::from("member_not_found")),
+        Err(why) => Err(why.to_string()),
+    }
+}
+
+//set_member_role(): changes what a member can do with a budget
+pub fn set_member_role(budget: Uuid, user: Uuid, role: Role) -> Result<(), String> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    match conn.execute(
+        "UPDATE members SET role = ? WHERE budget = ? AND user = ?",
+        rusqlite::params![role.as_str(), budget, user],
+    ) {
+        Ok(1) => Ok(()),
+        Ok(_) => Err(String::from("member_not_found")),
+        Err(why) => Err(why.to_string()),
+    }
+}
+
+//add_invite(): invites a user to a budget, replacing any invitation they already had to it
+pub fn add_invite(budget: Uuid, user: Uuid, role: Role) -> Result<(), String> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    conn.execute(
+        "INSERT OR REPLACE INTO invites(budget, user, role) VALUES (?, ?, ?)",
+        rusqlite::params![budget, user, role.as_str()],
+    )
+    .map(|_| ())
+    .map_err(|why| why.to_string())
+}
+
+//remove_invite(): drops a user's invitation to a budget, returning whether there was one
+pub fn remove_invite(budget: Uuid, user: Uuid) -> Result<bool, String> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    conn.execute("DELETE FROM invites WHERE budget = ? AND user = ?", rusqlite::params![budget, user])
+        .map(|removed| removed > 0)
+        .map_err(|why| why.to_string())
+}
+
+//take_invite(): drops a user's invitation to a budget, returning the role they were invited as
+pub fn take_invite(budget: Uuid, user: Uuid) -> Result<Option<Role>, String> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let role = conn
+        .query_row(
+            "SELECT role FROM invites WHERE budget = ? AND user = ?",
+            rusqlite::params![budget, user],
+            |row| row.get::<usize, String>(0),
+        )
+        .ok()
+        .and_then(|role| Role::parse(&role));
+    remove_invite(budget, user)?;
+    Ok(role)
+}
+
+//get_invites(): the invitations to a budget, or waiting on a user
+pub fn get_invites(budget: Option<Uuid>, user: Option<Uuid>) -> Vec<Invite> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let mut stmt = conn
+        .prepare("SELECT invites.budget, invited.username, owner_auth.username, invites.role FROM invites JOIN auth AS invited ON invited.uuid = invites.user JOIN members AS owner ON owner.budget = invites.budget AND owner.role = 'owner' JOIN auth AS owner_auth ON owner_auth.uuid = owner.user WHERE (?1 IS NULL OR invites.budget = ?1) AND (?2 IS NULL OR invites.user = ?2) ORDER BY invited.username")
+        .unwrap();
+
+    stmt.query_map(rusqlite::params![budget, user], |row| {
+        let role: String = row.get(3)?;
+        Ok(Invite {
+            budget: row.get(0)?,
+            username: row.get(1)?,
+            owner: row.get(2)?,
+            role: Role::parse(&role).unwrap_or(Role::Viewer),
+        })
+    })
+    .unwrap()
+    .filter_map(Result::ok)
+    .collect()
+}
+
+//the shares table holds the read-only links to budgets, which are deleted when revoked
+
+//share_from_row(): reads a share link out of a row of the shares table
+fn share_from_row(row: &rusqlite::Row) -> rusqlite::Result<Share> {
+    let scope: String = row.get("scope")?;
+    Ok(Share {
+        token: row.get("token")?,
+        budget: row.get("budget")?,
+        scope: Scope::parse(&scope).unwrap_or(Scope::Reports),
+        created_by: row.get("created_by")?,
+        expires: row.get("expires")?,
+    })
+}
+
+//add_share(): saves a new share link
+pub fn add_share(share: &Share) -> Result<(), String> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    conn.execute(
+        "INSERT INTO shares(token, budget, scope, created_by, expires) VALUES (?, ?, ?, ?, ?)",
+        rusqlite::params![share.token, share.budget, share.scope.as_str(), share.created_by, share.expires],
+    )
+    .map(|_| ())
+    .map_err(|why| why.to_string())
+}
+
+//get_share(): the share link with the given token, if it exists and hasn't expired
+pub fn get_share(token: &str) -> Option<Share> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    conn.query_row(
+        "SELECT * FROM shares WHERE token = ? AND expires > ?",
+        rusqlite::params![token, chrono::Utc::now().timestamp()],
+        share_from_row,
+    )
+    .ok()
+}
+
+//get_shares(): every share link to a budget that hasn't expired, soonest to expire first
+//(expired ones are cleared out of the table along the way)
+pub fn get_shares(budget: Uuid) -> Vec<Share> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let now = chrono::Utc::now().timestamp();
+    let _ = conn.execute("DELETE FROM shares WHERE expires <= ?", rusqlite::params![now]);
+
+    let mut stmt = conn
+        .prepare("SELECT * FROM shares WHERE budget = ? ORDER BY expires")
+        .unwrap();
+
+    stmt.query_map(rusqlite::params![budget], share_from_row)
+        .unwrap()
+        .filter_map(Result::ok)
+        .collect()
+}
+
+//remove_share(): revokes a share link to a budget, returning whether there was one
+pub fn remove_share(budget: Uuid, token: &str) -> Result<bool, String> {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    conn.execute("DELETE FROM shares WHERE budget = ? AND token = ?", rusqlite::params![budget, token])
+        .map(|removed| removed > 0)
+        .map_err(|why| why.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::EntryKind;
+
+    //saved_ledger(): a new budget with the given number of entries saved to its ledger, each entry's amount its seq,
+    //recorded at times running backwards so seq and timestamp disagree on the order
+    fn saved_ledger(count: usize) -> Uuid {
+        db::for_tests();
+        let budget = Uuid::new_v4();
+        let mut history = History::default();
+        history.record(
+            (0..count)
+                .map(|seq| {
+                    let mut entry = LedgerEntry::new(EntryKind::Payment, None, "rent", seq as i64, 0);
+                    entry.timestamp = 1_000_000 - seq as i64;
+                    entry
+                })
+                .collect(),
+        );
+        save_user_history(budget, &mut history).unwrap();
+        budget
+    }
+
+    //amounts(): the amounts on one page of a budget's ledger
+    fn amounts(budget: Uuid, page: usize) -> Vec<i64> {
+        get_ledger_page(budget, page).entries.iter().map(|entry| entry.amount).collect()
+    }
+
+    #[test]
+    fn pages_run_newest_first_by_seq() {
+        let budget = saved_ledger(HISTORY_PAGE_SIZE + 5);
+        let first = get_ledger_page(budget, 0);
+        assert_eq!((first.page, first.page_size, first.total), (0, HISTORY_PAGE_SIZE, HISTORY_PAGE_SIZE + 5));
+        let newest: Vec<i64> = (5..HISTORY_PAGE_SIZE as i64 + 5).rev().collect();
+        assert_eq!(amounts(budget, 0), newest);
+        assert_eq!(amounts(budget, 1), [4, 3, 2, 1, 0]);
+
+        //a full last page, and the one after it
+        let budget = saved_ledger(HISTORY_PAGE_SIZE * 2);
+        assert_eq!(amounts(budget, 1).len(), HISTORY_PAGE_SIZE);
+        assert_eq!(amounts(budget, 1).last(), Some(&0));
+        assert!(amounts(budget, 2).is_empty());
+    }
+
+    #[test]
+    fn pages_past_the_end_are_empty() {
+        let budget = saved_ledger(3);
+        assert_eq!(amounts(budget, 0), [2, 1, 0]);
+        assert!(amounts(budget, 1).is_empty());
+        let page = get_ledger_page(budget, usize::MAX);
+        assert_eq!((page.page, page.total, page.entries.len()), (usize::MAX, 3, 0));
+        assert_eq!(get_ledger_page(Uuid::new_v4(), 0).total, 0);
+    }
+
+    #[test]
+    fn later_saves_carry_on_from_the_last_seq() {
+        let budget = saved_ledger(2);
+        let mut history = History::default();
+        history.set_saved_len(get_ledger_len(budget));
+        history.record(vec![LedgerEntry::new(EntryKind::Deposit, None, "income", 50, 0)]);
+        save_user_history(budget, &mut history).unwrap();
+        assert_eq!(amounts(budget, 0), [50, 1, 0]);
+        assert_eq!(get_ledger(budget, None).iter().map(|entry| entry.amount).collect::<Vec<_>>(), [0, 1, 50]);
+    }
+}
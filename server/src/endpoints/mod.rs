@@ -10,18 +10,31 @@ use http_bytes::http;
 pub enum Content {
     File(String),
     HandlerFunction(
-        Box<dyn Fn(&mut path::Iter, Option<String>) -> Result<http::Response<Vec<u8>>, String>>,
+        Box<dyn Fn(&mut path::Iter, Option<String>) -> Result<http::Response<Vec<u8>>, String> + Send + Sync>,
     ),
     LoginRequest,
     RegisterRequest,
     LogoutRequest,
     UserDataRequest,
+    UserHistoryRequest,
+    UserPeriodsRequest,
+    UserImportRequest,
+    UserExportRequest,
+    UserReportRequest,
+    UserForecastRequest,
+    UserNotificationsRequest,
+    UserRestoreRequest,
+    UserMembersRequest,
+    UserBudgetsRequest,
+    UserSharesRequest,
+    SharedRequest,
     UserCommand,
+    UserBatchCommand,
     TelemetryQuery
 }
 pub fn new_func_endpoint(
     func: Box<
-        dyn Fn(&mut path::Iter, Option<String>) -> Result<http::Response<Vec<u8>>, String>,
+        dyn Fn(&mut path::Iter, Option<String>) -> Result<http::Response<Vec<u8>>, String> + Send + Sync,
     >,
 ) -> Content {
     Content::HandlerFunction(func)
@@ -1,15 +1,13 @@
-use std::{env, time::Instant};
+use std::time::Instant;
 
 use bcrypt;
 use jsonwebtoken;
 use uuid::{self, Uuid};
 
 use crate::{
-    budget::Budget, db::{self, UserAuthRow, UserCredentials, UserInfo}, threads::auth::{self, AuthError}
+    budget::Budget, config, db::{self, UserAuthRow, UserCredentials, UserInfo}, endpoints::database, history::History, threads::auth::{self, AuthError}
 };
 
-const HASH_COST: u32 = 7;
-
 //register() takes user data as a string, parses it,
 //hashes the password, and then inserts into databases
 pub fn register(data: String) -> Result<(Uuid, String), AuthError> {
@@ -48,7 +46,7 @@ pub fn register(data: String) -> Result<(Uuid, String), AuthError> {
     //eprintln!("\t\tuser parsed from json string: {:?}", now.elapsed());
     
     //attempt to hash the password
-    user.password = match bcrypt::hash(user.password, HASH_COST) {
+    user.password = match bcrypt::hash(user.password, config::get().hash_cost) {
         //if successful, great!
         Ok(hash) => hash,
         //otherwise, idk what couldve happened tbh. just send a 400
@@ -60,8 +58,9 @@ pub fn register(data: String) -> Result<(Uuid, String), AuthError> {
     
     //eprintln!("\t\tpassword hashed: {:?}", now.elapsed());
 
-    //generate a new uuid
+    //generate a new uuid, for the user and for the budget they start out with
     let id = uuid::Uuid::new_v4();
+    let budget = uuid::Uuid::new_v4();
 
     //eprintln!("\t\tuuid generated: {:?}", now.elapsed());
 
@@ -95,17 +94,17 @@ pub fn register(data: String) -> Result<(Uuid, String), AuthError> {
 
     //eprintln!("\t\tempty budget generated: {:?}", now.elapsed());
 
-    //insert new user data into userdata table
+    //insert the new budget into the budgets table
     match conn.execute(
-        "INSERT INTO users(uuid, jsondata, jsonhistory) VALUES (?, ?, ?)",
-        rusqlite::params![id, serde_json::to_string(&new_budget).unwrap(), "{}"],
+        "INSERT INTO budgets(uuid, jsondata, jsonhistory) VALUES (?, ?, ?)",
+        rusqlite::params![budget, serde_json::to_string(&new_budget).unwrap(), "{}"],
     ) {
         Ok(_) => {
             //println!("user {} data registered", user.username)
         },
         Err(why) => {
             println!(
-                "failed user data table registration for {}\n{}",
+                "failed budgets table registration for {}\n{}",
                 user.username,
                 why.to_string()
             );
@@ -114,6 +113,15 @@ pub fn register(data: String) -> Result<(Uuid, String), AuthError> {
         }
     }
 
+    //the new budget is the user's own
+    if let Err(why) = conn.execute(
+        "INSERT INTO members(budget, user, role) VALUES (?, ?, 'owner')",
+        rusqlite::params![budget, id],
+    ) {
+        println!("failed members table registration for {}\n{}", user.username, why);
+        return Err(AuthError::BadRequest);
+    }
+
     //eprintln!("\t\tuser added to data table: {:?}", now.elapsed());
 
     //println!("user registered!");
@@ -201,8 +209,8 @@ pub fn login(data: String) -> Result<(Uuid, String), AuthError> {
 
 //create_token_response() takes in UserInfo, generates a jsonwebtoken, and sends a CREATED response
 pub fn create_token(user_info: UserInfo) -> String {
-    //token expires in an hour
-    let exp = chrono::Utc::now() + chrono::Duration::minutes(60);
+    //token expires after token_minutes (an hour, by default)
+    let exp = chrono::Utc::now() + chrono::Duration::minutes(i64::from(config::get().token_minutes));
     //create token data struct
     let token_data = auth::UserToken::new(user_info, exp.timestamp() as usize);
 
@@ -210,11 +218,7 @@ pub fn create_token(user_info: UserInfo) -> String {
     jsonwebtoken::encode(
         &jsonwebtoken::Header::default(),
         &token_data,
-        &jsonwebtoken::EncodingKey::from_secret(
-            env::var("SECRET")
-                .expect("SECRET should be in .env")
-                .as_ref(),
-        ),
+        &jsonwebtoken::EncodingKey::from_secret(config::get().secret.as_bytes()),
     )
     .unwrap()
 }
@@ -244,25 +248,46 @@ pub fn get_user_auth_row(username: String) -> Result<UserAuthRow, rusqlite::Erro
 }
 
 //TODO: MOVE TO db.rs?!
-//get_user_data_from_uuid(): takes in a user's unique id, and returns their Budget data from the db
+//get_user_data_from_uuid(): takes in a budget's unique id, and returns its Budget data from the db
 pub fn get_user_data_from_uuid(uuid: Uuid) -> Budget {
     let conn = db::USER_DB.read().unwrap().connection();
 
-    let mut stmt = conn.prepare("SELECT * FROM users WHERE uuid = ?").unwrap();
+    let mut stmt = conn.prepare("SELECT * FROM budgets WHERE uuid = ?").unwrap();
 
     stmt.query_row(rusqlite::params![uuid], |row| {
         let data: String = row.get("jsondata").unwrap();
-        let bud: Budget = serde_json::from_str(data.as_str()).unwrap();
+        let bud: Budget = Budget::from_json(data.as_str()).unwrap();
         Ok(bud)
     }).unwrap()
 }
 
+//TODO: MOVE TO db.rs?!
+//get_user_history_from_uuid(): takes in a budget's unique id, and returns its History from the db
+pub fn get_user_history_from_uuid(uuid: Uuid) -> History {
+    let conn = db::USER_DB.read().unwrap().connection();
+
+    let mut stmt = conn.prepare("SELECT jsonhistory FROM budgets WHERE uuid = ?").unwrap();
+
+    stmt.query_row(rusqlite::params![uuid], |row| {
+        let data: String = row.get("jsonhistory").unwrap();
+        //older accounts were registered with an empty "{}" history, which loads as an empty History
+        let history: History = serde_json::from_str(data.as_str()).unwrap_or_default();
+        Ok(history)
+    }).map(|mut history| {
+        history.set_saved_len(database::get_ledger_len(uuid));
+        for (member, stacks) in database::get_undo_stacks(uuid) {
+            history.load_undo(member, stacks);
+        }
+        history
+    }).unwrap()
+}
+
 //get_uuid_from_token(): takes in a JSONWEBTOKEN and returns the UUID encoded in it
 //if the token is valid. returns failure if invalid
 pub fn get_uuid_from_token(token: &String) -> Result<Uuid, String> {
 
     let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
-    let secret: String = env::var("SECRET").expect("SECRET should be in .env");
+    let secret = &config::get().secret;
 
     //attempt to decode token
     let user_info = jsonwebtoken::decode::<auth::UserToken>(
@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{self, Deserialize, Serialize};
+
+use crate::money::{add_cents, sub_cents};
+
+//Envelope: money set aside for one expense category, amounts in cents
+//what's been spent out of it this period is the category's current expense
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Envelope {
+    //left over from earlier pay periods, negative if the envelope was overspent
+    pub carried: i64,
+    //assigned into the envelope this pay period
+    pub assigned: i64,
+}
+impl Envelope {
+    //available(): what's left to spend, given what's been spent this period
+    pub fn available(&self, spent: i64) -> i64 {
+        self.carried.saturating_add(self.assigned).saturating_sub(spent)
+    }
+}
+
+//Envelopes: zero-based budgeting, where income goes into an unassigned pool and is then assigned into categories
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Envelopes {
+    //income that hasn't been assigned to a category yet
+    pub unassigned: i64,
+    //by lowercase category name, only for categories that have had money assigned
+    #[serde(default)]
+    pub envelopes: BTreeMap<String, Envelope>,
+}
+impl Envelopes {
+    //available(): what's left to spend in a category's envelope
+    pub fn available(&self, name: &str, spent: i64) -> i64 {
+        self.envelopes
+            .get(name)
+            .copied()
+            .unwrap_or_default()
+            .available(spent)
+    }
+
+    //assign(): moves money from the unassigned pool into an envelope, or back out of it for a negative amount
+    pub fn assign(&mut self, name: &str, cents: i64, spent: i64) -> Result<(), String> {
+        if cents > self.unassigned {
+            return Err(String::from("not_enough_unassigned"));
+        }
+        if -cents > self.available(name, spent) {
+            return Err(String::from("not_enough_in_envelope"));
+        }
+        let unassigned = sub_cents(self.unassigned, cents)?;
+        let envelope = self.envelopes.entry(name.to_string()).or_default();
+        envelope.assigned = add_cents(envelope.assigned, cents)?;
+        self.unassigned = unassigned;
+        Ok(())
+    }
+
+    //roll_over(): carries what's left in every envelope into the next pay period, given what was spent in this one
+    pub fn roll_over(&mut self, spent: &HashMap<String, i64>) {
+        for (name, envelope) in self.envelopes.iter_mut() {
+            *envelope = Envelope {
+                carried: envelope.available(spent.get(name).copied().unwrap_or(0)),
+                assigned: 0,
+            };
+        }
+        self.envelopes.retain(|_, envelope| *envelope != Envelope::default());
+    }
+
+    //rename(): moves an envelope over to a renamed category, adding it to the other category's when they're merged
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), String> {
+        let Some(envelope) = self.envelopes.get(from).copied() else {
+            return Ok(());
+        };
+        let into = self.envelopes.get(to).copied().unwrap_or_default();
+        let merged = Envelope {
+            carried: add_cents(into.carried, envelope.carried)?,
+            assigned: add_cents(into.assigned, envelope.assigned)?,
+        };
+        self.envelopes.remove(from);
+        self.envelopes.insert(to.to_string(), merged);
+        Ok(())
+    }
+
+    //remove(): empties a deleted category's envelope back into the unassigned pool
+    pub fn remove(&mut self, name: &str, spent: i64) -> Result<(), String> {
+        if let Some(envelope) = self.envelopes.get(name) {
+            self.unassigned = add_cents(self.unassigned, envelope.available(spent))?;
+            self.envelopes.remove(name);
+        }
+        Ok(())
+    }
+}
+
+//EnvelopeBalance: one envelope as it's sent back to the user
+#[derive(Debug, Serialize)]
+pub struct EnvelopeBalance {
+    pub category: String,
+    pub carried: i64,
+    pub assigned: i64,
+    pub spent: i64,
+    pub available: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::Budget;
+
+    //pool(): envelopes with 100.00 waiting to be assigned
+    fn pool() -> Envelopes {
+        Envelopes {
+            unassigned: 10000,
+            ..Envelopes::default()
+        }
+    }
+
+    #[test]
+    fn assigns_only_what_there_is() {
+        let mut envelopes = pool();
+        envelopes.assign("food", 3000, 0).unwrap();
+        assert_eq!((envelopes.unassigned, envelopes.available("food", 1000)), (7000, 2000));
+
+        assert_eq!(envelopes.assign("food", 7001, 0), Err(String::from("not_enough_unassigned")));
+        assert_eq!(envelopes.assign("food", -2001, 1000), Err(String::from("not_enough_in_envelope")));
+        assert_eq!(envelopes.unassigned, 7000);
+
+        //taking money back out only goes as far as what hasn't been spent
+        envelopes.assign("food", -2000, 1000).unwrap();
+        assert_eq!((envelopes.unassigned, envelopes.available("food", 1000)), (9000, 0));
+    }
+
+    #[test]
+    fn rolls_leftovers_and_overspending_into_the_next_period() {
+        let mut envelopes = pool();
+        envelopes.assign("food", 3000, 0).unwrap();
+        envelopes.assign("rent", 5000, 0).unwrap();
+        envelopes.assign("fun", 1000, 0).unwrap();
+        let spent = HashMap::from([(String::from("food"), 1000), (String::from("rent"), 6000), (String::from("fun"), 1000)]);
+
+        envelopes.roll_over(&spent);
+        assert_eq!(envelopes.envelopes["food"], Envelope { carried: 2000, assigned: 0 });
+        assert_eq!(envelopes.envelopes["rent"], Envelope { carried: -1000, assigned: 0 });
+        assert!(!envelopes.envelopes.contains_key("fun"));
+        assert_eq!(Envelope { carried: i64::MAX, assigned: 1 }.available(0), i64::MAX);
+    }
+
+    #[test]
+    fn follows_categories_when_theyre_renamed_or_removed() {
+        let mut envelopes = pool();
+        envelopes.assign("food", 3000, 0).unwrap();
+        envelopes.assign("snacks", 1000, 0).unwrap();
+
+        envelopes.rename("snacks", "food").unwrap();
+        assert_eq!(envelopes.envelopes["food"].assigned, 4000);
+        assert!(!envelopes.envelopes.contains_key("snacks"));
+        envelopes.rename("nothing", "food").unwrap();
+        assert_eq!(envelopes.envelopes.len(), 1);
+
+        envelopes.remove("food", 500).unwrap();
+        assert_eq!((envelopes.unassigned, envelopes.envelopes.len()), (9500, 0));
+    }
+
+    #[test]
+    fn moves_between_a_budgets_envelopes() {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.set_income(10000).unwrap();
+        budget.get_paid(None).unwrap();
+        budget.add_expense("food", 3000, None);
+        budget.add_expense("rent", 5000, None);
+        assert_eq!(budget.assign("food", 1000), Err(String::from("envelopes_not_on")));
+
+        budget.set_envelopes(true).unwrap();
+        assert_eq!(budget.set_envelopes(true), Err(String::from("envelopes_already_on")));
+        budget.assign("food", 3000).unwrap();
+        budget.move_envelope("Food", "rent", 1000).unwrap();
+        assert_eq!(budget.move_envelope("food", "food", 100), Err(String::from("invalid_envelope_move")));
+        assert_eq!(budget.move_envelope("food", "rent", -100), Err(String::from("invalid_envelope_move")));
+        assert_eq!(budget.move_envelope("food", "gas", 100), Err(String::from("expense_not_found")));
+        assert_eq!(budget.move_envelope("food", "rent", 2001), Err(String::from("not_enough_in_envelope")));
+        assert_eq!(budget.assign("gas", 100), Err(String::from("expense_not_found")));
+    }
+
+    #[test]
+    fn automatic_payments_take_from_the_pool_only_once_made() {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.set_envelopes(true).unwrap();
+        budget.set_income(10000).unwrap();
+        budget.add_expense("gym", 5000, Some(true));
+        budget.add_expense("rent", 6000, Some(true));
+        budget.get_paid(None).unwrap();
+
+        //the gym is paid and its envelope funded, the rent can't be paid so nothing is set aside for it
+        let envelopes = &serde_json::to_value(&budget).unwrap()["envelopes"];
+        assert_eq!((envelopes["unassigned"].as_i64(), envelopes["envelopes"]["gym"]["assigned"].as_i64()), (Some(5000), Some(5000)));
+        assert!(envelopes["envelopes"].get("rent").is_none());
+        assert_eq!(budget.overdue_total(), 6000);
+        let codes: Vec<&str> = budget.notifications().iter().map(|notification| notification.code.as_str()).collect();
+        assert_eq!(codes, ["payment_overdue"]);
+    }
+}
@@ -0,0 +1,260 @@
+use serde::{self, Deserialize, Serialize};
+
+use crate::budget::Budget;
+use crate::history::{History, LedgerEntry};
+use crate::money::Money;
+use crate::period::PeriodSnapshot;
+
+//the version of the export document this server writes, bumped whenever its layout changes
+//documents from older versions can still be restored, newer ones can't
+pub const EXPORT_VERSION: u32 = 1;
+
+//ExportDocument: everything stored for a user, as sent back by /user/export/json and read by /user/restore
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExportDocument {
+    pub version: u32,
+    pub exported: i64,
+    pub budget: Budget,
+    pub history: ExportedHistory,
+}
+
+//ExportedHistory: a user's whole ledger and archived pay periods, oldest first
+//undo and redo aren't exported, they only make sense against the budget they were made on
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct ExportedHistory {
+    #[serde(default)]
+    pub ledger: Vec<LedgerEntry>,
+    #[serde(default)]
+    pub periods: Vec<PeriodSnapshot>,
+}
+
+impl ExportDocument {
+    //new(): bundles up a user's budget, history and ledger, stamped with the current time
+    pub fn new(budget: &Budget, history: &History, ledger: Vec<LedgerEntry>) -> ExportDocument {
+        ExportDocument {
+            version: EXPORT_VERSION,
+            exported: chrono::Utc::now().timestamp(),
+            budget: budget.clone(),
+            history: ExportedHistory {
+                ledger,
+                periods: history.periods().into_iter().rev().cloned().collect(),
+            },
+        }
+    }
+
+    //parse(): reads and validates an uploaded export document
+    pub fn parse(body: &str) -> Result<ExportDocument, String> {
+        let version = serde_json::from_str::<serde_json::Value>(body)
+            .map_err(|_err| String::from("invalid_export_json"))?
+            .get("version")
+            .and_then(|version| version.as_u64())
+            .ok_or(String::from("missing_export_version"))?;
+        if version == 0 || version > EXPORT_VERSION as u64 {
+            return Err(String::from("unsupported_export_version"));
+        }
+
+        let document: ExportDocument =
+            serde_json::from_str(body).map_err(|_err| String::from("invalid_export_document"))?;
+        let mut document = document;
+        document.budget.move_automatic_prefixes();
+        let entries = document.budget.take_journal();
+        document.history.ledger.extend(entries);
+        document.budget.validate()?;
+        Ok(document)
+    }
+}
+
+//CsvTable: the parts of a user's data that can be exported as csv
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvTable {
+    Ledger,
+    Expenses,
+    Accounts,
+    Periods,
+}
+impl CsvTable {
+    //parse(): reads a CsvTable from its path segment (/user/export/csv/ledger)
+    pub fn parse(name: &str) -> Option<CsvTable> {
+        match name {
+            "ledger" => Some(CsvTable::Ledger),
+            "expenses" => Some(CsvTable::Expenses),
+            "accounts" => Some(CsvTable::Accounts),
+            "periods" => Some(CsvTable::Periods),
+            _ => None,
+        }
+    }
+
+    //filename(): the name the csv file is downloaded as
+    pub fn filename(self) -> &'static str {
+        match self {
+            CsvTable::Ledger => "ledger.csv",
+            CsvTable::Expenses => "expenses.csv",
+            CsvTable::Accounts => "accounts.csv",
+            CsvTable::Periods => "periods.csv",
+        }
+    }
+
+    //to_csv(): writes the table out of the user's budget and history, and ledger (only loaded for the ledger table)
+    pub fn to_csv(self, budget: &Budget, history: &History, ledger: &[LedgerEntry]) -> String {
+        let mut out = String::new();
+        match self {
+            CsvTable::Ledger => {
+                out.push_str("timestamp,kind,account,label,amount,balance,currency,member\n");
+                for entry in ledger {
+                    let kind = serde_json::to_value(entry.kind).unwrap();
+                    push_row(&mut out, &[
+                        entry.timestamp.to_string(),
+                        kind.as_str().unwrap_or("").to_string(),
+                        entry.account.clone().unwrap_or_default(),
+                        entry.label.clone(),
+                        Money(entry.amount).to_string(),
+                        Money(entry.balance).to_string(),
+                        entry.currency.clone().unwrap_or_else(|| budget.base_currency().to_string()),
+                        entry.member.clone().unwrap_or_default(),
+                    ]);
+                }
+            }
+            CsvTable::Expenses => {
+                out.push_str("category,expected,current\n");
+                for (name, expected, current) in budget.expense_rows() {
+                    push_row(&mut out, &[name, Money(expected).to_string(), Money(current).to_string()]);
+                }
+            }
+            CsvTable::Accounts => {
+                out.push_str("account,kind,balance,currency,primary\n");
+                for (name, account) in budget.accounts() {
+                    let kind = serde_json::to_value(account.kind).unwrap();
+                    push_row(&mut out, &[
+                        name.clone(),
+                        kind.as_str().unwrap_or("").to_string(),
+                        Money(account.balance).to_string(),
+                        budget.currency_of(name).to_string(),
+                        (*name == budget.primary_account()).to_string(),
+                    ]);
+                }
+            }
+            CsvTable::Periods => {
+                out.push_str("number,started,ended,income,spent,saved,ending_balance\n");
+                for period in history.periods().into_iter().rev() {
+                    push_row(&mut out, &[
+                        period.number.to_string(),
+                        period.started.to_string(),
+                        period.ended.to_string(),
+                        Money(period.income).to_string(),
+                        Money(period.spent()).to_string(),
+                        Money(period.saved).to_string(),
+                        Money(period.ending_balance).to_string(),
+                    ]);
+                }
+            }
+        }
+        out
+    }
+}
+
+//push_row(): appends one csv line, quoting any field that needs it
+fn push_row(out: &mut String, fields: &[String]) {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    out.push_str(&fields.join(","));
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    //used(): a budget (and its history) that's had a bit of everything happen to it
+    fn used() -> (Budget, History) {
+        let mut budget = Budget::new(String::from("sam"));
+        let mut history = History::default();
+        budget.set_income(10000).unwrap();
+        history.archive_period(budget.close_period());
+        budget.get_paid(None).unwrap();
+        budget.add_expense("rent", 5000, None);
+        budget.add_expense("food, drink", 3000, None);
+        budget.make_dynamic_payment(None, "food, drink", 1234).unwrap();
+        budget.add_account("wallet", crate::account::AccountKind::Cash, 2000, None).unwrap();
+        budget.add_goal("car", 50000, None).unwrap();
+        budget.save(None, 1000, Some("car")).unwrap();
+        history.archive_period(budget.close_period());
+        budget.get_paid(None).unwrap();
+        history.record(budget.take_journal());
+        (budget, history)
+    }
+
+    #[test]
+    fn restores_exactly_what_was_exported() {
+        let (budget, history) = used();
+        let ledger = history.unsaved().to_vec();
+        let exported = serde_json::to_string(&ExportDocument::new(&budget, &history, ledger.clone())).unwrap();
+
+        let document = ExportDocument::parse(&exported).unwrap();
+        assert_eq!(serde_json::to_value(&document.budget).unwrap(), serde_json::to_value(&budget).unwrap());
+        assert_eq!(serde_json::to_value(&document.history.ledger).unwrap(), serde_json::to_value(&ledger).unwrap());
+
+        let mut restored = History::default();
+        restored.restore(document.history.ledger, document.history.periods);
+        assert_eq!(restored.unsaved().len(), ledger.len());
+        let numbers = |history: &History| history.periods().iter().map(|period| period.number).collect::<Vec<_>>();
+        assert_eq!(numbers(&restored), numbers(&history));
+
+        //and exporting the restored data gives the same document back
+        let again = ExportDocument::new(&document.budget, &restored, restored.unsaved().to_vec());
+        let mut first: serde_json::Value = serde_json::from_str(&exported).unwrap();
+        let mut second = serde_json::to_value(&again).unwrap();
+        first["exported"] = json!(0);
+        second["exported"] = json!(0);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn turns_down_documents_it_cant_read() {
+        let (budget, history) = used();
+        let mut document = serde_json::to_value(ExportDocument::new(&budget, &history, Vec::new())).unwrap();
+        let parse = |value: &serde_json::Value| ExportDocument::parse(&value.to_string()).map(|_document| ());
+
+        assert_eq!(ExportDocument::parse("{budget").map(|_document| ()), Err(String::from("invalid_export_json")));
+        document["version"] = json!(EXPORT_VERSION + 1);
+        assert_eq!(parse(&document), Err(String::from("unsupported_export_version")));
+        document["version"] = json!(0);
+        assert_eq!(parse(&document), Err(String::from("unsupported_export_version")));
+        document.as_object_mut().unwrap().remove("version");
+        assert_eq!(parse(&document), Err(String::from("missing_export_version")));
+        document["version"] = json!(EXPORT_VERSION);
+        document["budget"]["accounts"] = json!("none");
+        assert_eq!(parse(&document), Err(String::from("invalid_export_document")));
+    }
+
+    #[test]
+    fn writes_each_table_as_csv() {
+        let (budget, history) = used();
+        assert!(CsvTable::parse("ledger").is_some_and(|table| table.filename() == "ledger.csv"));
+        assert_eq!(CsvTable::parse("goals"), None);
+
+        let expenses = CsvTable::Expenses.to_csv(&budget, &history, &[]);
+        assert_eq!(expenses, "category,expected,current\n\"food, drink\",30.00,0.00\nrent,50.00,0.00\n");
+
+        let accounts = CsvTable::Accounts.to_csv(&budget, &history, &[]);
+        assert_eq!(accounts.lines().next(), Some("account,kind,balance,currency,primary"));
+        assert!(accounts.contains("\nwallet,cash,20.00,USD,false\n"));
+
+        let periods = CsvTable::Periods.to_csv(&budget, &history, &[]);
+        assert_eq!(periods.lines().count(), 3);
+        assert!(periods.lines().nth(2).unwrap().starts_with("1,"));
+
+        let ledger = CsvTable::Ledger.to_csv(&budget, &history, history.unsaved());
+        assert_eq!(ledger.lines().count(), history.unsaved().len() + 1);
+        assert!(ledger.contains(",payment,checking,\"food, drink\",12.34,"));
+        assert_eq!(Money(-5).to_string(), "-0.05");
+    }
+}
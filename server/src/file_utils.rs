@@ -1,4 +1,3 @@
-use std::env;
 use std::ffi::OsStr;
 use std::fs::{self, File, Metadata};
 use std::io::{prelude::*, BufReader};
@@ -6,9 +5,7 @@ use std::path::PathBuf;
 use std::collections::HashMap;
 use std::sync::{LazyLock, Mutex};
 
-
-//CLIENT_FILE_PATH: the location of the files that will be sent to client
-const CLIENT_FILE_PATH: &str = "../client/static";
+use crate::config;
 
 //FILE CACHE IMPLEMENTATION:
 //instead of loading and reading a file from the file system every single time,
@@ -39,8 +36,8 @@ pub fn get_file(filename: &OsStr) -> Result<Vec<u8>, String> {
 
     let filename = sanitize_filename(filename);
 
-    //build the file path by sticking it to the end of CLIENT_FILE_PATH
-    let mut filepath = PathBuf::from(CLIENT_FILE_PATH);
+    //build the file path by sticking it to the end of client_path, where the files sent to the client are
+    let mut filepath = PathBuf::from(&config::get().client_path);
     filepath.push(filename.clone());
 
     //debug print
@@ -55,7 +52,7 @@ pub fn get_file(filename: &OsStr) -> Result<Vec<u8>, String> {
             let mut reader = BufReader::new(file);
             reader.seek(std::io::SeekFrom::Start(0)).unwrap();
             let file: Vec<u8> = reader.bytes().map(Result::unwrap).collect();
-            if env::var("DO_CACHING").unwrap_or_default() == "true" {
+            if config::get().do_caching {
                 FILE_CACHE.lock().unwrap().insert(filename, file.clone());
             }
             Ok(file)
@@ -67,8 +64,8 @@ pub fn get_file(filename: &OsStr) -> Result<Vec<u8>, String> {
 
 //grab the file's metadata, without opening it
 pub fn get_file_metadata(filename: &OsStr) -> Result<Metadata, String> {
-    //build the file path by sticking it to the end of CLIENT_FILE_PATH
-    let mut filepath = PathBuf::from(CLIENT_FILE_PATH);
+    //build the file path by sticking it to the end of client_path
+    let mut filepath = PathBuf::from(&config::get().client_path);
     filepath.push(sanitize_filename(filename));
 
     //debug print
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+
+use chrono::{Days, Local, NaiveDate, TimeZone};
+use serde::{self, Serialize};
+
+use crate::account::AccountKind;
+use crate::budget::Budget;
+use crate::goal::PAY_PERIOD_DAYS;
+use crate::schedule::{Schedule, ScheduleKind};
+
+//how many pay periods a forecast covers when the request doesn't say
+pub const DEFAULT_FORECAST_PERIODS: u32 = 6;
+//the most pay periods a forecast can cover (about two years)
+pub const MAX_FORECAST_PERIODS: u32 = 52;
+
+//ForecastPeriod: one projected pay period, amounts in cents of the base currency
+#[derive(Debug, Serialize)]
+pub struct ForecastPeriod {
+    pub number: u32,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    //the paycheck (expected_income), 0 for the period that's already open
+    pub paycheck: i64,
+    pub automatic_expenses: i64,
+    pub other_expenses: i64,
+    pub scheduled_income: i64,
+    pub current_balance: i64,
+    pub savings: i64,
+    pub negative: bool,
+}
+
+//Forecast: the json body sent back for /user/forecast
+#[derive(Debug, Serialize)]
+pub struct Forecast {
+    pub periods: Vec<ForecastPeriod>,
+    //the number of the first period whose balance goes below zero, if any does
+    pub first_negative: Option<u32>,
+}
+
+//Flow: where a scheduled item's money goes, as far as the forecast's two balances are concerned
+enum Flow {
+    Balance,
+    Savings,
+    //accounts that are neither the primary account nor savings don't move either balance
+    Elsewhere,
+}
+
+//flow_of(): which balance a schedule's money moves
+fn flow_of(budget: &Budget, schedule: &Schedule) -> Flow {
+    let account = schedule.account.as_deref().unwrap_or(budget.primary_account());
+    if account == budget.primary_account() {
+        return Flow::Balance;
+    }
+    match budget.accounts().get(account) {
+        Some(account) if account.kind == AccountKind::Savings => Flow::Savings,
+        Some(_) => Flow::Elsewhere,
+        None => Flow::Balance,
+    }
+}
+
+//build(): projects the budget forward over the given number of pay periods, the first being the one already open
+//every period spends each expected expense in full (or what's left of it, in the open period),
+//unless schedules pay more than that into the category; scheduled incomes come on top of the paycheck
+pub fn build(budget: &Budget, today: NaiveDate, periods: u32) -> Forecast {
+    let open = budget.period();
+    let started = Local
+        .timestamp_opt(open.started, 0)
+        .single()
+        .map_or(today, |time| time.date_naive());
+    //the next paycheck is a pay period after the last one, or today if that's already passed
+    let next_payday = (started + Days::new(PAY_PERIOD_DAYS as u64)).max(today + Days::new(1));
+
+    let mut schedules: Vec<Schedule> = budget.schedules().to_vec();
+    let expenses = budget.expense_rows();
+
+    let mut balance = budget.to_base(None, budget.primary_balance());
+    let mut savings = budget.savings_balance();
+    let mut first_negative = None;
+    let mut forecast = Vec::new();
+
+    for index in 0..periods {
+        let (start, end) = if index == 0 {
+            (today, next_payday - Days::new(1))
+        } else {
+            let start = next_payday + Days::new((index as u64 - 1) * PAY_PERIOD_DAYS as u64);
+            (start, start + Days::new(PAY_PERIOD_DAYS as u64 - 1))
+        };
+
+        let paycheck = if index == 0 { 0 } else { budget.expected_income() };
+        balance += paycheck;
+
+        //scheduled items falling in the period, expenses by category
+        let mut scheduled_income = 0;
+        let mut scheduled_expenses: BTreeMap<String, i64> = BTreeMap::new();
+        for schedule in schedules.iter_mut() {
+            while schedule.next_due <= end {
+                if schedule.next_due >= start || index == 0 {
+                    let amount = budget.to_base(schedule.account.as_deref(), schedule.amount);
+                    match (schedule.kind, flow_of(budget, schedule)) {
+                        (ScheduleKind::Income, Flow::Balance) => {
+                            balance += amount;
+                            scheduled_income += amount;
+                        }
+                        (ScheduleKind::Income, Flow::Savings) => savings += amount,
+                        (ScheduleKind::Expense, Flow::Savings) => savings -= amount,
+                        (ScheduleKind::Expense, Flow::Balance) => {
+                            *scheduled_expenses.entry(schedule.label.clone()).or_default() += amount;
+                        }
+                        (_, Flow::Elsewhere) => {}
+                    }
+                }
+                schedule.advance();
+            }
+        }
+
+        let mut automatic_expenses = 0;
+        let mut other_expenses = 0;
+        for (name, expected, current) in &expenses {
+            //the open period has already paid some of its expenses
+            let expected = if index == 0 { (expected - current).max(0) } else { *expected };
+            let spent = expected.max(scheduled_expenses.remove(name).unwrap_or(0));
+            if budget.is_automatic(name) {
+                automatic_expenses += spent;
+            } else {
+                other_expenses += spent;
+            }
+        }
+        //scheduled payments into categories that have since been removed
+        other_expenses += scheduled_expenses.values().sum::<i64>();
+        //overdue payments are still owed
+        if index == 0 {
+            other_expenses += budget.overdue_total();
+        }
+
+        balance -= automatic_expenses + other_expenses;
+
+        let number = open.number + index;
+        if balance < 0 && first_negative.is_none() {
+            first_negative = Some(number);
+        }
+
+        forecast.push(ForecastPeriod {
+            number,
+            start,
+            end,
+            paycheck,
+            automatic_expenses,
+            other_expenses,
+            scheduled_income,
+            current_balance: balance,
+            savings,
+            negative: balance < 0,
+        });
+    }
+
+    Forecast {
+        periods: forecast,
+        first_negative,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::Frequency;
+
+    #[test]
+    fn projects_paychecks_expenses_and_schedules() {
+        let today = Local::now().date_naive();
+        let mut budget = Budget::new(String::from("sam"));
+        budget.set_income(100000).unwrap();
+        budget.get_paid_value(None, 200000).unwrap();
+        budget.add_expense("rent", 60000, Some(true));
+        budget.add_expense("food", 30000, None);
+        budget.make_dynamic_payment(None, "food", 10000).unwrap();
+        //groceries every week come to more than food's expected amount, and savings get topped up every paycheck
+        budget.add_schedule(Schedule::new("food", ScheduleKind::Expense, 20000, Frequency::Weekly, today, None)).unwrap();
+        budget.add_schedule(Schedule::new("top up", ScheduleKind::Income, 5000, Frequency::Biweekly, today, Some("savings"))).unwrap();
+
+        let forecast = build(&budget, today, 3);
+        let periods: Vec<(u32, i64, i64, i64, i64, i64)> = forecast
+            .periods
+            .iter()
+            .map(|period| (period.number, period.paycheck, period.automatic_expenses, period.other_expenses, period.current_balance, period.savings))
+            .collect();
+        assert_eq!(periods, [
+            (0, 0, 60000, 40000, 90000, 5000),
+            (1, 100000, 60000, 40000, 90000, 10000),
+            (2, 100000, 60000, 40000, 90000, 15000),
+        ]);
+        assert_eq!((forecast.periods[0].start, forecast.periods[0].end), (today, today + Days::new(13)));
+        assert_eq!(forecast.periods[1].start, today + Days::new(14));
+        assert_eq!(forecast.first_negative, None);
+    }
+
+    #[test]
+    fn finds_the_first_period_that_runs_out() {
+        let today = Local::now().date_naive();
+        let mut budget = Budget::new(String::from("sam"));
+        budget.get_paid_value(None, 60000).unwrap();
+        budget.add_expense("rent", 50000, None);
+
+        let forecast = build(&budget, today, 3);
+        let balances: Vec<(i64, bool)> = forecast.periods.iter().map(|period| (period.current_balance, period.negative)).collect();
+        assert_eq!(balances, [(10000, false), (-40000, true), (-90000, true)]);
+        assert_eq!(forecast.first_negative, Some(1));
+
+        //overdue payments are owed straight away
+        budget.add_expense("loan", 0, None);
+        budget.add_schedule(Schedule::new("loan", ScheduleKind::Expense, 70000, Frequency::Weekly, today, None)).unwrap();
+        budget.run_schedules(today);
+        assert_eq!(budget.overdue_total(), 70000);
+        let forecast = build(&budget, today, 1);
+        assert_eq!((forecast.periods[0].other_expenses, forecast.first_negative), (50000 + 70000 + 70000, Some(0)));
+    }
+}
@@ -0,0 +1,197 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{self, Deserialize, Serialize};
+
+use crate::schedule::clamp_to_month;
+
+//how long a pay period is assumed to last when projecting goals, in days
+pub const PAY_PERIOD_DAYS: i64 = 14;
+
+//Goal: a named amount the user is saving up towards, optionally by a deadline
+//money saved towards a goal stays in the user's savings account, the goal just keeps count of it
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Goal {
+    pub target: i64,
+    pub saved: i64,
+    pub deadline: Option<NaiveDate>,
+    //closed goals are kept around, but can't be saved into anymore
+    pub closed: bool,
+}
+impl Goal {
+    //new(): creates an open goal with nothing saved towards it yet
+    pub fn new(target: i64, deadline: Option<NaiveDate>) -> Goal {
+        Goal {
+            target,
+            saved: 0,
+            deadline,
+            closed: false,
+        }
+    }
+
+    //remaining(): how much is still needed to reach the target
+    pub fn remaining(&self) -> i64 {
+        (self.target - self.saved).max(0)
+    }
+
+    //progress(): projects the goal as of the given day,
+    //given how much income is left over each pay period once expected expenses are paid
+    pub fn progress(&self, name: &str, today: NaiveDate, leftover: i64) -> GoalProgress {
+        let remaining = self.remaining();
+        let percent = if self.target > 0 {
+            (self.saved as f64 / self.target as f64 * 100.0).min(100.0)
+        } else {
+            100.0
+        };
+
+        //pay periods left before the deadline, counting the one that's currently open
+        let periods_left = self.deadline.map(|deadline| {
+            let days = (deadline - today).num_days();
+            if days < 0 {
+                0
+            } else {
+                days / PAY_PERIOD_DAYS + 1
+            }
+        });
+
+        let required_per_period = match periods_left {
+            Some(0) => Some(remaining),
+            Some(periods) => Some((remaining + periods - 1) / periods),
+            None => None,
+        };
+
+        let warning = if self.closed || remaining == 0 {
+            None
+        } else if periods_left == Some(0) {
+            Some(String::from("deadline_passed"))
+        } else if required_per_period.is_some_and(|required| required > leftover) {
+            Some(String::from("needs_more_than_leftover_income"))
+        } else {
+            None
+        };
+
+        GoalProgress {
+            name: name.to_string(),
+            target: self.target,
+            saved: self.saved,
+            remaining,
+            percent,
+            deadline: self.deadline,
+            periods_left,
+            required_per_period,
+            on_track: warning.is_none(),
+            warning,
+            closed: self.closed,
+        }
+    }
+}
+
+//GoalProgress: the computed state of a goal, sent back alongside the budget
+#[derive(Debug, Serialize)]
+pub struct GoalProgress {
+    pub name: String,
+    pub target: i64,
+    pub saved: i64,
+    pub remaining: i64,
+    pub percent: f64,
+    pub deadline: Option<NaiveDate>,
+    pub periods_left: Option<i64>,
+    pub required_per_period: Option<i64>,
+    pub on_track: bool,
+    pub warning: Option<String>,
+    pub closed: bool,
+}
+
+//parse_deadline(): reads a deadline from either YYYY-MM-DD, or YYYY-MM meaning the end of that month
+pub fn parse_deadline(s: &str) -> Result<NaiveDate, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date);
+    }
+
+    //chrono can't parse a date without a day, so borrow the 1st to read the month
+    match NaiveDate::parse_from_str(&format!("{}-01", s), "%Y-%m-%d") {
+        Ok(date) => Ok(clamp_to_month(date.year(), date.month(), 31)),
+        Err(_) => Err(String::from("invalid_goal_deadline_value")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::Budget;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    //savings(): the savings account's balance, read off the budget's json
+    fn savings(budget: &Budget) -> i64 {
+        serde_json::to_value(budget).unwrap()["accounts"]["savings"]["balance"].as_i64().unwrap()
+    }
+
+    #[test]
+    fn reads_deadlines_by_day_or_month() {
+        assert_eq!(parse_deadline("2024-06-15"), Ok(date(2024, 6, 15)));
+        assert_eq!(parse_deadline("2024-02"), Ok(date(2024, 2, 29)));
+        assert_eq!(parse_deadline("2024-13"), Err(String::from("invalid_goal_deadline_value")));
+        assert_eq!(parse_deadline("soon"), Err(String::from("invalid_goal_deadline_value")));
+    }
+
+    #[test]
+    fn projects_what_each_pay_period_needs() {
+        let mut goal = Goal::new(10000, Some(date(2024, 1, 29)));
+        goal.saved = 2500;
+        //the 29th is 4 weeks away, so there are 3 pay periods left counting this one
+        let progress = goal.progress("car", date(2024, 1, 1), 5000);
+        assert_eq!((progress.remaining, progress.periods_left, progress.required_per_period), (7500, Some(3), Some(2500)));
+        assert_eq!((progress.percent, progress.on_track, progress.warning), (25.0, true, None));
+
+        let progress = goal.progress("car", date(2024, 1, 1), 2000);
+        assert_eq!(progress.warning.as_deref(), Some("needs_more_than_leftover_income"));
+        let progress = goal.progress("car", date(2024, 2, 1), 5000);
+        assert_eq!((progress.periods_left, progress.warning.as_deref()), (Some(0), Some("deadline_passed")));
+
+        //reached goals are on track whatever the deadline, and goals without one never need anything
+        goal.saved = 12000;
+        let progress = goal.progress("car", date(2024, 2, 1), 0);
+        assert_eq!((progress.remaining, progress.percent, progress.on_track), (0, 100.0, true));
+        let progress = Goal::new(10000, None).progress("car", date(2024, 1, 1), 0);
+        assert_eq!((progress.periods_left, progress.required_per_period, progress.on_track), (None, None, true));
+    }
+
+    #[test]
+    fn saving_towards_a_goal_keeps_count_of_it() {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.set_income(30000).unwrap();
+        budget.add_expense("rent", 20000, None);
+        budget.get_paid_value(None, 50000).unwrap();
+        budget.add_goal("Car", 10000, Some(date(2024, 1, 29))).unwrap();
+        budget.save(None, 4000, Some("car")).unwrap();
+        budget.save(None, 1000, None).unwrap();
+        assert_eq!(savings(&budget), 5000);
+
+        let progress = budget.goal_progress(date(2024, 1, 1));
+        assert_eq!((progress[0].name.as_str(), progress[0].saved, progress[0].required_per_period), ("car", 4000, Some(2000)));
+        assert_eq!(budget.leftover_income(), 10000);
+
+        budget.edit_goal("car", Some(5000), Some(None)).unwrap();
+        budget.close_goal("car").unwrap();
+        let progress = budget.goal_progress(date(2024, 1, 1));
+        assert_eq!((progress[0].target, progress[0].deadline, progress[0].closed), (5000, None, true));
+    }
+
+    #[test]
+    fn turns_down_bad_goals() {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.get_paid_value(None, 5000).unwrap();
+        budget.add_goal("car", 10000, None).unwrap();
+        assert_eq!(budget.add_goal("CAR", 100, None), Err(String::from("goal_already_exists")));
+        assert_eq!(budget.add_goal("", 100, None), Err(String::from("invalid_goal_name")));
+        assert_eq!(budget.add_goal("boat", 0, None), Err(String::from("invalid_goal_target_value")));
+        assert_eq!(budget.edit_goal("car", Some(-5), None), Err(String::from("invalid_goal_target_value")));
+        assert_eq!(budget.save(None, 100, Some("boat")), Err(String::from("goal_not_found")));
+
+        budget.close_goal("car").unwrap();
+        assert_eq!(budget.save(None, 100, Some("car")), Err(String::from("goal_closed")));
+        assert_eq!(budget.close_goal("car"), Err(String::from("goal_closed")));
+        assert_eq!(savings(&budget), 0);
+    }
+}
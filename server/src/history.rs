@@ -0,0 +1,391 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{self, Deserialize, Serialize};
+
+use crate::budget::Budget;
+use crate::period::{PeriodComparison, PeriodSnapshot};
+use crate::undo::{UndoStacks, UndoStep};
+
+//how many ledger entries are sent back per page of history
+pub const HISTORY_PAGE_SIZE: usize = 25;
+
+//EntryKind: what sort of change a ledger entry represents
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    NewExpense,
+    //an expense's expected amount or flags changed, with its new expected amount
+    EditExpense,
+    DeleteExpense,
+    Payment,
+    Paycheck,
+    Deposit,
+    SetIncome,
+    RaiseIncome,
+    Save,
+    Overdue,
+    NewAccount,
+    TransferOut,
+    TransferIn,
+    NewGoal,
+    GoalSave,
+    CloseGoal,
+    //money assigned into (or out of, when negative) an envelope, with the unassigned pool left as the balance
+    Assign,
+    //a category renamed (or merged into another), from label to memo
+    Rename,
+    //the reversal of a Rename when it's undone
+    RenameUndone,
+    Undo,
+    Redo,
+}
+
+//LedgerEntry: one recorded change to a Budget
+//amount is in cents, balance is the balance of the changed account (or the primary account) right after the change
+//undoing a command appends its entries again with the amounts negated, so totals over the ledger stay right
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LedgerEntry {
+    pub timestamp: i64,
+    pub kind: EntryKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    pub label: String,
+    pub amount: i64,
+    pub balance: i64,
+    //the currency amount and balance are in, when the account isn't in the base currency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    //extra detail, like the payee of an imported statement row
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    //free-form tags the user put on a payment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    //the username of the member who made the change, none for scheduled changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub member: Option<String>,
+}
+impl LedgerEntry {
+    //new(): creates an entry stamped with the current time
+    pub fn new(kind: EntryKind, account: Option<&str>, label: &str, amount: i64, balance: i64) -> LedgerEntry {
+        LedgerEntry {
+            timestamp: chrono::Utc::now().timestamp(),
+            kind,
+            account: account.map(str::to_string),
+            label: label.to_string(),
+            amount,
+            balance,
+            currency: None,
+            memo: None,
+            tags: Vec::new(),
+            member: None,
+        }
+    }
+
+    //replayed(): a fresh copy of the entry with its amount multiplied by sign (-1 to reverse it),
+    //and the balance as it is in the given budget. renames have no amount, so reversing one changes its kind instead
+    fn replayed(&self, sign: i64, budget: &Budget) -> LedgerEntry {
+        let kind = match (self.kind, sign < 0) {
+            (EntryKind::Rename, true) => EntryKind::RenameUndone,
+            (kind, _) => kind,
+        };
+        let mut entry = LedgerEntry::new(
+            kind,
+            self.account.as_deref(),
+            &self.label,
+            self.amount * sign,
+            budget.balance_of(self.account.as_deref()),
+        );
+        entry.currency = self.currency.clone();
+        entry.memo = self.memo.clone();
+        entry.tags = self.tags.clone();
+        entry
+    }
+}
+
+//History: everything that has happened to a user's Budget, stored in the budgets.jsonhistory column
+//except the ledger, which is kept a row per entry in the ledger table, and each member's undo stacks,
+//kept a row per member in the undo table. the ledger is append-only, entries are never edited or removed
+//once recorded, so only the new ones are held here until they're saved
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct History {
+    //entries recorded since the history was last saved, oldest first
+    #[serde(skip)]
+    unsaved: Vec<LedgerEntry>,
+    //how many entries the ledger table already holds
+    #[serde(skip)]
+    saved: usize,
+    //closed pay periods, oldest first
+    #[serde(default)]
+    periods: Vec<PeriodSnapshot>,
+    //whether periods changed since the history was last saved, so jsonhistory needs writing again
+    #[serde(skip)]
+    periods_changed: bool,
+    //each member's recent commands that can be undone and redone, by username
+    #[serde(skip)]
+    undo: BTreeMap<String, UndoStacks>,
+    //the members whose undo stacks changed since the history was last saved
+    #[serde(skip)]
+    undo_changed: BTreeSet<String>,
+    //the member making the changes being recorded, stamped onto each new entry
+    #[serde(skip)]
+    acting: Option<String>,
+}
+impl History {
+    //record(): appends the given entries to the end of the ledger, made by the acting member
+    pub fn record(&mut self, entries: Vec<LedgerEntry>) {
+        let member = self.acting.clone();
+        self.unsaved.extend(entries.into_iter().map(|mut entry| {
+            entry.member = entry.member.or_else(|| member.clone());
+            entry
+        }));
+    }
+
+    //act_as(): sets the member whose changes are recorded next, none for changes nobody made (like schedules)
+    pub fn act_as(&mut self, member: Option<&str>) {
+        self.acting = member.map(str::to_string);
+    }
+
+    //restore(): replaces the history with a restored export's, its whole ledger waiting to be saved
+    //nobody's commands can be undone across a restore
+    pub fn restore(&mut self, ledger: Vec<LedgerEntry>, periods: Vec<PeriodSnapshot>) {
+        self.unsaved = ledger;
+        self.periods = periods;
+        self.periods_changed = true;
+        let members: Vec<String> = self.undo.keys().cloned().collect();
+        self.undo.clear();
+        self.undo_changed.extend(members);
+    }
+
+    //unsaved(): the entries recorded since the history was last saved, oldest first
+    pub fn unsaved(&self) -> &[LedgerEntry] {
+        &self.unsaved
+    }
+
+    //saved_len(): how many entries the ledger table already holds, which is where the unsaved ones go next
+    pub fn saved_len(&self) -> usize {
+        self.saved
+    }
+
+    //set_saved_len(): notes how many entries the ledger table holds, when the history is loaded
+    pub fn set_saved_len(&mut self, count: usize) {
+        self.saved = count;
+    }
+
+    //periods_changed(): whether the archived periods need saving into jsonhistory
+    pub fn periods_changed(&self) -> bool {
+        self.periods_changed
+    }
+
+    //load_undo(): puts a member's undo stacks back, when the history is loaded
+    pub fn load_undo(&mut self, member: String, stacks: UndoStacks) {
+        self.undo.insert(member, stacks);
+    }
+
+    //changed_undo(): the members whose undo stacks need saving, with their stacks (none once they're empty)
+    pub fn changed_undo(&self) -> Vec<(&str, Option<&UndoStacks>)> {
+        self.undo_changed
+            .iter()
+            .map(|member| (member.as_str(), self.undo.get(member).filter(|stacks| !stacks.is_empty())))
+            .collect()
+    }
+
+    //mark_saved(): notes that everything changed has been saved
+    pub fn mark_saved(&mut self) {
+        self.saved += self.unsaved.len();
+        self.unsaved.clear();
+        self.periods_changed = false;
+        self.undo_changed.clear();
+    }
+
+    //checkpoint(): remembers what a command changed, so the acting member can undo it
+    //a new command means the member's undone commands can't be redone anymore
+    pub fn checkpoint(&mut self, command: &str, before: &Budget, after: &Budget, archived: Vec<PeriodSnapshot>, entries: Vec<LedgerEntry>) {
+        let Some(member) = self.acting.clone() else {
+            return;
+        };
+        let step = UndoStep::new(command, before, after, archived, entries);
+        let stacks = self.undo.entry(member.clone()).or_default();
+        stacks.push_undo(step);
+        stacks.redo.clear();
+        self.undo_changed.insert(member);
+    }
+
+    //undo(): takes back the acting member's last command, keeping whatever anyone else has done since
+    pub fn undo(&mut self, budget: &mut Budget) -> Result<String, String> {
+        let member = self.acting.clone().unwrap_or_default();
+        let Some(step) = self.undo.get(&member).and_then(|stacks| stacks.undo.last()) else {
+            return Err(String::from("nothing_to_undo"));
+        };
+
+        //periods the command archived have to still be the newest ones, or a later one would be lost
+        let kept = self
+            .periods
+            .len()
+            .checked_sub(step.archived.len())
+            .filter(|&kept| self.periods[kept..].iter().map(|period| period.number).eq(step.archived.iter().map(|period| period.number)))
+            .ok_or_else(|| String::from("undo_conflict"))?;
+        let reverted = step.undo(budget)?;
+        let step = self.undo.get_mut(&member).and_then(|stacks| stacks.undo.pop()).unwrap();
+
+        //changes noted on the budget before it's swapped out still get recorded
+        self.record(budget.take_journal());
+        *budget = reverted;
+        if !step.archived.is_empty() {
+            self.periods.truncate(kept);
+            self.periods_changed = true;
+        }
+
+        let reversed: Vec<LedgerEntry> = step.entries.iter().map(|entry| entry.replayed(-1, budget)).collect();
+        self.record(reversed);
+        self.record(vec![LedgerEntry::new(EntryKind::Undo, None, &step.command, 0, budget.primary_balance())]);
+
+        let message = format!("Undid {}", step.command);
+        self.undo.entry(member.clone()).or_default().redo.push(step);
+        self.undo_changed.insert(member);
+        Ok(message)
+    }
+
+    //redo(): makes the acting member's last undone command's changes again
+    pub fn redo(&mut self, budget: &mut Budget) -> Result<String, String> {
+        let member = self.acting.clone().unwrap_or_default();
+        let Some(step) = self.undo.get(&member).and_then(|stacks| stacks.redo.last()) else {
+            return Err(String::from("nothing_to_redo"));
+        };
+
+        let replaced = step.redo(budget)?;
+        let step = self.undo.get_mut(&member).and_then(|stacks| stacks.redo.pop()).unwrap();
+
+        self.record(budget.take_journal());
+        *budget = replaced;
+        if !step.archived.is_empty() {
+            self.periods.extend(step.archived.iter().cloned());
+            self.periods_changed = true;
+        }
+
+        let replayed: Vec<LedgerEntry> = step.entries.iter().map(|entry| entry.replayed(1, budget)).collect();
+        self.record(replayed);
+        self.record(vec![LedgerEntry::new(EntryKind::Redo, None, &step.command, 0, budget.primary_balance())]);
+
+        let message = format!("Redid {}", step.command);
+        self.undo.entry(member.clone()).or_default().push_undo(step);
+        self.undo_changed.insert(member);
+        Ok(message)
+    }
+
+    //archive_period(): stores the snapshot of a pay period that just closed
+    pub fn archive_period(&mut self, snapshot: PeriodSnapshot) {
+        self.periods.push(snapshot);
+        self.periods_changed = true;
+    }
+
+    //periods(): every archived pay period, newest first
+    pub fn periods(&self) -> Vec<&PeriodSnapshot> {
+        self.periods.iter().rev().collect()
+    }
+
+    //compare_periods(): compares two archived periods by number, if they both exist
+    pub fn compare_periods(&self, first: u32, second: u32) -> Option<PeriodComparison> {
+        let find = |number| self.periods.iter().find(|period| period.number == number);
+        Some(PeriodComparison::new(find(first)?, find(second)?))
+    }
+}
+
+//HistoryPage: the json body sent back for a history request, one page of the ledger table, newest entries first
+#[derive(Debug, Serialize)]
+pub struct HistoryPage {
+    pub page: usize,
+    pub page_size: usize,
+    pub total: usize,
+    pub entries: Vec<LedgerEntry>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{self, CommandRequest};
+    use crate::undo::UNDO_LIMIT;
+    use serde_json::json;
+
+    //send(): runs one command as the given member
+    fn send(budget: &mut Budget, history: &mut History, member: &str, value: serde_json::Value) -> Result<(), String> {
+        history.act_as(Some(member));
+        CommandRequest::parse(&value.to_string())
+            .and_then(|request| commands::execute(request, budget, history))
+            .map(|_outcome| ())
+            .map_err(|error| error.code)
+    }
+
+    //kinds(): the kinds and amounts of the entries recorded since the history was last saved
+    fn kinds(history: &History) -> Vec<(EntryKind, i64)> {
+        history.unsaved().iter().map(|entry| (entry.kind, entry.amount)).collect()
+    }
+
+    #[test]
+    fn undo_reverses_the_commands_entries_on_the_ledger() {
+        let mut budget = Budget::new(String::from("ann"));
+        let mut history = History::default();
+        send(&mut budget, &mut history, "ann", json!({"command": "getpaid", "amount": 100})).unwrap();
+        history.mark_saved();
+
+        send(&mut budget, &mut history, "ann", json!({"command": "undo"})).unwrap();
+        assert_eq!(kinds(&history), [(EntryKind::Deposit, -10000), (EntryKind::Undo, 0)]);
+        send(&mut budget, &mut history, "ann", json!({"command": "redo"})).unwrap();
+        assert_eq!(kinds(&history)[2..], [(EntryKind::Deposit, 10000), (EntryKind::Redo, 0)]);
+        assert!(history.unsaved().iter().all(|entry| entry.member.as_deref() == Some("ann")));
+        assert_eq!(history.saved_len(), 1);
+    }
+
+    #[test]
+    fn each_member_keeps_their_own_limited_stack() {
+        let mut budget = Budget::new(String::from("ann"));
+        let mut history = History::default();
+        for amount in 1..=UNDO_LIMIT + 5 {
+            send(&mut budget, &mut history, "ann", json!({"command": "setincome", "amount": amount})).unwrap();
+        }
+        send(&mut budget, &mut history, "bob", json!({"command": "new", "label": "rent", "amount": 5})).unwrap();
+
+        for _ in 0..UNDO_LIMIT {
+            send(&mut budget, &mut history, "ann", json!({"command": "undo"})).unwrap();
+        }
+        assert_eq!(send(&mut budget, &mut history, "ann", json!({"command": "undo"})), Err(String::from("nothing_to_undo")));
+        //the oldest commands fell off the stack, so their changes stay
+        assert_eq!(budget.expected_income(), 500);
+        assert_eq!(budget.expense_rows().len(), 1);
+    }
+
+    #[test]
+    fn notes_whose_stacks_need_saving() {
+        let mut budget = Budget::new(String::from("ann"));
+        let mut history = History::default();
+        send(&mut budget, &mut history, "ann", json!({"command": "setincome", "amount": 5})).unwrap();
+        send(&mut budget, &mut history, "bob", json!({"command": "setincome", "amount": 6})).unwrap();
+        let changed: Vec<(&str, bool)> = history.changed_undo().into_iter().map(|(member, stacks)| (member, stacks.is_some())).collect();
+        assert_eq!(changed, [("ann", true), ("bob", true)]);
+
+        history.mark_saved();
+        assert!(history.changed_undo().is_empty());
+        assert!(!history.periods_changed());
+
+        //a restore forgets every member's stacks, which then need deleting
+        history.restore(Vec::new(), Vec::new());
+        let changed: Vec<(&str, bool)> = history.changed_undo().into_iter().map(|(member, stacks)| (member, stacks.is_some())).collect();
+        assert_eq!(changed, [("ann", false), ("bob", false)]);
+        assert_eq!(send(&mut budget, &mut history, "ann", json!({"command": "undo"})), Err(String::from("nothing_to_undo")));
+    }
+
+    #[test]
+    fn undo_keeps_periods_archived_since() {
+        let mut budget = Budget::new(String::from("ann"));
+        let mut history = History::default();
+        send(&mut budget, &mut history, "ann", json!({"command": "getpaid"})).unwrap();
+        send(&mut budget, &mut history, "bob", json!({"command": "getpaid"})).unwrap();
+
+        //ann's paycheck closed a period that's no longer the newest, so taking it back would lose bob's
+        assert_eq!(send(&mut budget, &mut history, "ann", json!({"command": "undo"})), Err(String::from("undo_conflict")));
+        send(&mut budget, &mut history, "bob", json!({"command": "undo"})).unwrap();
+        send(&mut budget, &mut history, "ann", json!({"command": "undo"})).unwrap();
+        assert!(history.periods().is_empty());
+        assert!(history.periods_changed());
+    }
+}
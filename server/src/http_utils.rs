@@ -1,11 +1,12 @@
 use colored::Colorize;
+use serde::Serialize;
 use http_bytes;
 use http_bytes::http;
 use std::{
     ffi::OsStr, io::Write, path::Path, thread
 };
 
-use crate::{file_utils, metrics::{self}, server::TimedStream};
+use crate::{config, file_utils, metrics::{self}, server::TimedStream};
 
 const REQ_BODY_TRUNCATE_LEN: usize = 32;
 const SHOW_HEADERS: bool = false;
@@ -16,7 +17,20 @@ pub fn send_response(
     stream: &mut TimedStream,
 ) -> Result<(), std::io::Error> {
 
-    
+    //every response says how long it is and whether the connection stays open,
+    //so a client reusing the connection knows where this response ends
+    let length = response.body().len();
+    let headers = response.headers_mut();
+    if !headers.contains_key(http::header::CONTENT_LENGTH) {
+        headers.insert(http::header::CONTENT_LENGTH, length.into());
+    }
+    if stream.keep_alive() {
+        headers.insert(http::header::CONNECTION, http::HeaderValue::from_static("keep-alive"));
+        headers.insert("keep-alive", format!("timeout={}", config::get().keep_alive_timeout).parse().unwrap());
+    } else {
+        headers.insert(http::header::CONNECTION, http::HeaderValue::from_static("close"));
+    }
+
     //print the response
     println!("{}{}\tfrom {}\n\t\t{}\n", "<-- ".bright_green().bold(), stream.id, metrics::thread_name(), stringify_response(&response));
     
@@ -27,6 +41,7 @@ pub fn send_response(
     let err = err.and(stream.flush());
 
     if err.is_err() { println!("\t\t\t\tfailed to send response!!: {}", stream.id) }
+    else { stream.responded() }
     
     metrics::response_sent(stream.id);
 
@@ -127,6 +142,32 @@ pub fn ok_json(status: http::StatusCode, body: String) -> Result<http::Response<
         .unwrap())
 }
 
+//ok_html: builds and returns a response with an html page built in memory as the body
+pub fn ok_html(status: http::StatusCode, body: String) -> Result<http::Response<Vec<u8>>, String> {
+    Ok(http::Response::builder()
+        .status(status)
+        .header("Content-Type", "text/html")
+        .header("Content-Length", body.len())
+        .body(body.as_bytes().to_vec())
+        .unwrap())
+}
+
+//ok_download: builds and returns a response that browsers save as a file with the given name
+pub fn ok_download(
+    status: http::StatusCode,
+    content_type: &str,
+    filename: &str,
+    body: String,
+) -> Result<http::Response<Vec<u8>>, String> {
+    Ok(http::Response::builder()
+        .status(status)
+        .header("Content-Type", content_type)
+        .header("Content-Disposition", format!("attachment; filename=\"{}\"", filename))
+        .header("Content-Length", body.len())
+        .body(body.as_bytes().to_vec())
+        .unwrap())
+}
+
 //grabs a file and returns it with a proper HTTP response for the file type
 pub fn ok_file(
     status: http::StatusCode,
@@ -170,8 +211,48 @@ pub fn content_too_large() -> Result<http::Response<Vec<u8>>, String> {
     empty_response(http::StatusCode::PAYLOAD_TOO_LARGE)
 }
 
+//ErrorBody: the json body of every error response
+//code is a stable snake_case name for scripts to match on, field is the request field at fault (if any)
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub field: Option<String>,
+    pub message: String,
+}
+impl ErrorBody {
+    pub fn new(code: &str, field: Option<&str>, message: &str) -> ErrorBody {
+        ErrorBody {
+            code: code.to_string(),
+            field: field.map(str::to_string),
+            message: message.to_string(),
+        }
+    }
+
+    //from_code(): builds an ErrorBody out of an error string from elsewhere in the server
+    //snake_case codes are kept as the code, anything else is a message for a person to read
+    pub fn from_code(code: String, field: Option<&str>) -> ErrorBody {
+        if code.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+            let message = code.replace('_', " ");
+            ErrorBody { code, field: field.map(str::to_string), message }
+        } else {
+            ErrorBody { code: String::from("command_failed"), field: field.map(str::to_string), message: code }
+        }
+    }
+}
+
+//builds and returns a 400 BAD REQUEST http response with an error code as its json body
 pub fn bad_request_msg(msg: String) -> Result<http::Response<Vec<u8>>, String> {
-    ok_json(http::StatusCode::BAD_REQUEST, format!("{{\"error\":\"{}\"}}", msg))
+    bad_request_error(&ErrorBody::from_code(msg, None))
+}
+
+//builds and returns a 400 BAD REQUEST http response with the given error as its json body
+pub fn bad_request_error(error: &ErrorBody) -> Result<http::Response<Vec<u8>>, String> {
+    ok_json(http::StatusCode::BAD_REQUEST, serde_json::to_string(error).unwrap())
+}
+
+//builds and returns a 403 FORBIDDEN http response with an error code as its json body
+pub fn forbidden_msg(msg: String) -> Result<http::Response<Vec<u8>>, String> {
+    ok_json(http::StatusCode::FORBIDDEN, serde_json::to_string(&ErrorBody::from_code(msg, None)).unwrap())
 }
 
 //builds and returns a 404 NOT FOUND http response, with the 404.html webpage
@@ -179,6 +260,15 @@ pub fn not_found() -> Result<http::Response<Vec<u8>>, String> {
     ok_file(http::StatusCode::NOT_FOUND, OsStr::new("404.html"))
 }
 
+//builds and returns a 301 MOVED PERMANENTLY http response, sending the client to the given location
+pub fn redirect(location: String) -> Result<http::Response<Vec<u8>>, String> {
+    http::Response::builder()
+        .status(http::StatusCode::MOVED_PERMANENTLY)
+        .header(http::header::LOCATION, location)
+        .body(Vec::new())
+        .map_err(|why| why.to_string())
+}
+
 pub fn unauthorized() -> Result<http::Response<Vec<u8>>, String> {
     empty_response(http::StatusCode::UNAUTHORIZED)
 }
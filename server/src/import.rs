@@ -0,0 +1,401 @@
+use chrono::NaiveDate;
+use serde::{self, Deserialize, Serialize};
+
+use crate::money::Money;
+
+//ImportFormat: the kinds of bank statement files that can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Csv,
+    Ofx,
+}
+impl ImportFormat {
+    //parse(): reads an ImportFormat from its path segment (/user/import/csv)
+    //qfx files are just ofx files with a different name
+    pub fn parse(name: &str) -> Option<ImportFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Some(ImportFormat::Csv),
+            "ofx" | "qfx" => Some(ImportFormat::Ofx),
+            _ => None,
+        }
+    }
+}
+
+//StatementUpload: a statement file sent to /user/import, on its way to the user's thread
+pub struct StatementUpload {
+    pub format: ImportFormat,
+    //the account the statement is from (the primary account if not given)
+    pub account: Option<String>,
+    pub body: String,
+}
+
+//CsvMapping: which columns of a user's csv statements hold which values, found by header name
+//statements list money going out as negative amounts unless debits_negative is false
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CsvMapping {
+    pub date: String,
+    pub payee: String,
+    pub amount: String,
+    //a column holding the bank's own transaction id, used for de-duplicating if given
+    pub id: Option<String>,
+    pub date_format: String,
+    pub debits_negative: bool,
+}
+impl Default for CsvMapping {
+    fn default() -> CsvMapping {
+        CsvMapping {
+            date: String::from("date"),
+            payee: String::from("description"),
+            amount: String::from("amount"),
+            id: None,
+            date_format: String::from("%Y-%m-%d"),
+            debits_negative: true,
+        }
+    }
+}
+
+//PayeeRule: sends any statement row whose payee contains the pattern to an expense category
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PayeeRule {
+    pub pattern: String,
+    pub category: String,
+}
+
+//StatementRow: one transaction read out of a statement file
+//amount is in cents, positive for money going out of the account
+#[derive(Debug, Serialize, Clone)]
+pub struct StatementRow {
+    pub id: String,
+    pub date: Option<NaiveDate>,
+    pub payee: String,
+    pub amount: i64,
+}
+
+//ImportReport: the json body sent back after an import, describing what happened to each row
+#[derive(Debug, Serialize, Default)]
+pub struct ImportReport {
+    pub imported: Vec<ImportedRow>,
+    //rows that were already imported before
+    pub duplicates: usize,
+    //rows paying money into the account, which aren't expenses
+    pub credits: usize,
+    //rows that no payee rule matched, left to be imported again once there is one
+    pub unmatched: Vec<StatementRow>,
+    //the row that matched a category but couldn't be paid, which stops the whole statement being imported
+    pub failed: Vec<FailedRow>,
+}
+impl ImportReport {
+    //batch(): the json array of importrow commands that pays every row in imported out of the given account
+    pub fn batch(&self, account: Option<&str>) -> String {
+        let commands: Vec<serde_json::Value> = self
+            .imported
+            .iter()
+            .map(|row| {
+                let mut command = serde_json::json!({
+                    "command": "importrow",
+                    "label": row.category,
+                    "amount": Money(row.amount).to_string(),
+                    "payee": row.payee,
+                    "id": row.id,
+                });
+                if let Some(account) = account {
+                    command["account"] = serde_json::Value::from(account);
+                }
+                command
+            })
+            .collect();
+        serde_json::Value::Array(commands).to_string()
+    }
+}
+
+//ImportedRow: a row that was paid into an expense category
+#[derive(Debug, Serialize)]
+pub struct ImportedRow {
+    #[serde(skip)]
+    pub id: String,
+    pub payee: String,
+    pub category: String,
+    pub amount: i64,
+}
+
+//FailedRow: a row that couldn't be paid, with the reason why
+#[derive(Debug, Serialize)]
+pub struct FailedRow {
+    pub payee: String,
+    pub amount: i64,
+    pub error: String,
+}
+
+//parse_statement(): reads every transaction out of a statement file
+pub fn parse_statement(format: ImportFormat, body: &str, mapping: &CsvMapping) -> Result<Vec<StatementRow>, String> {
+    match format {
+        ImportFormat::Csv => parse_csv(body, mapping),
+        ImportFormat::Ofx => parse_ofx(body),
+    }
+}
+
+//parse_csv(): reads a csv statement using the given column mapping, the first line must be the header
+pub fn parse_csv(body: &str, mapping: &CsvMapping) -> Result<Vec<StatementRow>, String> {
+    let mut lines = body.lines().filter(|line| !line.trim().is_empty());
+
+    let Some(header) = lines.next() else {
+        return Err(String::from("empty_statement"));
+    };
+    let header: Vec<String> = split_csv_line(header)
+        .iter()
+        .map(|column| column.trim().to_ascii_lowercase())
+        .collect();
+
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| *column == name.to_ascii_lowercase())
+            .ok_or(format!("missing_csv_column_{}", name.to_ascii_lowercase()))
+    };
+    let date_column = column(&mapping.date)?;
+    let payee_column = column(&mapping.payee)?;
+    let amount_column = column(&mapping.amount)?;
+    let id_column = mapping.id.as_deref().map(column).transpose()?;
+
+    let mut rows: Vec<StatementRow> = Vec::new();
+    for (line_number, line) in lines.enumerate() {
+        let fields = split_csv_line(line);
+        let field = |index: usize| {
+            fields
+                .get(index)
+                .map(|field| field.trim())
+                .ok_or(format!("short_csv_row_{}", line_number + 2))
+        };
+
+        let date = NaiveDate::parse_from_str(field(date_column)?, &mapping.date_format).ok();
+        let payee = field(payee_column)?.to_string();
+        let Some(amount) = parse_statement_amount(field(amount_column)?) else {
+            return Err(format!("invalid_csv_amount_row_{}", line_number + 2));
+        };
+        let amount = if mapping.debits_negative { -amount } else { amount };
+
+        //without an id column, identical rows are told apart by how many came before them in the file
+        let id = match id_column {
+            Some(index) => format!("csv:{}", field(index)?),
+            None => {
+                let key = format!("csv:{}|{}|{}|", field(date_column)?, payee.to_ascii_lowercase(), amount);
+                let repeats = rows.iter().filter(|row| row.id.starts_with(&key)).count();
+                format!("{}{}", key, repeats)
+            }
+        };
+
+        rows.push(StatementRow { id, date, payee, amount });
+    }
+
+    Ok(rows)
+}
+
+//split_csv_line(): splits one csv line into its fields, handling quoted fields and doubled quotes
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+//parse_statement_amount(): reads a statement amount like -$1,234.56 or (12.00) into cents
+fn parse_statement_amount(s: &str) -> Option<i64> {
+    Money::parse(s).ok().map(|amount| amount.0)
+}
+
+//parse_ofx(): reads an ofx/qfx statement, both the sgml (1.x) and xml (2.x) kinds
+//every <STMTTRN> block is a transaction, in 1.x the closing tags are optional
+pub fn parse_ofx(body: &str) -> Result<Vec<StatementRow>, String> {
+    if !body.contains("<STMTTRN>") {
+        return Err(String::from("no_ofx_transactions"));
+    }
+
+    let mut rows = Vec::new();
+    for block in body.split("<STMTTRN>").skip(1) {
+        let block = block.split("</STMTTRN>").next().unwrap_or(block);
+
+        let Some(fitid) = ofx_field(block, "FITID") else {
+            return Err(String::from("missing_ofx_fitid"));
+        };
+        let Some(amount) = ofx_field(block, "TRNAMT").and_then(parse_statement_amount) else {
+            return Err(format!("invalid_ofx_amount_{}", fitid));
+        };
+        let payee = ofx_field(block, "NAME")
+            .or_else(|| ofx_field(block, "MEMO"))
+            .unwrap_or("")
+            .to_string();
+        //dates look like 20260115 or 20260115120000[-5:EST], only the day matters
+        let date = ofx_field(block, "DTPOSTED")
+            .and_then(|date| date.get(..8))
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok());
+
+        rows.push(StatementRow {
+            id: format!("ofx:{}", fitid),
+            date,
+            payee,
+            //ofx amounts are always negative for money going out
+            amount: -amount,
+        });
+    }
+
+    Ok(rows)
+}
+
+//ofx_field(): the value of the first <TAG> in an ofx block, up to the next tag or line break
+fn ofx_field<'a>(block: &'a str, tag: &str) -> Option<&'a str> {
+    let start = block.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let value = block[start..]
+        .split(['<', '\n', '\r'])
+        .next()?
+        .trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::Budget;
+    use crate::commands;
+    use crate::history::History;
+
+    const CSV: &str = "Date,Description,Amount\n\
+        2024-01-02,\"Rent, January\",-1200.00\n\
+        2024-01-03,Coffee Shop,-$4.50\n\
+        \n\
+        2024-01-03,Coffee Shop,-$4.50\n\
+        2024-01-05,Paycheck,2000\n";
+
+    const OFX_SGML: &str = "OFXHEADER:100\n<OFX><BANKTRANLIST>\n\
+        <STMTTRN>\n<TRNTYPE>DEBIT\n<DTPOSTED>20240102120000[-5:EST]\n<TRNAMT>-1200.00\n<FITID>A1\n<NAME>RENT CO\n\
+        <STMTTRN>\n<TRNTYPE>DEBIT\n<DTPOSTED>20240103\n<TRNAMT>-4.50\n<FITID>A2\n<MEMO>COFFEE\n\
+        </BANKTRANLIST></OFX>";
+
+    const OFX_XML: &str = "<?xml version=\"1.0\"?><OFX><BANKTRANLIST>\
+        <STMTTRN><TRNTYPE>CREDIT</TRNTYPE><DTPOSTED>20240105</DTPOSTED><TRNAMT>2000.00</TRNAMT><FITID>B1</FITID><NAME>PAYROLL</NAME></STMTTRN>\
+        </BANKTRANLIST></OFX>";
+
+    #[test]
+    fn reads_csv_statements() {
+        let rows = parse_csv(CSV, &CsvMapping::default()).unwrap();
+        let read: Vec<(Option<NaiveDate>, &str, i64)> = rows.iter().map(|row| (row.date, row.payee.as_str(), row.amount)).collect();
+        assert_eq!(read, [
+            (NaiveDate::from_ymd_opt(2024, 1, 2), "Rent, January", 120000),
+            (NaiveDate::from_ymd_opt(2024, 1, 3), "Coffee Shop", 450),
+            (NaiveDate::from_ymd_opt(2024, 1, 3), "Coffee Shop", 450),
+            (NaiveDate::from_ymd_opt(2024, 1, 5), "Paycheck", -200000),
+        ]);
+        //the same purchase twice in one file is still two rows
+        assert_ne!(rows[1].id, rows[2].id);
+        assert_eq!(rows[1].id, parse_csv(CSV, &CsvMapping::default()).unwrap()[1].id);
+    }
+
+    #[test]
+    fn reads_csv_statements_through_a_mapping() {
+        let mapping = CsvMapping {
+            date: String::from("Posted"),
+            payee: String::from("Memo"),
+            amount: String::from("Debit"),
+            id: Some(String::from("Ref")),
+            date_format: String::from("%m/%d/%Y"),
+            debits_negative: false,
+        };
+        let rows = parse_csv("Ref,Posted,Memo,Debit\n77,01/31/2024,\"Say \"\"hi\"\"\",12.5\n", &mapping).unwrap();
+        assert_eq!((rows[0].id.as_str(), rows[0].date, rows[0].payee.as_str(), rows[0].amount), ("csv:77", NaiveDate::from_ymd_opt(2024, 1, 31), "Say \"hi\"", 1250));
+
+        assert_eq!(parse_csv("Ref,Posted,Memo\n", &mapping).unwrap_err(), "missing_csv_column_debit");
+        assert_eq!(parse_csv("Ref,Posted,Memo,Debit\n1,01/31/2024,x,lots\n", &mapping).unwrap_err(), "invalid_csv_amount_row_2");
+        assert_eq!(parse_csv("Ref,Posted,Memo,Debit\n1,01/31/2024\n", &mapping).unwrap_err(), "short_csv_row_2");
+        assert_eq!(parse_csv("\n\n", &mapping).unwrap_err(), "empty_statement");
+    }
+
+    #[test]
+    fn reads_both_kinds_of_ofx() {
+        let rows = parse_ofx(OFX_SGML).unwrap();
+        let read: Vec<(&str, Option<NaiveDate>, &str, i64)> =
+            rows.iter().map(|row| (row.id.as_str(), row.date, row.payee.as_str(), row.amount)).collect();
+        assert_eq!(read, [
+            ("ofx:A1", NaiveDate::from_ymd_opt(2024, 1, 2), "RENT CO", 120000),
+            ("ofx:A2", NaiveDate::from_ymd_opt(2024, 1, 3), "COFFEE", 450),
+        ]);
+
+        let rows = parse_ofx(OFX_XML).unwrap();
+        assert_eq!((rows[0].id.as_str(), rows[0].payee.as_str(), rows[0].amount), ("ofx:B1", "PAYROLL", -200000));
+
+        assert_eq!(parse_ofx("<OFX></OFX>").unwrap_err(), "no_ofx_transactions");
+        assert_eq!(parse_ofx("<STMTTRN><TRNAMT>1.00").unwrap_err(), "missing_ofx_fitid");
+        assert_eq!(parse_ofx("<STMTTRN><FITID>C1<TRNAMT>one").unwrap_err(), "invalid_ofx_amount_C1");
+        assert_eq!(ImportFormat::parse("QFX"), Some(ImportFormat::Ofx));
+        assert_eq!(ImportFormat::parse("pdf"), None);
+    }
+
+    //balance(): the primary account's balance, read off the budget's json
+    fn balance(budget: &Budget) -> i64 {
+        serde_json::to_value(budget).unwrap()["accounts"]["checking"]["balance"].as_i64().unwrap()
+    }
+
+    #[test]
+    fn imports_rows_into_the_categories_their_rules_pick() {
+        let mut budget = Budget::new(String::from("sam"));
+        let mut history = History::default();
+        history.act_as(Some("sam"));
+        budget.get_paid_value(None, 200000).unwrap();
+        budget.add_expense("rent", 120000, None);
+        budget.add_expense("coffee", 2000, None);
+        budget.add_payee_rule("RENT", "rent").unwrap();
+        budget.add_payee_rule("coffee", "coffee").unwrap();
+        budget.archive_expense("coffee", true).unwrap();
+
+        //one row that can't be paid stops the whole statement going in
+        let rows = parse_csv(CSV, &CsvMapping::default()).unwrap();
+        let before = serde_json::to_string(&budget).unwrap();
+        let report = commands::import_statement(None, rows.clone(), &mut budget, &mut history).unwrap();
+        assert!(report.imported.is_empty());
+        assert_eq!((report.credits, report.duplicates, report.unmatched.len()), (1, 0, 0));
+        assert_eq!(report.failed.iter().map(|row| (row.payee.as_str(), row.error.as_str())).collect::<Vec<_>>(), [("Coffee Shop", "expense_archived")]);
+        assert_eq!(serde_json::to_string(&budget).unwrap(), before);
+
+        budget.archive_expense("coffee", false).unwrap();
+        let report = commands::import_statement(None, rows.clone(), &mut budget, &mut history).unwrap();
+        assert_eq!(report.imported.iter().map(|row| (row.category.as_str(), row.amount)).collect::<Vec<_>>(), [("rent", 120000), ("coffee", 450), ("coffee", 450)]);
+        assert!(report.failed.is_empty());
+        assert_eq!(balance(&budget), 79100);
+
+        //importing the file again picks up nothing new
+        let report = commands::import_statement(None, rows, &mut budget, &mut history).unwrap();
+        assert_eq!((report.imported.len(), report.duplicates, report.credits), (0, 3, 1));
+        assert_eq!(balance(&budget), 79100);
+
+        //each row went in as its own command, so they can be undone one by one
+        history.undo(&mut budget).unwrap();
+        assert_eq!(balance(&budget), 79550);
+    }
+
+    #[test]
+    fn turns_down_bad_rules() {
+        let mut budget = Budget::new(String::from("sam"));
+        assert_eq!(budget.add_payee_rule("rent", "rent"), Err(String::from("expense_not_found")));
+        budget.add_expense("rent", 100, None);
+        assert_eq!(budget.add_payee_rule("", "rent"), Err(String::from("invalid_rule_pattern")));
+        assert_eq!(budget.remove_payee_rule("rent"), Err(String::from("rule_not_found")));
+        assert_eq!(budget.sort_statement(Some("vault"), Vec::new()).unwrap_err(), "account_not_found");
+    }
+}
@@ -1,4 +1,4 @@
-//dotenv: enables using .env file for variables
+//dotenv: enables using .env file for variables (as well as config.toml and the command line)
 //TODO: verbose debug print variable
 extern crate dotenv;
 use dotenv::dotenv;
@@ -13,36 +13,97 @@ mod http_utils;
 mod file_utils;
 //used for routing user connections
 mod router;
+//used for reading http requests off connections
+mod request;
+//used for serving HTTPS
+mod tls;
 //used for holding endpoint handler functions
 mod endpoints;
+//used for the settings the server is started with
+mod config;
 //used for managing database
 mod db;
 //used for holding thread code
 mod threads;
 //used for budgeting functionality
 mod budget;
+//used for parsing and running user commands
+mod commands;
+//used for the accounts a budget's money is held in
+mod account;
+//used for recording the ledger of budget changes
+mod history;
+//used for undoing and redoing commands
+mod undo;
+//used for tracking and archiving pay periods
+mod period;
+//used for recurring scheduled incomes and expenses
+mod schedule;
+//used for savings goals
+mod goal;
+//used for importing bank statements
+mod import;
+//used for exporting and restoring all of a user's data
+mod export;
+//used for spending reports
+mod reports;
+//used for grouping and tagging expense categories
+mod category;
+//used for spending limits, balance floors and the warnings they raise
+mod alerts;
+//used for envelope (zero-based) budgeting
+mod envelope;
+//used for currencies, exchange rates and formatting money
+mod currency;
+//used for parsing amounts of money exactly
+mod money;
+//used for shared budgets, their members, roles and invitations
+mod members;
+//used for read-only share links to a budget
+mod shares;
+//used for projecting balances over upcoming pay periods
+mod forecast;
 //used for logging and displaying metrics
 mod metrics;
 
 //entrypoint
 fn main() -> Result<(), String> {
 
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{}", config::usage());
+        return Ok(());
+    }
+
+    //load .env variables, if there are any, to be read as settings from the environment
+    let _ = dotenv();
+
+    //read the settings from config.toml, the environment and the command line, refusing to start if any are wrong
+    let config = match config::Config::load(args) {
+        Ok(config) => config,
+        Err(why) => {
+            eprintln!("{}", why);
+            std::process::exit(1);
+        }
+    };
+
     metrics::begin_startup();
-    
-    //load .env variables
-    dotenv().expect("file should load: /server/.env");
-
-    //default host address: localhost:3000
-    let host_address = format!(
-        "127.0.0.1:{}",
-        env::var("SERVER_PORT").expect("SERVER_PORT value in .env file")
-    );
-
-    if env::var("SECRET").expect("SECRET string in .env required!").is_empty() {
-        panic!("SECRET string in .env required!")
+
+    //optional, serve HTTPS with the certificate chain and private key in these PEM files (reloaded from them on SIGHUP)
+    let certificates = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => match tls::Certificates::load(cert.clone(), key.clone()) {
+            Ok(certificates) => Some(certificates),
+            Err(why) => {
+                eprintln!("{}", why);
+                std::process::exit(1);
+            }
+        },
+        _ => None,
     };
 
-    let mut server = server::Server::new(host_address);
+    config::init(config);
+
+    let mut server = server::Server::new(config::get(), certificates);
 
     server.listen()
-}
\ No newline at end of file
+}
@@ -0,0 +1,215 @@
+use serde::{self, Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::endpoints::{database, users};
+
+//Role: what a member can do with a shared budget
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    //everything, including inviting and removing members. every budget has exactly one
+    Owner,
+    //run commands, import statements and pay from the budget
+    Editor,
+    //only read the budget and its history
+    Viewer,
+}
+impl Role {
+    //as_str(): the role as it's stored in the members and invites tables
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    //parse(): reads a role back out of the members and invites tables
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "owner" => Some(Role::Owner),
+            "editor" => Some(Role::Editor),
+            "viewer" => Some(Role::Viewer),
+            _ => None,
+        }
+    }
+
+    //can_edit(): whether the role can change the budget
+    pub fn can_edit(self) -> bool {
+        self != Role::Viewer
+    }
+}
+
+//Member: a user with access to a budget
+#[derive(Debug, Serialize, Clone)]
+pub struct Member {
+    #[serde(skip)]
+    pub user: Uuid,
+    pub username: String,
+    pub role: Role,
+}
+
+//Invite: an invitation for a user to join a budget, waiting on them to accept it
+#[derive(Debug, Serialize, Clone)]
+pub struct Invite {
+    pub budget: Uuid,
+    //the invited user
+    pub username: String,
+    //the budget's owner
+    pub owner: String,
+    pub role: Role,
+}
+
+//Membership: a budget a user belongs to, as listed by GET /user/budgets
+#[derive(Debug, Serialize)]
+pub struct Membership {
+    pub budget: Uuid,
+    pub owner: String,
+    pub role: Role,
+    //whether it's the budget the session is working on
+    pub current: bool,
+}
+
+//MembersList: the json body sent back for GET /user/members
+#[derive(Debug, Serialize)]
+pub struct MembersList {
+    pub members: Vec<Member>,
+    pub invites: Vec<Invite>,
+}
+
+//BudgetsList: the json body sent back for GET /user/budgets
+#[derive(Debug, Serialize)]
+pub struct BudgetsList {
+    pub budgets: Vec<Membership>,
+    pub invites: Vec<Invite>,
+}
+
+//MembersRequest: what an owner can send to POST /user/members, picked by its "action" field
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum MembersRequest {
+    Invite { username: String, role: Role },
+    //cancel: takes back an invitation that hasn't been accepted yet
+    Cancel { username: String },
+    Remove { username: String },
+    Role { username: String, role: Role },
+}
+
+//BudgetsRequest: what a user can send to POST /user/budgets, picked by its "action" field
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum BudgetsRequest {
+    Accept { budget: Uuid },
+    Decline { budget: Uuid },
+    //switch: points the session at another budget the user belongs to
+    Switch { budget: Uuid },
+    Leave { budget: Uuid },
+}
+
+//MemberChange: how a members request changed someone's access, so their sessions can follow
+pub enum MemberChange {
+    None,
+    Removed(Uuid),
+    Role(Uuid, Role),
+}
+
+//find_user(): the id of the user with the given username
+fn find_user(username: &str) -> Result<Uuid, String> {
+    users::get_user_auth_row(username.to_string())
+        .map(|row| row.uuid)
+        .map_err(|_err| String::from("user_not_found"))
+}
+
+//manage(): runs an owner's members request against their budget
+pub fn manage(budget: Uuid, request: MembersRequest) -> Result<(String, MemberChange), String> {
+    match request {
+        MembersRequest::Invite { username, role } => {
+            let user = find_user(&username)?;
+            if role == Role::Owner {
+                return Err(String::from("invalid_role"));
+            }
+            if database::get_member(budget, user).is_some() {
+                return Err(String::from("already_a_member"));
+            }
+            database::add_invite(budget, user, role)?;
+            Ok((format!("{} invited as {}", username, role.as_str()), MemberChange::None))
+        }
+        MembersRequest::Cancel { username } => {
+            let user = find_user(&username)?;
+            if !database::remove_invite(budget, user)? {
+                return Err(String::from("invite_not_found"));
+            }
+            Ok((format!("Invitation for {} cancelled", username), MemberChange::None))
+        }
+        MembersRequest::Remove { username } => {
+            let user = find_user(&username)?;
+            let member = database::get_member(budget, user).ok_or_else(|| String::from("member_not_found"))?;
+            if member.role == Role::Owner {
+                return Err(String::from("cannot_remove_owner"));
+            }
+            database::remove_member(budget, user)?;
+            Ok((format!("{} removed", member.username), MemberChange::Removed(user)))
+        }
+        MembersRequest::Role { username, role } => {
+            let user = find_user(&username)?;
+            let member = database::get_member(budget, user).ok_or_else(|| String::from("member_not_found"))?;
+            if member.role == Role::Owner || role == Role::Owner {
+                return Err(String::from("invalid_role"));
+            }
+            database::set_member_role(budget, user, role)?;
+            Ok((format!("{} is now {}", member.username, role.as_str()), MemberChange::Role(user, role)))
+        }
+    }
+}
+
+//respond(): runs a user's request about the budgets they belong to (or are invited to)
+//returns the budget the session (currently on the given budget) should move to, if it should move
+pub fn respond(user: Uuid, current: Uuid, request: BudgetsRequest) -> Result<(String, Option<Uuid>), String> {
+    match request {
+        BudgetsRequest::Accept { budget } => {
+            let role = database::take_invite(budget, user)?.ok_or_else(|| String::from("invite_not_found"))?;
+            database::add_member(budget, user, role)?;
+            Ok((String::from("Invitation accepted"), None))
+        }
+        BudgetsRequest::Decline { budget } => {
+            if !database::remove_invite(budget, user)? {
+                return Err(String::from("invite_not_found"));
+            }
+            Ok((String::from("Invitation declined"), None))
+        }
+        BudgetsRequest::Switch { budget } => {
+            if database::get_member(budget, user).is_none() {
+                return Err(String::from("budget_not_found"));
+            }
+            Ok((String::from("Switched budgets"), Some(budget)))
+        }
+        BudgetsRequest::Leave { budget } => {
+            let member = database::get_member(budget, user).ok_or_else(|| String::from("budget_not_found"))?;
+            if member.role == Role::Owner {
+                return Err(String::from("owner_cannot_leave"));
+            }
+            database::remove_member(budget, user)?;
+            let moved = (budget == current).then(|| database::default_budget(user)).transpose()?;
+            Ok((String::from("Left the budget"), moved))
+        }
+    }
+}
+
+//budgets_of(): every budget a user belongs to and every invitation waiting on them
+pub fn budgets_of(user: Uuid, current: Uuid) -> BudgetsList {
+    BudgetsList {
+        budgets: database::get_memberships(user)
+            .into_iter()
+            .map(|(budget, owner, role)| Membership { budget, owner, role, current: budget == current })
+            .collect(),
+        invites: database::get_invites(None, Some(user)),
+    }
+}
+
+//members_of(): everyone with access to a budget and everyone invited to it
+pub fn members_of(budget: Uuid) -> MembersList {
+    MembersList {
+        members: database::get_members(budget),
+        invites: database::get_invites(Some(budget), None),
+    }
+}
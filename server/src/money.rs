@@ -0,0 +1,265 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{self, Deserialize};
+
+//the largest amount a single value can be, one trillion dollars in cents
+//balances and other totals are held within it too, adding to them through add_cents() and sub_cents(),
+//which turn down anything past it, so working with a few of them at once can't overflow an i64
+pub const MAX_CENTS: i64 = 100_000_000_000_000;
+
+//add_cents(): adds an amount to a total, failing with amount_too_large if the total would pass MAX_CENTS either way
+pub fn add_cents(total: i64, cents: i64) -> Result<i64, String> {
+    total
+        .checked_add(cents)
+        .filter(|total| total.abs() <= MAX_CENTS)
+        .ok_or_else(|| String::from(AMOUNT_TOO_LARGE))
+}
+
+//sub_cents(): takes an amount off a total, failing with amount_too_large if the total would pass MAX_CENTS either way
+pub fn sub_cents(total: i64, cents: i64) -> Result<i64, String> {
+    total
+        .checked_sub(cents)
+        .filter(|total| total.abs() <= MAX_CENTS)
+        .ok_or_else(|| String::from(AMOUNT_TOO_LARGE))
+}
+
+//the error for anything that isn't an amount of money
+pub const INVALID_AMOUNT: &str = "invalid_amount_value";
+//the error for an amount bigger than MAX_CENTS
+pub const AMOUNT_TOO_LARGE: &str = "amount_too_large";
+
+//Money: an exact amount of money in cents, parsed from decimal text without going through floats
+//amounts with more than two decimal places are rounded to the nearest cent, halves away from zero
+//sent as either a json number (12.5) or a string ("$1,234.50", "-5", "(12.00)")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Deserialize)]
+#[serde(try_from = "RawMoney")]
+pub struct Money(pub i64);
+
+//RawMoney: an amount as it comes in from json, before it's been checked
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum RawMoney {
+    Number(f64),
+    Text(String),
+}
+impl TryFrom<RawMoney> for Money {
+    type Error = String;
+
+    fn try_from(raw: RawMoney) -> Result<Money, String> {
+        match raw {
+            RawMoney::Number(dollars) => Money::from_f64(dollars),
+            RawMoney::Text(text) => Money::parse(&text),
+        }
+    }
+}
+
+impl Money {
+    //parse(): reads an amount of dollars, like 19.99, -$5, $-5, 1,234.56 or (12.00) for a negative one
+    //thousands separators have to be in groups of three
+    pub fn parse(text: &str) -> Result<Money, String> {
+        let invalid = || String::from(INVALID_AMOUNT);
+        let mut text = text.trim();
+
+        //accounting style, (12.00) is -12.00
+        let mut negative = false;
+        if let Some(inner) = text.strip_prefix('(').and_then(|inner| inner.strip_suffix(')')) {
+            negative = true;
+            text = inner.trim();
+        }
+        //the sign can go on either side of the dollar sign, but only once
+        let mut sign = strip_sign(&mut text);
+        text = text.strip_prefix('$').map_or(text, str::trim_start);
+        if sign.is_none() {
+            sign = strip_sign(&mut text);
+        }
+        match sign {
+            Some(_) if negative => return Err(invalid()),
+            Some(minus) => negative = minus,
+            None => {}
+        }
+
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        if !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let digits = whole_digits(whole).ok_or_else(invalid)?;
+
+        let mut cents: i64 = 0;
+        for digit in digits.chars().chain(fraction.chars().chain("00".chars()).take(2)) {
+            cents = cents
+                .checked_mul(10)
+                .and_then(|cents| cents.checked_add(i64::from(digit as u8 - b'0')))
+                .filter(|cents| *cents <= MAX_CENTS)
+                .ok_or_else(|| String::from(AMOUNT_TOO_LARGE))?;
+        }
+        //round on the first digit past the cents
+        if fraction.chars().nth(2).is_some_and(|digit| digit >= '5') {
+            cents += 1;
+            if cents > MAX_CENTS {
+                return Err(String::from(AMOUNT_TOO_LARGE));
+            }
+        }
+
+        Ok(Money(if negative { -cents } else { cents }))
+    }
+
+    //from_f64(): reads an amount of dollars sent as a json number
+    //goes through the shortest decimal text for the float, so 19.99 is 1999 cents rather than 1998
+    pub fn from_f64(dollars: f64) -> Result<Money, String> {
+        if !dollars.is_finite() {
+            return Err(String::from(INVALID_AMOUNT));
+        }
+        Money::parse(&dollars.to_string())
+    }
+}
+
+//strip_sign(): takes a leading + or - off an amount, returning whether it was a minus
+fn strip_sign(text: &mut &str) -> Option<bool> {
+    let minus = text.starts_with('-');
+    let rest = text.strip_prefix(['-', '+'])?;
+    *text = rest;
+    Some(minus)
+}
+
+//whole_digits(): the digits of the whole dollar part of an amount, checking any thousands separators
+fn whole_digits(whole: &str) -> Option<String> {
+    let mut groups = whole.split(',');
+    let first = groups.next()?;
+    if !first.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let mut digits = String::from(first);
+    if whole.contains(',') {
+        if first.is_empty() || first.len() > 3 {
+            return None;
+        }
+        for group in groups {
+            if group.len() != 3 || !group.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            digits.push_str(group);
+        }
+    }
+    Some(digits)
+}
+
+impl FromStr for Money {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Money, String> {
+        Money::parse(text)
+    }
+}
+
+//Display: a plain decimal amount (-12.34), for spreadsheets and anything else that reads numbers back in
+//currency::format_money() writes amounts for people
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        write!(f, "{}{}.{:02}", sign, self.0.unsigned_abs() / 100, self.0.unsigned_abs() % 100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currency::{self, Locale};
+    use proptest::prelude::*;
+
+    //any amount a budget can hold
+    fn cents() -> impl Strategy<Value = i64> {
+        -MAX_CENTS..=MAX_CENTS
+    }
+
+    #[test]
+    fn parses_exactly() {
+        assert_eq!(Money::parse("19.99"), Ok(Money(1999)));
+        assert_eq!(Money::from_f64(19.99), Ok(Money(1999)));
+        assert_eq!(Money::from_f64(0.29), Ok(Money(29)));
+        assert_eq!(Money::parse("$1,234,567.8"), Ok(Money(123456780)));
+        assert_eq!(Money::parse("-$5"), Ok(Money(-500)));
+        assert_eq!(Money::parse("$-5"), Ok(Money(-500)));
+        assert_eq!(Money::parse("(12.00)"), Ok(Money(-1200)));
+        assert_eq!(Money::parse(".5"), Ok(Money(50)));
+        assert_eq!(Money::parse("5."), Ok(Money(500)));
+        assert_eq!(Money::parse(" -$ 1,000 "), Ok(Money(-100000)));
+    }
+
+    #[test]
+    fn rounds_half_away_from_zero() {
+        assert_eq!(Money::parse("0.005"), Ok(Money(1)));
+        assert_eq!(Money::parse("0.00499"), Ok(Money(0)));
+        assert_eq!(Money::parse("-0.005"), Ok(Money(-1)));
+        assert_eq!(Money::parse("2.675"), Ok(Money(268)));
+    }
+
+    #[test]
+    fn rejects_bad_amounts() {
+        for text in ["", " ", "$", ".", "-", "abc", "1.2.3", "12,34", "1234,567", ",123", "1,23,456", "--5", "-$-5", "-(5)", "5-", "1e5", "NaN"] {
+            assert_eq!(Money::parse(text), Err(String::from(INVALID_AMOUNT)), "{:?}", text);
+        }
+        assert_eq!(Money::from_f64(f64::NAN), Err(String::from(INVALID_AMOUNT)));
+        assert_eq!(Money::from_f64(f64::INFINITY), Err(String::from(INVALID_AMOUNT)));
+        assert_eq!(Money::parse("1000000000000.01"), Err(String::from(AMOUNT_TOO_LARGE)));
+        assert_eq!(Money::parse("99999999999999999999999"), Err(String::from(AMOUNT_TOO_LARGE)));
+        assert_eq!(Money::from_f64(1e300), Err(String::from(AMOUNT_TOO_LARGE)));
+    }
+
+    proptest! {
+        #[test]
+        fn display_round_trips(cents in cents()) {
+            prop_assert_eq!(Money::parse(&Money(cents).to_string()), Ok(Money(cents)));
+        }
+
+        #[test]
+        fn formatted_round_trips(cents in cents()) {
+            let text = currency::format_money(cents, "USD", Locale::EnUs);
+            prop_assert_eq!(Money::parse(&text), Ok(Money(cents)));
+        }
+
+        #[test]
+        fn floats_round_trip(cents in cents()) {
+            prop_assert_eq!(Money::from_f64(cents as f64 / 100.0), Ok(Money(cents)));
+        }
+
+        #[test]
+        fn extra_decimals_round(cents in 0..MAX_CENTS, extra in "[0-9]{1,6}") {
+            let text = format!("{}{}", Money(cents), extra);
+            let rounded = if extra.starts_with(['5', '6', '7', '8', '9']) { cents + 1 } else { cents };
+            prop_assert_eq!(Money::parse(&text), Ok(Money(rounded)));
+            prop_assert_eq!(Money::parse(&format!("-{}", text)), Ok(Money(-rounded)));
+        }
+
+        #[test]
+        fn too_large_is_an_error(cents in (MAX_CENTS + 1)..i64::MAX) {
+            prop_assert_eq!(Money::parse(&Money(cents).to_string()), Err(String::from(AMOUNT_TOO_LARGE)));
+        }
+
+        #[test]
+        fn totals_stay_in_range(total in cents(), cents in cents()) {
+            for result in [add_cents(total, cents), sub_cents(total, cents)] {
+                match result {
+                    Ok(total) => prop_assert!(total.abs() <= MAX_CENTS),
+                    Err(code) => prop_assert_eq!(code, AMOUNT_TOO_LARGE),
+                }
+            }
+            prop_assert_eq!(add_cents(total, cents).is_ok(), (total + cents).abs() <= MAX_CENTS);
+        }
+
+        #[test]
+        fn never_panics(text in "\\PC*") {
+            let _ = Money::parse(&text);
+        }
+
+        #[test]
+        fn never_panics_on_amount_like_text(text in "[-+$(),.0-9 ]{0,24}") {
+            if let Ok(Money(cents)) = Money::parse(&text) {
+                prop_assert!(cents.abs() <= MAX_CENTS);
+            }
+        }
+    }
+}
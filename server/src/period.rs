@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+
+use serde::{self, Deserialize, Serialize};
+
+//PayPeriod: running totals for the pay period that is currently open
+//a period opens when the user gets paid, and closes the next time they get paid
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PayPeriod {
+    pub number: u32,
+    pub started: i64,
+    pub income: i64,
+    pub saved: i64,
+}
+impl PayPeriod {
+    //open(): starts a fresh period with the given number, as of right now
+    pub fn open(number: u32) -> PayPeriod {
+        PayPeriod {
+            number,
+            started: chrono::Utc::now().timestamp(),
+            income: 0,
+            saved: 0,
+        }
+    }
+}
+impl Default for PayPeriod {
+    fn default() -> PayPeriod {
+        PayPeriod::open(0)
+    }
+}
+
+//CategorySnapshot: how one expense category ended up at the close of a period
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CategorySnapshot {
+    pub expected: i64,
+    pub actual: i64,
+}
+
+//PeriodSnapshot: the archived record of a closed pay period
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PeriodSnapshot {
+    pub number: u32,
+    pub started: i64,
+    pub ended: i64,
+    pub income: i64,
+    pub categories: BTreeMap<String, CategorySnapshot>,
+    pub saved: i64,
+    pub ending_balance: i64,
+}
+impl PeriodSnapshot {
+    //spent(): total of every category's actual spending in the period
+    pub fn spent(&self) -> i64 {
+        self.categories.values().map(|category| category.actual).sum()
+    }
+}
+
+//CategoryComparison: one category's spending across two periods
+#[derive(Debug, Serialize)]
+pub struct CategoryComparison {
+    pub first: i64,
+    pub second: i64,
+    pub delta: i64,
+}
+
+//PeriodComparison: the json body sent back when comparing two archived periods
+//every delta is (second - first)
+#[derive(Debug, Serialize)]
+pub struct PeriodComparison {
+    pub first: u32,
+    pub second: u32,
+    pub income_delta: i64,
+    pub spent_delta: i64,
+    pub saved_delta: i64,
+    pub ending_balance_delta: i64,
+    pub categories: BTreeMap<String, CategoryComparison>,
+}
+impl PeriodComparison {
+    //new(): compares two snapshots, category by category
+    //categories that only exist in one of the periods count as 0 in the other
+    pub fn new(first: &PeriodSnapshot, second: &PeriodSnapshot) -> PeriodComparison {
+        let mut categories = BTreeMap::new();
+        for name in first.categories.keys().chain(second.categories.keys()) {
+            let a = first.categories.get(name).map_or(0, |c| c.actual);
+            let b = second.categories.get(name).map_or(0, |c| c.actual);
+            categories.insert(
+                name.clone(),
+                CategoryComparison {
+                    first: a,
+                    second: b,
+                    delta: b - a,
+                },
+            );
+        }
+
+        PeriodComparison {
+            first: first.number,
+            second: second.number,
+            income_delta: second.income - first.income,
+            spent_delta: second.spent() - first.spent(),
+            saved_delta: second.saved - first.saved,
+            ending_balance_delta: second.ending_balance - first.ending_balance,
+            categories,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::Budget;
+    use crate::history::History;
+
+    //pay_day(): gets paid the way the getpaid command does, archiving the period that just ended
+    fn pay_day(budget: &mut Budget, history: &mut History) {
+        history.archive_period(budget.close_period());
+        budget.get_paid(None).unwrap();
+    }
+
+    //snapshot(): an archived period with the given income and category spending
+    fn snapshot(number: u32, income: i64, categories: &[(&str, i64)]) -> PeriodSnapshot {
+        PeriodSnapshot {
+            number,
+            started: 0,
+            ended: 0,
+            income,
+            categories: categories
+                .iter()
+                .map(|(name, actual)| (name.to_string(), CategorySnapshot { expected: 0, actual: *actual }))
+                .collect(),
+            saved: 0,
+            ending_balance: income - categories.iter().map(|(_, actual)| actual).sum::<i64>(),
+        }
+    }
+
+    #[test]
+    fn getting_paid_archives_the_period_and_starts_the_next() {
+        let mut budget = Budget::new(String::from("sam"));
+        let mut history = History::default();
+        budget.set_income(10000).unwrap();
+        pay_day(&mut budget, &mut history);
+        budget.add_expense("rent", 5000, None);
+        budget.add_expense("food", 3000, None);
+        budget.make_dynamic_payment(None, "rent", 2000).unwrap();
+        budget.save(None, 1000, None).unwrap();
+        pay_day(&mut budget, &mut history);
+
+        let periods = history.periods();
+        assert_eq!(periods.iter().map(|period| period.number).collect::<Vec<_>>(), [1, 0]);
+        let closed = periods[0];
+        assert_eq!((closed.income, closed.spent(), closed.saved, closed.ending_balance), (10000, 2000, 1000, 8000));
+        assert_eq!((closed.categories["rent"].expected, closed.categories["rent"].actual), (5000, 2000));
+        assert_eq!(closed.categories["food"].actual, 0);
+
+        //the new period starts from nothing spent, with this paycheck as its income
+        let open = budget.close_period();
+        assert_eq!((open.number, open.income, open.saved, open.spent()), (2, 10000, 0, 0));
+    }
+
+    #[test]
+    fn archived_expenses_are_left_out_of_the_snapshot() {
+        let mut budget = Budget::new(String::from("sam"));
+        let mut history = History::default();
+        budget.add_expense("gym", 2000, None);
+        budget.archive_expense("gym", true).unwrap();
+        pay_day(&mut budget, &mut history);
+        assert!(history.periods()[0].categories.is_empty());
+    }
+
+    #[test]
+    fn comparisons_count_missing_categories_as_nothing_spent() {
+        let first = snapshot(1, 1000, &[("rent", 500), ("gym", 50)]);
+        let second = snapshot(2, 1200, &[("rent", 500), ("food", 300)]);
+        let comparison = PeriodComparison::new(&first, &second);
+
+        assert_eq!((comparison.first, comparison.second), (1, 2));
+        assert_eq!((comparison.income_delta, comparison.spent_delta, comparison.ending_balance_delta), (200, 250, -50));
+        let deltas: Vec<(&str, i64, i64, i64)> = comparison
+            .categories
+            .iter()
+            .map(|(name, category)| (name.as_str(), category.first, category.second, category.delta))
+            .collect();
+        assert_eq!(deltas, [("food", 0, 300, 300), ("gym", 50, 0, -50), ("rent", 500, 500, 0)]);
+    }
+
+    #[test]
+    fn compares_archived_periods_by_number() {
+        let mut history = History::default();
+        history.archive_period(snapshot(0, 0, &[]));
+        history.archive_period(snapshot(1, 1000, &[("rent", 500)]));
+        assert_eq!(history.compare_periods(1, 0).map(|comparison| comparison.spent_delta), Some(-500));
+        assert!(history.compare_periods(0, 7).is_none());
+    }
+}
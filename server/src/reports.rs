@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, TimeZone};
+use serde::{self, Serialize};
+
+use crate::budget::Budget;
+use crate::category;
+use crate::history::{EntryKind, LedgerEntry};
+
+//how many labels are listed in a report's top_labels
+const TOP_LABEL_COUNT: usize = 10;
+
+//Totals: money in, out and put away over some stretch of time, in cents of the base currency
+#[derive(Debug, Serialize, Default, Clone, Copy)]
+pub struct Totals {
+    pub income: i64,
+    pub spent: i64,
+    pub saved: i64,
+}
+impl Totals {
+    //add(): counts one ledger entry towards the totals, given its amount in the base currency
+    fn add(&mut self, entry: &LedgerEntry, amount: i64) {
+        match entry.kind {
+            EntryKind::Paycheck | EntryKind::Deposit => self.income += amount,
+            EntryKind::Payment => self.spent += amount,
+            EntryKind::Save => self.saved += amount,
+            _ => {}
+        }
+    }
+}
+
+//CategoryTotal: spending on one expense category (or one label) over the report's range
+//count goes down again for undone payments, the same way spent does
+#[derive(Debug, Serialize, Default, Clone)]
+pub struct CategoryTotal {
+    pub name: String,
+    pub spent: i64,
+    pub count: i64,
+}
+
+//MonthReport: one calendar month of the report, with how it changed from the month before
+#[derive(Debug, Serialize)]
+pub struct MonthReport {
+    pub month: String,
+    pub totals: Totals,
+    pub categories: BTreeMap<String, i64>,
+    //None for the first month of the report
+    pub spent_delta: Option<i64>,
+    pub category_deltas: Option<BTreeMap<String, i64>>,
+}
+
+//Variance: how the current pay period's spending on a category compares with what was expected
+#[derive(Debug, Serialize)]
+pub struct Variance {
+    pub category: String,
+    pub expected: i64,
+    pub actual: i64,
+    //expected - actual, negative when over budget
+    pub variance: i64,
+    pub percent_used: Option<f64>,
+}
+
+//Report: the json body sent back for /user/reports, laid out for charting
+//every amount is converted into the base currency
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub currency: String,
+    pub totals: Totals,
+    //biggest spending first, by the name each category goes by now
+    pub categories: Vec<CategoryTotal>,
+    //categories rolled up into their groups, biggest first
+    pub groups: Vec<CategoryTotal>,
+    //spending on each tag, biggest first. a payment with several tags counts towards each
+    pub tags: Vec<CategoryTotal>,
+    //oldest first
+    pub months: Vec<MonthReport>,
+    //the biggest merchants (payees of imported rows) or expense labels, biggest first
+    pub top_labels: Vec<CategoryTotal>,
+    pub variance: Vec<Variance>,
+}
+
+//entry_date(): the local day a ledger entry was recorded on
+fn entry_date(entry: &LedgerEntry) -> Option<NaiveDate> {
+    Local
+        .timestamp_opt(entry.timestamp, 0)
+        .single()
+        .map(|time| time.date_naive())
+}
+
+//month_key(): the month a date falls in, as YYYY-MM
+fn month_key(date: NaiveDate) -> String {
+    format!("{:04}-{:02}", date.year(), date.month())
+}
+
+//count(): +1 for an entry, -1 for an entry reversing an undone one
+fn count(entry: &LedgerEntry) -> i64 {
+    entry.amount.signum()
+}
+
+//since(): the time the first entry a report from the given day can cover was recorded at, at the earliest,
+//so only the ledger from then on has to be loaded. later renames are still in it, so labels come out the same
+pub fn since(from: NaiveDate) -> Option<i64> {
+    Local
+        .from_local_datetime(&from.and_time(NaiveTime::MIN))
+        .earliest()
+        .map(|time| time.timestamp())
+}
+
+//build(): computes a report over every ledger entry from one day to another, both included,
+//out of the ledger from since(from) on (or the whole of it)
+pub fn build(budget: &Budget, ledger: &[LedgerEntry], from: NaiveDate, to: NaiveDate) -> Report {
+    let entries: Vec<(NaiveDate, &LedgerEntry, String)> = ledger
+        .iter()
+        .zip(category::current_labels(ledger))
+        .filter_map(|(entry, label)| Some((entry_date(entry)?, entry, label)))
+        .filter(|(date, _, _)| *date >= from && *date <= to)
+        .collect();
+
+    let mut totals = Totals::default();
+    let mut categories: BTreeMap<String, CategoryTotal> = BTreeMap::new();
+    let mut groups: BTreeMap<String, CategoryTotal> = BTreeMap::new();
+    let mut tags: BTreeMap<String, CategoryTotal> = BTreeMap::new();
+    let mut labels: BTreeMap<String, CategoryTotal> = BTreeMap::new();
+    let mut months: BTreeMap<String, (Totals, BTreeMap<String, i64>)> = BTreeMap::new();
+
+    for (date, entry, label) in entries {
+        let amount = budget.base_amount(entry.amount, entry.currency.as_deref());
+        totals.add(entry, amount);
+
+        let month = months.entry(month_key(date)).or_default();
+        month.0.add(entry, amount);
+
+        if entry.kind != EntryKind::Payment {
+            continue;
+        }
+
+        *month.1.entry(label.clone()).or_default() += amount;
+
+        let category = categories.entry(label.clone()).or_default();
+        category.spent += amount;
+        category.count += count(entry);
+
+        let group = groups.entry(category::group_of(budget.categories(), &label)).or_default();
+        group.spent += amount;
+        group.count += count(entry);
+
+        for tag in &entry.tags {
+            let tag = tags.entry(tag.clone()).or_default();
+            tag.spent += amount;
+            tag.count += count(entry);
+        }
+
+        let name = entry.memo.clone().unwrap_or(label);
+        let label = labels.entry(name.to_ascii_lowercase()).or_default();
+        label.spent += amount;
+        label.count += count(entry);
+    }
+
+    Report {
+        from,
+        to,
+        currency: budget.base_currency().to_string(),
+        totals,
+        categories: ranked(categories, usize::MAX),
+        groups: ranked(groups, usize::MAX),
+        tags: ranked(tags, usize::MAX),
+        months: month_reports(months),
+        top_labels: ranked(labels, TOP_LABEL_COUNT),
+        variance: variance(budget),
+    }
+}
+
+//ranked(): names each total by its key, biggest spending first, keeping at most limit of them
+fn ranked(totals: BTreeMap<String, CategoryTotal>, limit: usize) -> Vec<CategoryTotal> {
+    let mut totals: Vec<CategoryTotal> = totals
+        .into_iter()
+        .map(|(name, total)| CategoryTotal { name, ..total })
+        .collect();
+    totals.sort_by(|a, b| b.spent.cmp(&a.spent).then_with(|| a.name.cmp(&b.name)));
+    totals.truncate(limit);
+    totals
+}
+
+//month_reports(): turns the per-month sums into MonthReports, with deltas from each previous month
+fn month_reports(months: BTreeMap<String, (Totals, BTreeMap<String, i64>)>) -> Vec<MonthReport> {
+    let mut reports: Vec<MonthReport> = Vec::new();
+
+    for (month, (totals, categories)) in months {
+        let (spent_delta, category_deltas) = match reports.last() {
+            Some(previous) => {
+                let mut deltas = BTreeMap::new();
+                for name in categories.keys().chain(previous.categories.keys()) {
+                    let now = categories.get(name).copied().unwrap_or(0);
+                    let before = previous.categories.get(name).copied().unwrap_or(0);
+                    deltas.insert(name.clone(), now - before);
+                }
+                (Some(totals.spent - previous.totals.spent), Some(deltas))
+            }
+            None => (None, None),
+        };
+
+        reports.push(MonthReport {
+            month,
+            totals,
+            categories,
+            spent_delta,
+            category_deltas,
+        });
+    }
+
+    reports
+}
+
+//variance(): budget vs actual for every expense category in the current pay period
+fn variance(budget: &Budget) -> Vec<Variance> {
+    budget
+        .expense_rows()
+        .into_iter()
+        .map(|(category, expected, actual)| Variance {
+            category,
+            expected,
+            actual,
+            variance: expected - actual,
+            percent_used: if expected > 0 {
+                Some(actual as f64 / expected as f64 * 100.0)
+            } else {
+                None
+            },
+        })
+        .collect()
+}
+
+//parse_range(): reads a report's date range from the rest of its path
+//nothing means this month so far, one date means from then until today
+pub fn parse_range(segments: &[&str]) -> Result<(NaiveDate, NaiveDate), String> {
+    let today = Local::now().date_naive();
+    let parse = |date: &str| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_err| String::from("invalid_report_date"))
+    };
+
+    let (from, to) = match segments {
+        [] => (today.with_day(1).unwrap(), today),
+        [from] => (parse(from)?, today),
+        [from, to] => (parse(from)?, parse(to)?),
+        _ => return Err(String::from("invalid_report_range")),
+    };
+
+    if from > to {
+        return Err(String::from("invalid_report_range"));
+    }
+    Ok((from, to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    //entry(): a ledger entry recorded at noon on the given day
+    fn entry(day: NaiveDate, kind: EntryKind, label: &str, amount: i64) -> LedgerEntry {
+        let mut entry = LedgerEntry::new(kind, None, label, amount, 0);
+        entry.timestamp = Local.from_local_datetime(&day.and_hms_opt(12, 0, 0).unwrap()).unwrap().timestamp();
+        entry
+    }
+
+    //names(): the names and totals in a ranked list
+    fn names(totals: &[CategoryTotal]) -> Vec<(&str, i64, i64)> {
+        totals.iter().map(|total| (total.name.as_str(), total.spent, total.count)).collect()
+    }
+
+    #[test]
+    fn totals_spending_by_category_group_tag_and_payee() {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.add_expense("rent", 100000, None);
+        budget.add_expense("food", 30000, None);
+        budget.set_parent("rent", Some("housing")).unwrap();
+
+        let mut lunch = entry(date(2024, 1, 5), EntryKind::Payment, "food", 1500);
+        lunch.tags = vec![String::from("work"), String::from("lunch")];
+        let mut grocer = entry(date(2024, 1, 6), EntryKind::Payment, "food", 4000);
+        grocer.memo = Some(String::from("GROCER"));
+        let ledger = [
+            entry(date(2023, 12, 31), EntryKind::Payment, "food", 999),
+            entry(date(2024, 1, 1), EntryKind::Paycheck, "income", 200000),
+            entry(date(2024, 1, 2), EntryKind::Payment, "rent", 100000),
+            entry(date(2024, 1, 3), EntryKind::Save, "checking", 20000),
+            lunch,
+            grocer.clone(),
+            //the grocer payment, undone
+            LedgerEntry { amount: -4000, ..grocer },
+            entry(date(2024, 2, 1), EntryKind::Payment, "food", 500),
+        ];
+
+        let report = build(&budget, &ledger, date(2024, 1, 1), date(2024, 1, 31));
+        assert_eq!((report.totals.income, report.totals.spent, report.totals.saved), (200000, 101500, 20000));
+        assert_eq!(names(&report.categories), [("rent", 100000, 1), ("food", 1500, 1)]);
+        assert_eq!(names(&report.groups), [("housing", 100000, 1), ("food", 1500, 1)]);
+        assert_eq!(names(&report.tags), [("lunch", 1500, 1), ("work", 1500, 1)]);
+        assert_eq!(names(&report.top_labels), [("rent", 100000, 1), ("food", 1500, 1), ("grocer", 0, 0)]);
+    }
+
+    #[test]
+    fn compares_each_month_with_the_one_before() {
+        let budget = Budget::new(String::from("sam"));
+        let ledger = [
+            entry(date(2024, 1, 2), EntryKind::Payment, "rent", 1000),
+            entry(date(2024, 1, 9), EntryKind::Payment, "gym", 200),
+            entry(date(2024, 2, 2), EntryKind::Payment, "rent", 1100),
+            entry(date(2024, 2, 3), EntryKind::Payment, "food", 300),
+        ];
+        let report = build(&budget, &ledger, date(2024, 1, 1), date(2024, 2, 29));
+
+        let months: Vec<(&str, i64, Option<i64>)> = report.months.iter().map(|month| (month.month.as_str(), month.totals.spent, month.spent_delta)).collect();
+        assert_eq!(months, [("2024-01", 1200, None), ("2024-02", 1400, Some(200))]);
+        let deltas: Vec<(&str, i64)> = report.months[1].category_deltas.as_ref().unwrap().iter().map(|(name, delta)| (name.as_str(), *delta)).collect();
+        assert_eq!(deltas, [("food", 300), ("gym", -200), ("rent", 100)]);
+    }
+
+    #[test]
+    fn compares_this_periods_spending_with_what_was_expected() {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.add_expense("rent", 1000, None);
+        budget.add_expense("misc", 0, None);
+        budget.make_dynamic_payment(None, "rent", 1200).unwrap();
+        let report = build(&budget, &[], date(2024, 1, 1), date(2024, 1, 31));
+        let variance: Vec<(&str, i64, Option<f64>)> =
+            report.variance.iter().map(|row| (row.category.as_str(), row.variance, row.percent_used)).collect();
+        assert_eq!(variance, [("misc", 0, None), ("rent", -200, Some(120.0))]);
+    }
+
+    #[test]
+    fn reads_date_ranges() {
+        assert_eq!(parse_range(&["2024-01-01", "2024-01-31"]), Ok((date(2024, 1, 1), date(2024, 1, 31))));
+        assert_eq!(parse_range(&["2024-01-31", "2024-01-01"]), Err(String::from("invalid_report_range")));
+        assert_eq!(parse_range(&["a", "b", "c"]), Err(String::from("invalid_report_range")));
+        assert_eq!(parse_range(&["january"]), Err(String::from("invalid_report_date")));
+        let (from, to) = parse_range(&[]).unwrap();
+        assert_eq!((from.day(), to), (1, Local::now().date_naive()));
+    }
+}
@@ -0,0 +1,569 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read, Seek, Write};
+use std::path::PathBuf;
+
+use http_bytes::http::StatusCode;
+use uuid::Uuid;
+
+//the most headers a request can have
+pub const MAX_HEADERS: usize = 64;
+
+//the longest a chunk size line of a chunked body can be, extensions included
+const MAX_CHUNK_LINE_BYTES: u64 = 1024;
+
+//the interim response sent to clients waiting on "Expect: 100-continue" before they send the body
+const CONTINUE_RESPONSE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
+//Limits: how big a request can get before it's turned away
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    //the request line and headers together, 431 REQUEST HEADER FIELDS TOO LARGE past this
+    pub max_header_bytes: usize,
+    //the body, once any chunked encoding is decoded, 413 PAYLOAD TOO LARGE past this
+    pub max_body_bytes: usize,
+    //bodies bigger than this are written out to a temporary file while they're read, instead of held in memory
+    pub memory_body_bytes: usize,
+}
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_header_bytes: 4096,
+            //bank statement imports can get big
+            max_body_bytes: 4 * 1024 * 1024,
+            memory_body_bytes: 64 * 1024,
+        }
+    }
+}
+
+//RequestError: why a request couldn't be read
+#[derive(Debug)]
+pub enum RequestError {
+    //the client closed the connection (or it broke) partway through, so there's nobody to answer
+    Closed,
+    //the client went quiet for longer than the connection's read timeout
+    TimedOut,
+    //not a valid http request, with the error code sent back to the client
+    Malformed(&'static str),
+    HeadersTooLarge,
+    BodyTooLarge,
+    //an Expect header other than 100-continue
+    Expectation,
+    //a transfer coding other than chunked on its own, which can't be decoded
+    UnsupportedEncoding,
+    //the body couldn't be written out to (or read back from) its temporary file
+    Storage(io::Error),
+}
+impl RequestError {
+    //status(): the status code to answer with, none if the connection should just be dropped
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            RequestError::Closed => None,
+            RequestError::TimedOut => Some(StatusCode::REQUEST_TIMEOUT),
+            RequestError::Malformed(_) => Some(StatusCode::BAD_REQUEST),
+            RequestError::HeadersTooLarge => Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE),
+            RequestError::BodyTooLarge => Some(StatusCode::PAYLOAD_TOO_LARGE),
+            RequestError::Expectation => Some(StatusCode::EXPECTATION_FAILED),
+            RequestError::UnsupportedEncoding => Some(StatusCode::NOT_IMPLEMENTED),
+            RequestError::Storage(_) => Some(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+}
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Closed => write!(f, "connection closed"),
+            RequestError::TimedOut => write!(f, "timed out"),
+            RequestError::Malformed(code) => write!(f, "malformed request: {}", code),
+            RequestError::HeadersTooLarge => write!(f, "headers too large"),
+            RequestError::BodyTooLarge => write!(f, "body too large"),
+            RequestError::Expectation => write!(f, "unsupported expectation"),
+            RequestError::UnsupportedEncoding => write!(f, "unsupported transfer encoding"),
+            RequestError::Storage(why) => write!(f, "failed to store body: {}", why),
+        }
+    }
+}
+//reading errors: a timeout is the client's fault, anything else means the connection is gone
+impl From<io::Error> for RequestError {
+    fn from(why: io::Error) -> RequestError {
+        match why.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => RequestError::TimedOut,
+            _ => RequestError::Closed,
+        }
+    }
+}
+
+//Request: a request read off a connection, its head (request line and headers) still raw for httparse
+#[derive(Debug)]
+pub struct Request {
+    pub head: Vec<u8>,
+    pub body: Body,
+}
+
+//parse_head(): parses a request's head into the given headers, which always works since it was checked while reading
+pub fn parse_head<'h, 'b>(head: &'b [u8], headers: &'h mut [httparse::Header<'b>]) -> httparse::Request<'h, 'b> {
+    let mut req = httparse::Request::new(headers);
+    let _ = req.parse(head);
+    req
+}
+
+//Body: a request's body, decoded from any chunked encoding. small ones are held in memory,
+//anything past the memory limit is written out to a temporary file (deleted along with the body)
+#[derive(Debug, Default)]
+pub struct Body {
+    memory: Vec<u8>,
+    file: Option<SpilledBody>,
+}
+impl Body {
+    //size(): how many bytes the body is
+    pub fn size(&self) -> usize {
+        match &self.file {
+            Some(spilled) => spilled.len,
+            None => self.memory.len(),
+        }
+    }
+
+    //into_text(): the body as text, none if it's empty. a spilled body is read back from its file
+    pub fn into_text(mut self) -> Result<Option<String>, RequestError> {
+        let bytes = match self.file.as_mut() {
+            Some(spilled) => spilled.read_back().map_err(RequestError::Storage)?,
+            None => std::mem::take(&mut self.memory),
+        };
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(why) => String::from_utf8_lossy(why.as_bytes()).into_owned(),
+        }))
+    }
+
+    //copy(): reads exactly the given number of bytes of body off the reader, as long as the body stays within the limit
+    fn copy<R: Read>(&mut self, reader: &mut R, bytes: u64, limits: &Limits) -> Result<(), RequestError> {
+        if self.size() as u64 + bytes > limits.max_body_bytes as u64 {
+            return Err(RequestError::BodyTooLarge);
+        }
+        let mut buffer = [0u8; 8192];
+        let mut left = bytes;
+        while left > 0 {
+            let wanted = left.min(buffer.len() as u64) as usize;
+            let read = reader.read(&mut buffer[..wanted])?;
+            if read == 0 {
+                return Err(RequestError::Closed);
+            }
+            self.write(&buffer[..read], limits).map_err(RequestError::Storage)?;
+            left -= read as u64;
+        }
+        Ok(())
+    }
+
+    //write(): adds to the body, moving it out to a temporary file once it grows past the memory limit
+    fn write(&mut self, bytes: &[u8], limits: &Limits) -> Result<(), io::Error> {
+        if self.file.is_none() && self.memory.len() + bytes.len() > limits.memory_body_bytes {
+            let mut spilled = SpilledBody::create()?;
+            spilled.append(&std::mem::take(&mut self.memory))?;
+            self.file = Some(spilled);
+        }
+        match self.file.as_mut() {
+            Some(spilled) => spilled.append(bytes),
+            None => {
+                self.memory.extend_from_slice(bytes);
+                Ok(())
+            }
+        }
+    }
+}
+
+//SpilledBody: the temporary file a big body is written out to, removed when it's dropped
+#[derive(Debug)]
+struct SpilledBody {
+    path: PathBuf,
+    file: File,
+    len: usize,
+}
+impl SpilledBody {
+    //create(): makes a new, empty temporary file for a body
+    fn create() -> Result<SpilledBody, io::Error> {
+        let path = std::env::temp_dir().join(format!("budget-request-{}", Uuid::new_v4().simple()));
+        let file = File::options().read(true).write(true).create_new(true).open(&path)?;
+        Ok(SpilledBody { path, file, len: 0 })
+    }
+
+    fn append(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+        self.file.write_all(bytes)?;
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    //read_back(): everything written to the file so far
+    fn read_back(&mut self) -> Result<Vec<u8>, io::Error> {
+        let mut bytes = Vec::with_capacity(self.len);
+        self.file.rewind()?;
+        self.file.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+impl Drop for SpilledBody {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+//Framing: how the end of a request's body is found
+enum Framing {
+    Empty,
+    Length(u64),
+    Chunked,
+}
+
+//read_request(): reads one request off a connection, decoding a chunked body and answering "Expect: 100-continue"
+//on the given writer. stops right after the body, so anything past it (a pipelined request) is left in the reader
+pub fn read_request<R: BufRead, W: Write>(reader: &mut R, interim: &mut W, limits: &Limits) -> Result<Request, RequestError> {
+    let head = read_head(reader, limits)?;
+
+    //check the head and pull out what's needed to read the body
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut req = httparse::Request::new(&mut headers);
+    match req.parse(&head) {
+        Ok(httparse::Status::Complete(_)) => {}
+        Ok(httparse::Status::Partial) => return Err(RequestError::Malformed("incomplete_request")),
+        Err(httparse::Error::TooManyHeaders) => return Err(RequestError::HeadersTooLarge),
+        Err(_) => return Err(RequestError::Malformed("malformed_request")),
+    }
+    let framing = framing(&req, limits)?;
+
+    //a client expecting 100-continue waits to be told to send its body
+    let expect = req.headers.iter().find(|header| header.name.eq_ignore_ascii_case("expect"));
+    if let Some(expect) = expect {
+        if !expect.value.eq_ignore_ascii_case(b"100-continue") {
+            return Err(RequestError::Expectation);
+        }
+        if req.version == Some(1) && !matches!(framing, Framing::Empty) {
+            interim.write_all(CONTINUE_RESPONSE)?;
+            interim.flush()?;
+        }
+    }
+
+    let mut body = Body::default();
+    match framing {
+        Framing::Empty => {}
+        Framing::Length(length) => body.copy(reader, length, limits)?,
+        Framing::Chunked => read_chunks(reader, &mut body, limits)?,
+    }
+
+    Ok(Request { head, body })
+}
+
+//read_head(): reads the request line and headers, up to and including the blank line after them
+fn read_head<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Vec<u8>, RequestError> {
+    let mut head = Vec::new();
+    let mut skipped = 0;
+    loop {
+        let start = head.len();
+        let room = (limits.max_header_bytes - start.min(limits.max_header_bytes)) as u64 + 1;
+        let read = reader.by_ref().take(room).read_until(b'\n', &mut head)?;
+        if read == 0 {
+            return Err(RequestError::Closed);
+        }
+        if head.len() > limits.max_header_bytes {
+            return Err(RequestError::HeadersTooLarge);
+        }
+        if !head.ends_with(b"\n") {
+            return Err(RequestError::Closed);
+        }
+
+        let line = &head[start..];
+        if line == b"\r\n" || line == b"\n" {
+            //blank lines before the request line are skipped, the first one after it ends the head
+            if start == 0 {
+                skipped += head.len();
+                if skipped > limits.max_header_bytes {
+                    return Err(RequestError::HeadersTooLarge);
+                }
+                head.clear();
+                continue;
+            }
+            return Ok(head);
+        }
+    }
+}
+
+//framing(): works out how the body is sent, turning away ambiguous requests
+//(both a length and chunked, or two different lengths) that could be read differently by a proxy,
+//and any transfer coding but chunked on its own, since there's nothing here to decode the others
+fn framing(req: &httparse::Request, limits: &Limits) -> Result<Framing, RequestError> {
+    let mut length: Option<u64> = None;
+    let mut chunked = false;
+
+    for header in req.headers.iter() {
+        if header.name.eq_ignore_ascii_case("content-length") {
+            let value = std::str::from_utf8(header.value).map_err(|_err| RequestError::Malformed("invalid_content_length"))?;
+            let value = value.trim();
+            if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(RequestError::Malformed("invalid_content_length"));
+            }
+            let value = value.parse::<u64>().map_err(|_err| RequestError::BodyTooLarge)?;
+            if length.is_some_and(|length| length != value) {
+                return Err(RequestError::Malformed("invalid_content_length"));
+            }
+            length = Some(value);
+        } else if header.name.eq_ignore_ascii_case("transfer-encoding") {
+            if chunked {
+                return Err(RequestError::Malformed("repeated_transfer_encoding"));
+            }
+            if !header.value.trim_ascii().eq_ignore_ascii_case(b"chunked") {
+                return Err(RequestError::UnsupportedEncoding);
+            }
+            chunked = true;
+        }
+    }
+
+    match (length, chunked) {
+        (Some(_), true) => Err(RequestError::Malformed("conflicting_body_length")),
+        (Some(length), false) if length > limits.max_body_bytes as u64 => Err(RequestError::BodyTooLarge),
+        (Some(0) | None, false) => Ok(Framing::Empty),
+        (Some(length), false) => Ok(Framing::Length(length)),
+        (None, true) => Ok(Framing::Chunked),
+    }
+}
+
+//read_chunks(): decodes a chunked body, each chunk a hex size line followed by that many bytes,
+//ending with a zero size chunk and any trailer fields, which are read and thrown away
+fn read_chunks<R: BufRead>(reader: &mut R, body: &mut Body, limits: &Limits) -> Result<(), RequestError> {
+    loop {
+        let line = read_line(reader, MAX_CHUNK_LINE_BYTES)?;
+        let size = line.split(|byte| *byte == b';').next().unwrap_or_default();
+        let size = std::str::from_utf8(size)
+            .map(str::trim)
+            .map_err(|_err| RequestError::Malformed("invalid_chunk_size"))?;
+        if size.is_empty() || !size.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(RequestError::Malformed("invalid_chunk_size"));
+        }
+        let size = u64::from_str_radix(size, 16).map_err(|_err| RequestError::BodyTooLarge)?;
+
+        if size == 0 {
+            break;
+        }
+        body.copy(reader, size, limits)?;
+        if !read_line(reader, 2)?.is_empty() {
+            return Err(RequestError::Malformed("invalid_chunk"));
+        }
+    }
+
+    //trailer fields, up to a blank line, count towards the header limit
+    let mut trailers = 0;
+    loop {
+        let line = read_line(reader, limits.max_header_bytes as u64)?;
+        if line.is_empty() {
+            return Ok(());
+        }
+        trailers += line.len();
+        if trailers > limits.max_header_bytes {
+            return Err(RequestError::HeadersTooLarge);
+        }
+    }
+}
+
+//read_line(): reads a line of at most the given length, without its line ending (CRLF, or a bare LF)
+fn read_line<R: BufRead>(reader: &mut R, max: u64) -> Result<Vec<u8>, RequestError> {
+    let mut line = Vec::new();
+    //room for the line ending as well
+    reader.by_ref().take(max + 2).read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        return Err(if line.len() as u64 > max {
+            RequestError::Malformed("line_too_long")
+        } else {
+            RequestError::Closed
+        });
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::io::Cursor;
+
+    fn read(input: &[u8]) -> Result<(Request, Vec<u8>, Vec<u8>), RequestError> {
+        read_with(input, &Limits::default())
+    }
+
+    //read_with(): reads a request, returning it, what was written back, and whatever was left unread
+    fn read_with(input: &[u8], limits: &Limits) -> Result<(Request, Vec<u8>, Vec<u8>), RequestError> {
+        let mut reader = Cursor::new(input);
+        let mut interim = Vec::new();
+        let request = read_request(&mut reader, &mut interim, limits)?;
+        let rest = input[reader.position() as usize..].to_vec();
+        Ok((request, interim, rest))
+    }
+
+    fn body(request: Request) -> Vec<u8> {
+        let mut body = request.body;
+        match body.file.as_mut() {
+            Some(spilled) => spilled.read_back().unwrap(),
+            None => body.memory,
+        }
+    }
+
+    //chunk(): chunk encodes a body, split at the given sizes
+    fn chunk(body: &[u8], sizes: &[usize]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut rest = body;
+        for size in sizes.iter().copied().chain(std::iter::repeat(usize::MAX)) {
+            if rest.is_empty() {
+                break;
+            }
+            let (part, remaining) = rest.split_at(size.clamp(1, rest.len()));
+            out.extend_from_slice(format!("{:x}\r\n", part.len()).as_bytes());
+            out.extend_from_slice(part);
+            out.extend_from_slice(b"\r\n");
+            rest = remaining;
+        }
+        out.extend_from_slice(b"0\r\n\r\n");
+        out
+    }
+
+    #[test]
+    fn reads_a_content_length_body() {
+        let (request, interim, rest) = read(b"POST /user HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloGET / HTTP/1.1\r\n\r\n").unwrap();
+        assert!(interim.is_empty());
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(body(request), b"hello");
+    }
+
+    #[test]
+    fn reads_a_chunked_body() {
+        let input = b"POST /user HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;name=value\r\nhello\r\n1\r\n!\r\n0\r\nTrailer: yes\r\n\r\nnext";
+        let (request, _, rest) = read(input).unwrap();
+        assert_eq!(rest, b"next");
+        assert_eq!(body(request), b"hello!");
+    }
+
+    #[test]
+    fn answers_100_continue() {
+        let (request, interim, _) = read(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\nhi").unwrap();
+        assert_eq!(interim, CONTINUE_RESPONSE);
+        assert_eq!(body(request), b"hi");
+
+        //nothing to continue with, or a body that's too big to ever take
+        let (_, interim, _) = read(b"GET / HTTP/1.1\r\nExpect: 100-continue\r\n\r\n").unwrap();
+        assert!(interim.is_empty());
+        let limits = Limits { max_body_bytes: 1, ..Limits::default() };
+        let mut interim = Vec::new();
+        let result = read_request(&mut Cursor::new(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\n"), &mut interim, &limits);
+        assert!(matches!(result, Err(RequestError::BodyTooLarge)));
+        assert!(interim.is_empty());
+
+        assert!(matches!(read(b"GET / HTTP/1.1\r\nExpect: magic\r\n\r\n"), Err(RequestError::Expectation)));
+    }
+
+    #[test]
+    fn big_bodies_go_through_a_temporary_file() {
+        let limits = Limits { memory_body_bytes: 16, ..Limits::default() };
+        let text: String = (0..10_000).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+        let input = [b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".as_slice(), &chunk(text.as_bytes(), &[10, 5000])].concat();
+        let (request, _, _) = read_with(&input, &limits).unwrap();
+
+        let path = request.body.file.as_ref().expect("the body should have been spilled").path.clone();
+        assert!(path.exists());
+        assert_eq!(request.body.size(), text.len());
+        assert_eq!(request.body.into_text().unwrap(), Some(text));
+        assert!(!path.exists());
+
+        //small ones stay in memory
+        let (request, _, _) = read_with(b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi", &limits).unwrap();
+        assert!(request.body.file.is_none());
+    }
+
+    #[test]
+    fn only_decodes_chunked() {
+        for coding in ["gzip", "gzip, chunked", "chunked, chunked", "identity", "chunked;q=1"] {
+            let input = format!("POST / HTTP/1.1\r\nTransfer-Encoding: {}\r\n\r\n0\r\n\r\n", coding);
+            let error = read(input.as_bytes()).unwrap_err();
+            assert_eq!(error.status(), Some(StatusCode::NOT_IMPLEMENTED), "{}", coding);
+        }
+        let error = read(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n").unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+        let (request, _, _) = read(b"POST / HTTP/1.1\r\nTransfer-Encoding:  CHUNKED \r\n\r\n2\r\nhi\r\n0\r\n\r\n").unwrap();
+        assert_eq!(body(request), b"hi");
+    }
+
+    #[test]
+    fn maps_errors_to_status_codes() {
+        let limits = Limits { max_header_bytes: 72, max_body_bytes: 8, ..Limits::default() };
+        let cases: &[(&[u8], Option<StatusCode>)] = &[
+            (b"", None),
+            (b"GET / HTTP/1.1\r\nHost: x", None),
+            (b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhi", None),
+            (b"NOT A REQUEST\r\n\r\n", Some(StatusCode::BAD_REQUEST)),
+            (b"POST / HTTP/1.1\r\nContent-Length: 12abc\r\n\r\n", Some(StatusCode::BAD_REQUEST)),
+            (b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n", Some(StatusCode::BAD_REQUEST)),
+            (b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab", Some(StatusCode::BAD_REQUEST)),
+            (b"POST / HTTP/1.1\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n", Some(StatusCode::BAD_REQUEST)),
+            (b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n", Some(StatusCode::NOT_IMPLEMENTED)),
+            (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n", Some(StatusCode::BAD_REQUEST)),
+            (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhiX\r\n", Some(StatusCode::BAD_REQUEST)),
+            (b"GET / HTTP/1.1\r\nX-Long: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n\r\n", Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)),
+            (b"POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\n", Some(StatusCode::PAYLOAD_TOO_LARGE)),
+            (b"POST / HTTP/1.1\r\nContent-Length: 99999999999999999999999\r\n\r\n", Some(StatusCode::PAYLOAD_TOO_LARGE)),
+            (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n5\r\nhello\r\n0\r\n\r\n", Some(StatusCode::PAYLOAD_TOO_LARGE)),
+            (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffffffff\r\n", Some(StatusCode::PAYLOAD_TOO_LARGE)),
+        ];
+        for (input, status) in cases {
+            let result = read_with(input, &limits);
+            let error = result.as_ref().err().unwrap_or_else(|| panic!("{:?} should have failed", String::from_utf8_lossy(input)));
+            assert_eq!(error.status(), *status, "{:?}", String::from_utf8_lossy(input));
+        }
+    }
+
+    #[test]
+    fn timeouts_are_408() {
+        let error = RequestError::from(io::Error::from(io::ErrorKind::WouldBlock));
+        assert_eq!(error.status(), Some(StatusCode::REQUEST_TIMEOUT));
+        let error = RequestError::from(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert_eq!(error.status(), None);
+    }
+
+    proptest! {
+        //fuzz: no input, however broken, panics the reader
+        #[test]
+        fn fuzz_never_panics(input in proptest::collection::vec(any::<u8>(), 0..512)) {
+            let limits = Limits { max_header_bytes: 128, max_body_bytes: 64, memory_body_bytes: 16 };
+            let _ = read_with(&input, &limits);
+        }
+
+        //fuzz: inputs that look like requests, to get past the head more often than random bytes do
+        #[test]
+        fn fuzz_request_like_input_never_panics(
+            head in "(GET|POST) /[a-z/]{0,8} HTTP/1\\.[01]\r\n((Content-Length|Transfer-Encoding|Expect): [-0-9a-z, ]{0,12}\r\n){0,3}\r\n",
+            body in "[0-9a-fA-F;\r\n xz]{0,64}",
+        ) {
+            let limits = Limits { max_header_bytes: 256, max_body_bytes: 32, ..Limits::default() };
+            let input = format!("{}{}", head, body);
+            if let Ok((request, _, _)) = read_with(input.as_bytes(), &limits) {
+                prop_assert!(request.body.size() <= 32);
+            }
+        }
+
+        //fuzz: however a body is chunked, it decodes back to itself, leaving the next request unread
+        #[test]
+        fn fuzz_chunked_round_trips(
+            text in proptest::collection::vec(any::<u8>(), 0..300),
+            sizes in proptest::collection::vec(1usize..64, 0..8),
+        ) {
+            let mut input = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+            input.extend(chunk(&text, &sizes));
+            input.extend_from_slice(b"GET / HTTP/1.1\r\n\r\n");
+
+            let (request, _, rest) = read(&input).unwrap();
+            prop_assert_eq!(request.body.size(), text.len());
+            prop_assert_eq!(body(request), text);
+            prop_assert_eq!(rest, b"GET / HTTP/1.1\r\n\r\n".to_vec());
+        }
+    }
+}
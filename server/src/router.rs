@@ -175,9 +175,9 @@ enum NodeData {
 pub struct Router {
     get: RouteNode,
     post: RouteNode,
-    not_found: Box<dyn Fn() -> http::Response<Vec<u8>>>,
-    bad_request: Box<dyn Fn() -> http::Response<Vec<u8>>>, //TODO: error + 404 pages
-    method_not_allowed: Box<dyn Fn() -> http::Response<Vec<u8>>>,
+    not_found: Box<dyn Fn() -> http::Response<Vec<u8>> + Send + Sync>,
+    bad_request: Box<dyn Fn() -> http::Response<Vec<u8>> + Send + Sync>, //TODO: error + 404 pages
+    method_not_allowed: Box<dyn Fn() -> http::Response<Vec<u8>> + Send + Sync>,
 }
 impl Router {
     //new(): returns default Router with hard-coded routes
@@ -198,7 +198,7 @@ impl Router {
         &self,
         path_iterator: &mut path::Iter,
         method: &str,
-    ) -> Result<&Content, &Box<dyn Fn() -> http::Response<Vec<u8>>>> {
+    ) -> Result<&Content, &Box<dyn Fn() -> http::Response<Vec<u8>> + Send + Sync>> {
         let tree: &RouteNode;
 
         //then, check what HTTP method the request used, and select the proper tree/data for it
@@ -250,14 +250,24 @@ impl Router {
                 "favicon.ico",
                 Leaf(new_func_endpoint(Box::new(endpoints::files::favicon))),
             )
-            .add_child(
-                "user",
-                Leaf(Content::UserDataRequest)
-            )
             .add_child(
                 "probe_telemetry",
                 Leaf(Content::TelemetryQuery)
-            );
+            )
+            .add_child("shared", Leaf(Content::SharedRequest));
+
+        tree.select_child("/").unwrap()
+            .add_and_select_child("user", Branch(HashMap::new()))
+            .add_child("/", Leaf(Content::UserDataRequest))
+            .add_child("history", Leaf(Content::UserHistoryRequest))
+            .add_child("periods", Leaf(Content::UserPeriodsRequest))
+            .add_child("export", Leaf(Content::UserExportRequest))
+            .add_child("reports", Leaf(Content::UserReportRequest))
+            .add_child("forecast", Leaf(Content::UserForecastRequest))
+            .add_child("notifications", Leaf(Content::UserNotificationsRequest))
+            .add_child("members", Leaf(Content::UserMembersRequest))
+            .add_child("budgets", Leaf(Content::UserBudgetsRequest))
+            .add_child("shares", Leaf(Content::UserSharesRequest));
 
         tree
     }
@@ -273,7 +283,14 @@ impl Router {
             .add_child(  "logout",Leaf(Content::LogoutRequest));
 
         tree.select_child("/").unwrap()
-            .add_child("user", Leaf(Content::UserCommand));
+            .add_and_select_child("user", Branch(HashMap::new()))
+            .add_child("/", Leaf(Content::UserCommand))
+            .add_child("batch", Leaf(Content::UserBatchCommand))
+            .add_child("import", Leaf(Content::UserImportRequest))
+            .add_child("restore", Leaf(Content::UserRestoreRequest))
+            .add_child("members", Leaf(Content::UserMembersRequest))
+            .add_child("budgets", Leaf(Content::UserBudgetsRequest))
+            .add_child("shares", Leaf(Content::UserSharesRequest));
 
         tree
     }
@@ -0,0 +1,237 @@
+use chrono::{Datelike, Days, Months, NaiveDate};
+use serde::{self, Deserialize, Serialize};
+
+//ScheduleKind: whether a schedule pays the user, or pays one of their expenses
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleKind {
+    Income,
+    Expense,
+}
+
+//Frequency: how often a schedule repeats
+//monthly schedules land on the given day, or the last day of shorter months
+//yearly schedules land on the given month and day, or Feb 28 for Feb 29 in non-leap years
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "every")]
+pub enum Frequency {
+    Weekly,
+    Biweekly,
+    Monthly { day: u32 },
+    Yearly { month: u32, day: u32 },
+}
+impl Frequency {
+    //parse(): builds a Frequency from its command name, anchored to the first due date
+    //day only applies to monthly schedules, and defaults to the day of the first due date
+    pub fn parse(name: &str, start: NaiveDate, day: Option<u32>) -> Result<Frequency, String> {
+        match name {
+            "weekly" => Ok(Frequency::Weekly),
+            "biweekly" => Ok(Frequency::Biweekly),
+            "monthly" => match day.unwrap_or(start.day()) {
+                day @ 1..=31 => Ok(Frequency::Monthly { day }),
+                _ => Err(String::from("invalid_schedule_day_value")),
+            },
+            "yearly" => Ok(Frequency::Yearly {
+                month: start.month(),
+                day: start.day(),
+            }),
+            _ => Err(String::from("invalid_schedule_frequency_value")),
+        }
+    }
+
+    //next_after(): the first due date after the given one
+    pub fn next_after(&self, date: NaiveDate) -> NaiveDate {
+        match *self {
+            Frequency::Weekly => date + Days::new(7),
+            Frequency::Biweekly => date + Days::new(14),
+            Frequency::Monthly { day } => {
+                let next_month = first_of_month(date) + Months::new(1);
+                clamp_to_month(next_month.year(), next_month.month(), day)
+            }
+            Frequency::Yearly { month, day } => clamp_to_month(date.year() + 1, month, day),
+        }
+    }
+}
+
+//first_of_month(): the 1st of the given date's month
+fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).expect("every month has a 1st")
+}
+
+//clamp_to_month(): the given day of the given month, or the month's last day if it's too short
+pub fn clamp_to_month(year: i32, month: u32, day: u32) -> NaiveDate {
+    (1..=day)
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .expect("every month has at least 28 days")
+}
+
+//Schedule: a recurring income or expense, posted automatically when it comes due
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Schedule {
+    pub label: String,
+    pub kind: ScheduleKind,
+    pub amount: i64,
+    pub frequency: Frequency,
+    pub next_due: NaiveDate,
+    //the account incomes are paid into or expenses are paid from (the primary account if not given)
+    #[serde(default)]
+    pub account: Option<String>,
+}
+impl Schedule {
+    //new(): creates a schedule, first due on the given date
+    pub fn new(
+        label: &str,
+        kind: ScheduleKind,
+        amount: i64,
+        frequency: Frequency,
+        first_due: NaiveDate,
+        account: Option<&str>,
+    ) -> Schedule {
+        //monthly schedules always land on their day, so line the first due date up with it
+        let next_due = match frequency {
+            Frequency::Monthly { day } => {
+                let due = clamp_to_month(first_due.year(), first_due.month(), day);
+                if due < first_due {
+                    frequency.next_after(due)
+                } else {
+                    due
+                }
+            }
+            _ => first_due,
+        };
+
+        Schedule {
+            label: label.to_ascii_lowercase(),
+            kind,
+            amount,
+            frequency,
+            next_due,
+            account: account.map(str::to_ascii_lowercase),
+        }
+    }
+
+    //is_due(): whether the schedule should have been posted by the given date
+    pub fn is_due(&self, today: NaiveDate) -> bool {
+        self.next_due <= today
+    }
+
+    //advance(): moves the schedule along to its next due date
+    pub fn advance(&mut self) {
+        self.next_due = self.frequency.next_after(self.next_due);
+    }
+}
+
+//Overdue: a scheduled or automatic payment that came due but couldn't be afforded
+//kept on the budget until it's paid off
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Overdue {
+    pub label: String,
+    pub amount: i64,
+    pub due: NaiveDate,
+    #[serde(default)]
+    pub account: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::Budget;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    //dues(): the first few due dates of a schedule
+    fn dues(mut schedule: Schedule, count: usize) -> Vec<NaiveDate> {
+        (0..count)
+            .map(|_| {
+                let due = schedule.next_due;
+                schedule.advance();
+                due
+            })
+            .collect()
+    }
+
+    //state(): the budget's balance and how much of it is overdue, read off its json
+    fn state(budget: &Budget) -> (i64, i64) {
+        let value = serde_json::to_value(budget).unwrap();
+        let overdue = value["overdue"].as_array().unwrap().iter().map(|item| item["amount"].as_i64().unwrap()).sum();
+        (value["accounts"]["checking"]["balance"].as_i64().unwrap(), overdue)
+    }
+
+    //next_dues(): when each of the budget's schedules is next due
+    fn next_dues(budget: &Budget) -> Vec<String> {
+        let value = serde_json::to_value(budget).unwrap();
+        value["schedules"].as_array().unwrap().iter().map(|schedule| schedule["next_due"].as_str().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn monthly_schedules_fall_back_to_the_end_of_short_months() {
+        let frequency = Frequency::parse("monthly", date(2024, 1, 31), None).unwrap();
+        let schedule = Schedule::new("rent", ScheduleKind::Expense, 100, frequency, date(2024, 1, 31), None);
+        assert_eq!(dues(schedule, 4), [date(2024, 1, 31), date(2024, 2, 29), date(2024, 3, 31), date(2024, 4, 30)]);
+
+        let frequency = Frequency::parse("yearly", date(2024, 2, 29), None).unwrap();
+        let schedule = Schedule::new("fee", ScheduleKind::Expense, 100, frequency, date(2024, 2, 29), None);
+        assert_eq!(dues(schedule, 3), [date(2024, 2, 29), date(2025, 2, 28), date(2026, 2, 28)]);
+    }
+
+    #[test]
+    fn first_due_date_lines_up_with_the_day() {
+        let start = date(2024, 1, 20);
+        let frequency = Frequency::parse("monthly", start, Some(5)).unwrap();
+        assert_eq!(Schedule::new("rent", ScheduleKind::Expense, 100, frequency, start, None).next_due, date(2024, 2, 5));
+        let frequency = Frequency::parse("monthly", start, Some(25)).unwrap();
+        assert_eq!(Schedule::new("rent", ScheduleKind::Expense, 100, frequency, start, None).next_due, date(2024, 1, 25));
+
+        let frequency = Frequency::parse("biweekly", start, Some(25)).unwrap();
+        let schedule = Schedule::new("Pay", ScheduleKind::Income, 100, frequency, start, Some("Checking"));
+        assert_eq!((schedule.label.as_str(), schedule.account.as_deref()), ("pay", Some("checking")));
+        assert_eq!(dues(schedule, 2), [date(2024, 1, 20), date(2024, 2, 3)]);
+    }
+
+    #[test]
+    fn rejects_unknown_frequencies_and_days() {
+        assert_eq!(Frequency::parse("daily", date(2024, 1, 1), None), Err(String::from("invalid_schedule_frequency_value")));
+        assert_eq!(Frequency::parse("monthly", date(2024, 1, 1), Some(32)), Err(String::from("invalid_schedule_day_value")));
+        assert_eq!(Frequency::parse("monthly", date(2024, 1, 1), Some(0)), Err(String::from("invalid_schedule_day_value")));
+    }
+
+    #[test]
+    fn posts_what_came_due_and_marks_what_couldnt_be_paid_overdue() {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.add_expense("rent", 15000, None);
+        let monthly = Frequency::Monthly { day: 1 };
+        budget.add_schedule(Schedule::new("rent", ScheduleKind::Expense, 15000, monthly, date(2024, 1, 1), None)).unwrap();
+        budget.add_schedule(Schedule::new("pay", ScheduleKind::Income, 10000, Frequency::Weekly, date(2024, 1, 2), None)).unwrap();
+
+        budget.run_schedules(date(2024, 1, 1));
+        assert_eq!(state(&budget), (0, 15000));
+
+        //two paychecks come in, and the rent is paid off the next time schedules are run
+        budget.run_schedules(date(2024, 1, 9));
+        assert_eq!(state(&budget), (20000, 15000));
+        budget.run_schedules(date(2024, 1, 10));
+        assert_eq!(state(&budget), (5000, 0));
+        assert_eq!(next_dues(&budget), ["2024-02-01", "2024-01-16"]);
+    }
+
+    #[test]
+    fn income_is_posted_before_expenses_due_the_same_day() {
+        let mut budget = Budget::new(String::from("sam"));
+        budget.add_expense("rent", 10000, None);
+        budget.add_schedule(Schedule::new("rent", ScheduleKind::Expense, 10000, Frequency::Weekly, date(2024, 1, 1), None)).unwrap();
+        budget.add_schedule(Schedule::new("pay", ScheduleKind::Income, 10000, Frequency::Weekly, date(2024, 1, 1), None)).unwrap();
+        budget.run_schedules(date(2024, 1, 1));
+        assert_eq!(state(&budget), (0, 0));
+    }
+
+    #[test]
+    fn expense_schedules_need_an_expense() {
+        let mut budget = Budget::new(String::from("sam"));
+        let schedule = Schedule::new("rent", ScheduleKind::Expense, 100, Frequency::Weekly, date(2024, 1, 1), None);
+        assert_eq!(budget.add_schedule(schedule), Err(String::from("expense_not_found")));
+        assert_eq!(budget.remove_schedule("pay"), Err(String::from("schedule_not_found")));
+    }
+}
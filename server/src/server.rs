@@ -1,130 +1,386 @@
 //used for reading/handling TCP connection
-use std::io::{prelude::*, BufReader};
+use std::io::{prelude::*, BufReader, Cursor};
 use std::net::{TcpListener, TcpStream};
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
 
 use std::time::{Duration, Instant};
-use std::{env, path, thread};
+use std::{path, thread};
 
 //external crates:
 //used for parsing HTTP requests into objects
 use httparse::{self};
-//http_bytes replacement for http, as http normally doesnt support raw bytes ??
-use http_bytes::http::{self, StatusCode};
 //pretty text colors for emphasis :)
 use colored::Colorize;
 
 use crate::threads::auth::{self, AuthMessage};
-use crate::{db, metrics};
-use crate::endpoints::Content;
+use crate::{db, forecast, metrics, reports, shares};
+use crate::endpoints::{self, Content};
 use crate::http_utils;
+use crate::export::CsvTable;
+use crate::import::{ImportFormat, StatementUpload};
+use crate::request::{self, Limits, Request, RequestError};
 use crate::router::Router;
-use crate::threads::user_threads::{self, UserManagerThreadMessage};
+use crate::threads::user_threads::{self, UserManagerThreadMessage, UserQuery};
+use crate::threads::workers::{Slot, WorkerPool};
+use crate::tls::Certificates;
+use crate::config::Config;
 
-//the limit on http request size (i cant imagine i'd need more than 1kb)
-const MAX_REQUEST_BYTES: usize = 4096;
-
-//time interval (in seconds) for the timeout_clock thread (for checking for inactive user threads)
-const TIMEOUT_INTERVAL: u64 = 60;
+//the number of worker threads answering the HTTP redirect listener, which only ever sends one short response
+const REDIRECT_WORKERS: usize = 2;
 
 const AUTH_DATABASE_INIT: &str = "auth(uuid TEXT UNIQUE NOT NULL, username TEXT UNIQUE NOT NULL, password TEXT NOT NULL, PRIMARY KEY (uuid))";
-const USER_DATABASE_INIT: &str = "users(uuid TEXT UNIQUE NOT NULL, jsondata TEXT NOT NULL, jsonhistory TEXT NOT NULL, PRIMARY KEY (uuid))";
+const BUDGETS_DATABASE_INIT: &str = "budgets(uuid TEXT UNIQUE NOT NULL, jsondata TEXT NOT NULL, jsonhistory TEXT NOT NULL, PRIMARY KEY (uuid))";
+const MEMBERS_DATABASE_INIT: &str = "members(budget TEXT NOT NULL, user TEXT NOT NULL, role TEXT NOT NULL, PRIMARY KEY (budget, user))";
+const INVITES_DATABASE_INIT: &str = "invites(budget TEXT NOT NULL, user TEXT NOT NULL, role TEXT NOT NULL, PRIMARY KEY (budget, user))";
+const LEDGER_DATABASE_INIT: &str = "ledger(budget TEXT NOT NULL, seq INTEGER NOT NULL, timestamp INTEGER NOT NULL, jsonentry TEXT NOT NULL, PRIMARY KEY (budget, seq))";
+const UNDO_DATABASE_INIT: &str = "undo(budget TEXT NOT NULL, member TEXT NOT NULL, jsonstacks TEXT NOT NULL, PRIMARY KEY (budget, member))";
+const SHARES_DATABASE_INIT: &str = "shares(token TEXT UNIQUE NOT NULL, budget TEXT NOT NULL, scope TEXT NOT NULL, created_by TEXT NOT NULL, expires INTEGER NOT NULL, PRIMARY KEY (token))";
+
+//create_tables(): makes every table the server keeps in the database, if they aren't there yet
+pub fn create_tables() {
+    let database = db::USER_DB.read().unwrap();
+
+    database.create_table(String::from(AUTH_DATABASE_INIT));
+    database.create_table(String::from(BUDGETS_DATABASE_INIT));
+    database.create_table(String::from(MEMBERS_DATABASE_INIT));
+    database.create_table(String::from(INVITES_DATABASE_INIT));
+    database.create_table(String::from(SHARES_DATABASE_INIT));
+    database.create_table(String::from(LEDGER_DATABASE_INIT));
+    database.create_table(String::from(UNDO_DATABASE_INIT));
+}
+
+//Transport: the connection a TimedStream is read from and written to, either plain TCP or TLS over TCP
+#[derive(Debug)]
+enum Transport {
+    Plain(TcpStream),
+    //locked, so it can be read and written through shared references just like a TcpStream
+    //(a request is read from it while "100 Continue" might be written to it)
+    Tls(Box<Mutex<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>>),
+}
+impl Transport {
+    fn tls(tls: &Mutex<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>) -> MutexGuard<'_, rustls::StreamOwned<rustls::ServerConnection, TcpStream>> {
+        tls.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    //socket(): runs the given function on the TCP connection underneath
+    fn socket<T>(&self, function: impl FnOnce(&TcpStream) -> T) -> T {
+        match self {
+            Transport::Plain(socket) => function(socket),
+            Transport::Tls(tls) => function(&Transport::tls(tls).sock),
+        }
+    }
+
+    //buffered(): whether a TLS connection has already decrypted bytes that haven't been read yet
+    fn buffered(&self) -> bool {
+        match self {
+            Transport::Plain(_) => false,
+            Transport::Tls(tls) => Transport::tls(tls)
+                .conn
+                .process_new_packets()
+                .is_ok_and(|state| state.plaintext_bytes_to_read() > 0),
+        }
+    }
+
+    //close(): tells a TLS client the connection is closing on purpose, rather than being cut off
+    //nothing is sent before the handshake is done, so closing a connection no worker has read never writes to it
+    fn close(&self) {
+        if let Transport::Tls(tls) = self {
+            let mut tls = Transport::tls(tls);
+            if tls.conn.is_handshaking() {
+                return;
+            }
+            tls.conn.send_close_notify();
+            let _ = tls.flush();
+        }
+    }
+}
+impl Read for &Transport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Transport::Plain(socket) => (&*socket).read(buf),
+            Transport::Tls(tls) => Transport::tls(tls).read(buf),
+        }
+    }
+}
+impl Write for &Transport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Transport::Plain(socket) => (&*socket).write(buf),
+            Transport::Tls(tls) => Transport::tls(tls).write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Transport::Plain(socket) => (&*socket).flush(),
+            Transport::Tls(tls) => Transport::tls(tls).flush(),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct TimedStream {
-    stream: TcpStream,
+    stream: Transport,
     pub spawntime: Instant,
-    pub id: usize
+    pub id: usize,
+    //boxed, since streams are passed around inside thread messages
+    reuse: Box<Reuse>,
+}
+//Reuse: what's needed to read more than one request off a connection
+#[derive(Debug, Default)]
+struct Reuse {
+    //bytes read off the connection past the end of the current request (the start of a pipelined one)
+    pending: Vec<u8>,
+    //whether the connection stays open for another request once this one has been answered
+    keep_alive: bool,
+    //whether the current request has been answered
+    responded: bool,
+    //whether the connection has been answered before, and is back in the worker pool waiting for its next request
+    waiting: bool,
+    //the worker pool's hold on the connection, which it goes back to once answered if it's kept alive
+    slot: Option<Slot>,
 }
 impl TimedStream {
     pub fn new(stream: TcpStream) -> TimedStream {
-        TimedStream{stream, spawntime: Instant::now(), id: metrics::start() }
+        TimedStream{stream: Transport::Plain(stream), spawntime: Instant::now(), id: metrics::start(), reuse: Box::default() }
+    }
+
+    //tls(): a connection served over TLS, with the handshake left to whichever worker reads it first
+    pub fn tls(stream: TcpStream, config: Arc<rustls::ServerConfig>) -> Result<TimedStream, rustls::Error> {
+        let tls = rustls::StreamOwned::new(rustls::ServerConnection::new(config)?, stream);
+        Ok(TimedStream{stream: Transport::Tls(Box::new(Mutex::new(tls))), spawntime: Instant::now(), id: metrics::start(), reuse: Box::default() })
+    }
+
+    //attach(): gives the connection its place in the worker pool
+    pub fn attach(&mut self, slot: Slot) {
+        self.reuse.slot = Some(slot);
+    }
+
+    //restart(): starts timing the next request on a kept-alive connection
+    pub fn restart(&mut self) {
+        self.spawntime = Instant::now();
+        self.id = metrics::start();
+        self.reuse.waiting = false;
+    }
+
+    //waiting(): whether the connection is kept alive from an earlier request, rather than new
+    pub fn waiting(&self) -> bool {
+        self.reuse.waiting
+    }
+
+    pub fn keep_alive(&self) -> bool {
+        self.reuse.keep_alive
+    }
+
+    //responded(): marks the current request as answered, so the connection can be read again once it's dropped
+    pub fn responded(&mut self) {
+        self.reuse.responded = true;
+    }
+
+    //wait_for_request(): waits up to the given time for the next request to start arriving,
+    //straight away if it already has (pipelined behind the last one)
+    //false if it doesn't, or the client closes the connection first
+    pub fn wait_for_request(&self, timeout: Duration) -> bool {
+        if !self.reuse.pending.is_empty() || self.stream.buffered() {
+            return true;
+        }
+        let peeked = self.stream.socket(|socket| {
+            //a zero timeout would mean waiting forever
+            socket.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+            socket.peek(&mut [0; 1])
+        });
+        matches!(peeked, Ok(read) if read > 0)
     }
     
     pub fn elapsed(&self) -> Duration{
         self.spawntime.elapsed()
     }
+
+    //set_timeouts(): limits how long reading from or writing to the connection can block
+    pub fn set_timeouts(&self, read: Duration, write: Duration) -> std::io::Result<()> {
+        self.stream.socket(|socket| {
+            socket.set_read_timeout(Some(read))?;
+            socket.set_write_timeout(Some(write))
+        })
+    }
 }
+//whoever answers a request (a worker, or the auth or user threads it was passed to) drops the stream when done,
+//which queues a kept-alive connection up in the worker pool again, for a worker to wait on its next request
+impl Drop for TimedStream {
+    fn drop(&mut self) {
+        if !(self.reuse.keep_alive && self.reuse.responded) {
+            self.stream.close();
+            return;
+        }
+        //the connection is moved out into the new TimedStream, leaving a copy of its socket behind to be dropped
+        let (Some(slot), Ok(socket)) = (self.reuse.slot.take(), self.stream.socket(TcpStream::try_clone)) else {
+            return;
+        };
+        let stream = std::mem::replace(&mut self.stream, Transport::Plain(socket));
+        let queue = slot.queue();
+        let _ = queue.send(TimedStream {
+            stream,
+            spawntime: Instant::now(),
+            id: self.id,
+            reuse: Box::new(Reuse {
+                pending: std::mem::take(&mut self.reuse.pending),
+                waiting: true,
+                slot: Some(slot),
+                ..Reuse::default()
+            }),
+        });
+    }
+}
+
 impl std::io::Write for TimedStream {
     
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.stream.write(buf)
+        (&self.stream).write(buf)
     }
     
     fn flush(&mut self) -> std::io::Result<()> {
-        self.stream.flush()
+        (&self.stream).flush()
     }
     
 }
 
 pub struct Server {
     listener: TcpListener,
-    router: Router,
-    auth_thread_sender: Option<mpsc::Sender<AuthMessage>>,
+    //the number of worker threads reading connections
+    workers: usize,
+    //the number of connections that can be open (waiting for or being read by a worker) at once,
+    //any more are closed as soon as they are accepted
+    max_connections: usize,
+    limits: Limits,
+    //how long a connection can go without sending or accepting anything before it's dropped,
+    //so a client that stalls partway through a request only holds up its own worker
+    timeouts: Timeouts,
+    //how often the timeout_clock thread checks for inactive user threads
+    timeout_interval: Duration,
+    //how often the scheduler thread posts scheduled incomes and expenses
+    schedule_interval: Duration,
+    //served over TLS when set
+    certificates: Option<Arc<Certificates>>,
+    //where plain HTTP requests are answered with a redirect to HTTPS, if anywhere
+    redirect_listener: Option<TcpListener>,
     auth_thread_receiver: Option<mpsc::Receiver<AuthMessage>>,
-    users_thread_sender: Option<mpsc::Sender<UserManagerThreadMessage>>,
     users_thread_receiver: Option<mpsc::Receiver<UserManagerThreadMessage>>,
 }
 //TODO: FIND WAY TO REMOVE THE Option FROM THE STRUCT^^^ its annoying
 impl Server {
-    pub fn new(address: String) -> Server {
+    pub fn new(config: &Config, certificates: Option<Certificates>) -> Server {
+        let address = config.address();
         let listener = TcpListener::bind(&address)
             .expect(&format!("listener should have bound to {}", address)[..]);
-        let router = Router::new();
+        //redirecting to HTTPS only makes sense when it's served
+        let redirect_listener = config.redirect_address().filter(|_| certificates.is_some()).map(|address| {
+            TcpListener::bind(&address).unwrap_or_else(|why| panic!("redirect listener should have bound to {}: {}", address, why))
+        });
 
-        let database = db::USER_DB.read().unwrap();
+        create_tables();
 
-        database.create_table(String::from(AUTH_DATABASE_INIT));
-        database.create_table(String::from(USER_DATABASE_INIT));
+        //budgets used to be kept in a users table, a row per user
+        endpoints::database::move_budgets_out_of_users();
+        //budgets from before sharing belong to the user they were registered for
+        endpoints::database::adopt_existing_budgets();
+        //ledgers and undo stacks from before their own tables were kept inside jsonhistory
+        endpoints::database::migrate_histories();
 
         Server {
             listener,
-            router,
-            auth_thread_sender: None,
+            workers: config.worker_threads,
+            max_connections: config.max_connections,
+            limits: Limits {
+                max_header_bytes: config.max_header_bytes,
+                max_body_bytes: config.max_body_bytes,
+                ..Limits::default()
+            },
+            timeouts: Timeouts {
+                read: Duration::from_secs(config.read_timeout),
+                write: Duration::from_secs(config.write_timeout),
+                keep_alive: Duration::from_secs(config.keep_alive_timeout),
+            },
+            timeout_interval: Duration::from_secs(config.timeout_interval),
+            schedule_interval: Duration::from_secs(config.schedule_interval),
+            certificates: certificates.map(Arc::new),
+            redirect_listener,
             auth_thread_receiver: None,
-            users_thread_sender: None,
             users_thread_receiver: None,
         }
     }
 
-    pub fn send_message_to_auth_thread(&self, msg: AuthMessage) -> Result<(), mpsc::SendError<AuthMessage>> {
-        self.auth_thread_sender.as_ref().unwrap().send(msg)
-    }
-
-    pub fn send_message_to_user_thread(&self, msg: UserManagerThreadMessage) -> Result<(), mpsc::SendError<UserManagerThreadMessage>> {
-        self.users_thread_sender.as_ref().unwrap().send(msg)
-    }
-
     //listen(): loops forever through incoming TCP streams and handles them
     pub fn listen(&mut self) -> Result<(), String> {
 
         let (host_sender, thread_receiver) = mpsc::channel::<auth::AuthMessage>();
         let (thread_sender, host_receiver) = mpsc::channel::<auth::AuthMessage>();
 
-        self.auth_thread_sender = Some(host_sender);
         self.auth_thread_receiver = Some(host_receiver);
 
         let (user_host_sender, user_thread_receiver) = mpsc::channel::<user_threads::UserManagerThreadMessage>();
         let (user_thread_sender, user_host_receiver) = mpsc::channel::<user_threads::UserManagerThreadMessage>();
         let timer_thread_sender = user_host_sender.clone();
+        let scheduler_thread_sender = user_host_sender.clone();
+        //budget threads tell the user manager when they go idle, on its own channel
+        let manager_sender = user_host_sender.clone();
 
-        self.users_thread_sender = Some(user_host_sender.clone());
         self.users_thread_receiver = Some(user_host_receiver);
 
+        //every worker answers connections with the same routes, and passes them on to the same threads
+        let connections = Connections {
+            router: Router::new(),
+            limits: self.limits,
+            auth_thread_sender: host_sender,
+            users_thread_sender: user_host_sender.clone(),
+        };
+        let timeouts = self.timeouts;
+        let pool = WorkerPool::new(self.workers, self.max_connections, timeouts.keep_alive, move |stream: TimedStream| {
+            let stream_id = stream.id;
+            metrics::arrive(stream_id);
+
+            //handle the request, get a response
+            let _ = stream.set_timeouts(timeouts.read, timeouts.write);
+            let _ = connections.handle_connection(stream);
+
+            metrics::end(stream_id);
+        });
+
         thread::Builder::new().name("user_master".into()).spawn(move || {
-            user_threads::handle_user_threads(user_thread_sender, user_thread_receiver);
+            user_threads::handle_user_threads(user_thread_sender, user_thread_receiver, manager_sender);
         }).expect("failed to create user_master thread: OS error");
 
         thread::Builder::new().name("authenticator".into()).spawn(move || {
             auth::handle_auth_requests(thread_sender, thread_receiver, user_host_sender);
         }).expect("failed to create authenticator thread: OS error");
         
+        let (timeout_interval, schedule_interval) = (self.timeout_interval, self.schedule_interval);
         thread::Builder::new().name("timeout_clock".into()).spawn(move || {
-            generate_timeout_checks(timer_thread_sender);
+            generate_timeout_checks(timer_thread_sender, timeout_interval);
         }).expect("failed to create timeout_clock thread: OS error");
 
+        thread::Builder::new().name("scheduler".into()).spawn(move || {
+            generate_schedule_checks(scheduler_thread_sender, schedule_interval);
+        }).expect("failed to create scheduler thread: OS error");
+
+        if let Some(certificates) = &self.certificates {
+            crate::tls::watch_reloads(Arc::clone(certificates))?;
+        }
+        if let Some(redirect_listener) = self.redirect_listener.take() {
+            let https_port = self.listener.local_addr().unwrap().port();
+            let (max_connections, limits, timeouts) = (self.max_connections, self.limits, self.timeouts);
+            thread::Builder::new().name("redirector".into()).spawn(move || {
+                redirect_to_https(redirect_listener, https_port, max_connections, limits, timeouts);
+            }).expect("failed to create redirector thread: OS error");
+        }
+
         metrics::finish_startup();
-        println!("listening on {:?} from thread\t{}", self.listener.local_addr().unwrap(), metrics::thread_name_display());
+        println!(
+            "listening on {:?} ({}) from thread\t{} with {} workers",
+            self.listener.local_addr().unwrap(),
+            if self.certificates.is_some() { "https" } else { "http" },
+            metrics::thread_name_display(),
+            self.workers
+        );
 
         //iterate through incoming TCP connections/requests
         for stream in self.listener.incoming() {
@@ -134,15 +390,19 @@ impl Server {
                     //count req, print
                     //println!("\n{}{}\n", "~~~~~~<[ REQUEST! ]>~~~~~~ ".bold().bright_green(), req_count);
 
-                    let stream = TimedStream::new(stream);
-                    let stream_id = stream.id;
-
-                    metrics::arrive(stream_id);
-
-                    //handle the request, get a response
-                    let _ = self.handle_connection(stream);
-
-                    metrics::end(stream_id);
+                    //hand it off to a worker, so the next connection can be accepted straight away
+                    //(including the TLS handshake, so a slow one doesn't hold up the accept loop)
+                    let stream = match &self.certificates {
+                        None => TimedStream::new(stream),
+                        Some(certificates) => match TimedStream::tls(stream, certificates.config()) {
+                            Ok(stream) => stream,
+                            Err(why) => {
+                                eprintln!("failed to start TLS on a connection: {}", why);
+                                continue;
+                            }
+                        },
+                    };
+                    pool.dispatch(stream);
                 }
                 Err(why) => {
                     return Err(format!("stream connection failed!:\n{:?}", why));
@@ -153,103 +413,59 @@ impl Server {
 
         Ok(())
     }
+}
 
-    //handle_connection(): reads the given TCP stream and sends back a response, using the given Router
-    fn handle_connection(&self, mut stream: TimedStream) -> Result<(), std::io::Error> {
-
-        //buffer bytes, to store request in
-        let mut buffer: [u8; MAX_REQUEST_BYTES] = [0; MAX_REQUEST_BYTES];
-        //TODO: check if size is ok? 4kb? how big are requests really?
-
-        //create a buffered reader to read through the stream input
-        let mut reader = BufReader::new(&stream.stream);
-
-        //i was having a difficult issue where sometimes request headers and bodies
-        //were arriving at different enough times to where stream.read() would
-        //finish the headers before the body arrived, leading to missing data
-        //i had to fix this by manually parsing the expected content length
-        //and fetching that many bytes, after the headers
-
-        //initialize a string to hold headers
-        let mut headers = String::new();
-
-        //read lines from tcp stream until end of headers (empty line)
-        loop {
-            let bytes_read = reader.read_line(&mut headers).unwrap();
-            // \r\f = 2 bytes
-            if bytes_read < 3 {
-                break;
-            }
-        }
-
-        //take the headers string, split it by line,
-        let body_size = match headers
-            .split("\n")
-            //find the first line where,
-            .find(|line| {
-                //it contains the content-length header
-                line.to_lowercase().starts_with("content-length")
-            }) {
-            //if the line exists,
-            Some(content_length) => {
-                //split it just past the ':' (14 chars, plus : and space)
-                content_length
-                    .split_at(16)
-                    //grab the 2nd half (index 1)
-                    .1
-                    .trim()
-                    //and parse it as an integer (or just a zero if something weird is here)
-                    .parse::<usize>()
-                    .unwrap_or(0)
-            }
-            //otherwise, assume 0 request body
-            None => 0,
-        };
-
-        if body_size > MAX_REQUEST_BYTES {
-            return http_utils::send_response(http_utils::empty_response(http::StatusCode::PAYLOAD_TOO_LARGE).unwrap(), &mut stream)
-        }
-
-        //the body will be stored in a vec of the exact required size
-        let mut body_bytes = vec![0; body_size];
+//Timeouts: how long reading a request from, and writing a response to, a connection can block,
+//and how long a kept-alive connection can sit idle between requests
+#[derive(Debug, Clone, Copy)]
+struct Timeouts {
+    read: Duration,
+    write: Duration,
+    keep_alive: Duration,
+}
 
-        //read into the body buffer
-        reader.read_exact(&mut body_bytes).unwrap();
+//Connections: what the workers need to answer a connection, the routes and the channels to the auth and user manager threads
+struct Connections {
+    router: Router,
+    limits: Limits,
+    auth_thread_sender: mpsc::Sender<AuthMessage>,
+    users_thread_sender: mpsc::Sender<UserManagerThreadMessage>,
+}
+impl Connections {
+    pub fn send_message_to_auth_thread(&self, msg: AuthMessage) -> Result<(), mpsc::SendError<AuthMessage>> {
+        self.auth_thread_sender.send(msg)
+    }
 
-        //convert headers into bytes
-        let headers = headers.as_bytes();
+    pub fn send_message_to_user_thread(&self, msg: UserManagerThreadMessage) -> Result<(), mpsc::SendError<UserManagerThreadMessage>> {
+        self.users_thread_sender.send(msg)
+    }
 
-        //concatenate the headers and body vecs
-        let mut vec_buf = [headers, body_bytes.as_slice()].concat();
+    //handle_connection(): reads the given TCP stream and sends back a response, using the given Router
+    fn handle_connection(&self, mut stream: TimedStream) -> Result<(), std::io::Error> {
 
-        //resize the new vec buffer to fit into the byte array buffer
-        vec_buf.resize(MAX_REQUEST_BYTES, 0);
+        //create a buffered reader to read through the stream input,
+        //starting with whatever was read past the end of the last request on this connection
+        let pending = std::mem::take(&mut stream.reuse.pending);
+        let mut reader = BufReader::new(Cursor::new(pending).chain(&stream.stream));
 
-        //copy the vec into the array buffer
-        buffer.copy_from_slice(vec_buf.as_slice());
+        //read the request's head and body (answering "Expect: 100-continue" straight onto the connection)
+        let request = request::read_request(&mut reader, &mut &stream.stream, &self.limits);
 
-        //dont need this anymore
-        drop(vec_buf);
+        //anything read past the body is the start of the next (pipelined) request, so keep it for then
+        let mut leftover = reader.buffer().to_vec();
+        let (unread, _) = reader.into_inner().into_inner();
+        leftover.extend_from_slice(&unread.get_ref()[unread.position() as usize..]);
+        stream.reuse.pending = leftover;
 
-        //i have to do this [u8] shit because httparse ONLY works on arrays, NOT vectors. L
+        let Request { head, body } = match request {
+            Ok(request) => request,
+            Err(why) => return request_failed(why, &mut stream),
+        };
+        let body_size = body.size();
 
         //parse request into req (its headers go into req_headers)
-        let mut req_headers: [httparse::Header; 64] = [httparse::EMPTY_HEADER; 64];
-        let mut req = httparse::Request::new(&mut req_headers);
-
-        //req_status: whether the request was successfully received entirely, without data loss
-        let Ok(req_status) = req.parse(&buffer) else {
-            println!(
-                "{}{}\t{}\n\t\t\t{}",
-                "--> ".bright_red().bold(),
-                stream.id,
-                "INCOMING REQUEST FAILED TO PARSE:".bright_red(),
-                String::from_utf8_lossy(&buffer)
-
-            );
-            http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream);
-            return Ok(());
-        };
+        let mut req_headers = [httparse::EMPTY_HEADER; request::MAX_HEADERS];
+        let req = request::parse_head(&head, &mut req_headers);
 
         //print the request method and path
         println!(
@@ -261,37 +477,26 @@ impl Server {
             if body_size > 0 { format!("- body size: {} bytes", body_size) } else { "".into() }
         );
 
-        //Option likely not needed tbh
-        let body: Option<String>;
-
-        //check the req status, send bad_request if not complete (probably not needed)
-        match req_status {
-            //if complete request,
-            httparse::Status::Complete(body_index) => {
-                
-                let str = String::from_utf8_lossy(&body_bytes);
-
-                //if it's empty, ensure its None
-                if str.is_empty() {
-                    body = None;
-                    println!("\t\tbody empty\n");
-                }
-                else{
-                    body = Some(str.into());
-                    println!("\t\tbody: {:?}\n", body.as_ref().unwrap());
-                }
-            }
-            //if partial request, just crash. i dont think i even need this
-            httparse::Status::Partial => {
-                println!("PARTIAL REQUEST???????");
-                println!("{:?}", String::from_utf8(buffer.to_vec()));
-                return http_utils::send_response(
-                    http_utils::content_too_large().unwrap(),
-                    &mut stream,
-                );
-            }
+        //the body as text, None if it's empty
+        let body: Option<String> = match body.into_text() {
+            Ok(body) => body,
+            Err(why) => return request_failed(why, &mut stream),
+        };
+        match &body {
+            None => println!("\t\tbody empty\n"),
+            //statement imports are far too long to print
+            Some(body) if body_size <= self.limits.max_header_bytes => println!("\t\tbody: {:?}\n", body),
+            Some(_) => {}
         }
 
+        //HTTP/1.1 connections stay open for more requests unless the client asks to close them,
+        //HTTP/1.0 ones only if it asks to keep them
+        let connection = http_utils::find_header_in_request(&req, "connection").unwrap_or_default().to_ascii_lowercase();
+        stream.reuse.keep_alive = match req.version {
+            Some(1) => connection.trim() != "close",
+            _ => connection.trim() == "keep-alive",
+        };
+
         //print out request for debugging
         //println!("\n{}\nbody: {:?}",http_utils::stringify_request(&req), &body.clone().unwrap_or("NONE".to_owned()));
 
@@ -411,6 +616,22 @@ impl Server {
                     }
                 }
                 
+                //if it's a batch of user commands, same as above, but the body is a json array of commands
+                Content::UserBatchCommand => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    let Some(body) = body else {
+                        return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::user_batch(stream.id, token, body, stream));
+                    Ok(())
+                }
+                
                 Content::UserDataRequest => {
 
                     let token = match http_utils::find_header_in_request(&req, "authorization") {
@@ -420,6 +641,221 @@ impl Server {
                     self.send_message_to_user_thread(UserManagerThreadMessage::user_data_request(stream.id, token, stream));
                     Ok(())
                 }
+
+                Content::UserHistoryRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    //the page number is the rest of the path (/user/history/2), defaulting to the newest page
+                    let page = path_iterator
+                        .next()
+                        .and_then(|page| page.to_str())
+                        .and_then(|page| page.parse::<usize>().ok())
+                        .unwrap_or(0);
+
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::user_query(stream.id, token, UserQuery::History { page }, stream));
+                    Ok(())
+                }
+
+                Content::UserImportRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    //the statement format is the rest of the path (/user/import/csv),
+                    //optionally followed by the account it's from (/user/import/ofx/visa)
+                    let Some(format) = path_iterator
+                        .next()
+                        .and_then(|format| format.to_str())
+                        .and_then(ImportFormat::parse) else {
+                        return http_utils::send_response(http_utils::bad_request_msg("invalid_import_format".into()).unwrap(), &mut stream)
+                    };
+                    let account = path_iterator
+                        .next()
+                        .and_then(|account| account.to_str())
+                        .map(str::to_string);
+
+                    let Some(body) = body else {
+                        return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::user_import(stream.id, token, Box::new(StatementUpload { format, account, body }), stream));
+                    Ok(())
+                }
+
+                Content::UserExportRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    //the rest of the path picks the export: /user/export/json, or /user/export/csv/{table}
+                    let segments: Vec<&str> = path_iterator
+                        .filter_map(|segment| segment.to_str())
+                        .collect();
+
+                    let query = match segments[..] {
+                        ["json"] => UserQuery::ExportJson,
+                        ["csv", table] => match CsvTable::parse(table) {
+                            Some(table) => UserQuery::ExportCsv { table },
+                            None => return http_utils::send_response(http_utils::bad_request_msg("invalid_export_table".into()).unwrap(), &mut stream)
+                        },
+                        _ => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::user_query(stream.id, token, query, stream));
+                    Ok(())
+                }
+
+                Content::UserReportRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    //the date range is the rest of the path (/user/reports/2026-01-01/2026-03-31)
+                    let segments: Vec<&str> = path_iterator
+                        .filter_map(|segment| segment.to_str())
+                        .collect();
+
+                    let (from, to) = match reports::parse_range(&segments) {
+                        Ok(range) => range,
+                        Err(msg) => return http_utils::send_response(http_utils::bad_request_msg(msg).unwrap(), &mut stream)
+                    };
+
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::user_query(stream.id, token, UserQuery::Report { from, to }, stream));
+                    Ok(())
+                }
+
+                Content::UserNotificationsRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::user_query(stream.id, token, UserQuery::Notifications, stream));
+                    Ok(())
+                }
+
+                Content::UserForecastRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    //the number of pay periods is the rest of the path (/user/forecast/12)
+                    let periods = match path_iterator.next().and_then(|periods| periods.to_str()) {
+                        Some(periods) => match periods.parse::<u32>() {
+                            Ok(periods @ 1..=forecast::MAX_FORECAST_PERIODS) => periods,
+                            _ => return http_utils::send_response(http_utils::bad_request_msg("invalid_forecast_periods".into()).unwrap(), &mut stream)
+                        },
+                        None => forecast::DEFAULT_FORECAST_PERIODS,
+                    };
+
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::user_query(stream.id, token, UserQuery::Forecast { periods }, stream));
+                    Ok(())
+                }
+
+                Content::UserRestoreRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    let Some(body) = body else {
+                        return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::user_restore(stream.id, token, body, stream));
+                    Ok(())
+                }
+
+                Content::UserMembersRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    //a GET lists the budget's members, a POST (with a body) manages them
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::members(stream.id, token, body, stream));
+                    Ok(())
+                }
+
+                Content::UserBudgetsRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    //a GET lists the user's budgets and invitations, a POST (with a body) acts on them
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::budgets(stream.id, token, body, stream));
+                    Ok(())
+                }
+
+                Content::UserSharesRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    //a GET lists the budget's share links, a POST (with a body) creates or revokes one
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::shares(stream.id, token, body, stream));
+                    Ok(())
+                }
+
+                Content::SharedRequest => {
+
+                    //no login, the share token is the rest of the path (/shared/{token}/summary)
+                    let segments: Vec<&str> = path_iterator
+                        .filter_map(|segment| segment.to_str())
+                        .collect();
+
+                    let Some((token, view)) = segments.split_first() else {
+                        return http_utils::send_response(http_utils::not_found().unwrap(), &mut stream)
+                    };
+
+                    //read straight from the database, never through a user thread, so nothing can be changed
+                    match shares::serve(token, view) {
+                        Ok(response) => http_utils::send_response(response, &mut stream),
+                        Err(msg) => http_utils::send_response(http_utils::bad_request_msg(msg).unwrap(), &mut stream)
+                    }
+                }
+
+                Content::UserPeriodsRequest => {
+
+                    let token = match http_utils::find_header_in_request(&req, "authorization") {
+                        Some(token) => token,
+                        None => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    //two period numbers in the rest of the path (/user/periods/3/4) compares them,
+                    //otherwise every archived period is listed
+                    let numbers: Vec<u32> = path_iterator
+                        .filter_map(|number| number.to_str()?.parse::<u32>().ok())
+                        .collect();
+
+                    let query = match numbers[..] {
+                        [] => UserQuery::Periods,
+                        [first, second] => UserQuery::ComparePeriods { first, second },
+                        _ => return http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream)
+                    };
+
+                    let _ = self.send_message_to_user_thread(UserManagerThreadMessage::user_query(stream.id, token, query, stream));
+                    Ok(())
+                }
                 Content::File(_) => { 
                     todo!()
                 }
@@ -435,13 +871,155 @@ impl Server {
     }
 }
 
+//request_failed(): answers a connection whose request couldn't be read, then closes it
+//(408 REQUEST TIMEOUT if the client stalled for longer than the read timeout, 400, 413, 431...)
+fn request_failed(why: RequestError, stream: &mut TimedStream) -> Result<(), std::io::Error> {
+    let Some(status) = why.status() else {
+        //the client's gone, nobody to answer
+        return Ok(());
+    };
+    println!("{}{}\t{}", "--> ".bright_red().bold(), stream.id, format!("REQUEST FAILED: {}", why).bright_red());
+    let response = match why {
+        RequestError::Malformed(code) => http_utils::bad_request_msg(code.into()),
+        RequestError::BodyTooLarge => http_utils::content_too_large(),
+        _ => http_utils::empty_response(status),
+    };
+    http_utils::send_response(response.unwrap(), stream)
+}
+
+//redirect_to_https(): answers every request on the plain HTTP listener with a redirect to the same path over HTTPS,
+//on a small worker pool of its own so it can't hold up the real one
+fn redirect_to_https(listener: TcpListener, https_port: u16, max_connections: usize, limits: Limits, timeouts: Timeouts) {
+    eprintln!("\t\tredirector thread spawned:\t{}", metrics::thread_name_display());
+    println!("redirecting http on {:?} to https", listener.local_addr().unwrap());
+
+    let pool = WorkerPool::new(REDIRECT_WORKERS, max_connections, timeouts.keep_alive, move |mut stream: TimedStream| {
+        let stream_id = stream.id;
+        metrics::arrive(stream_id);
+        let _ = stream.set_timeouts(timeouts.read, timeouts.write);
+
+        let request = request::read_request(&mut BufReader::new(&stream.stream), &mut &stream.stream, &limits);
+        let _ = match request {
+            Ok(request) => {
+                let mut headers = [httparse::EMPTY_HEADER; request::MAX_HEADERS];
+                let req = request::parse_head(&request.head, &mut headers);
+                println!("{}{}\t{} {} (redirecting to https)", "--> ".bright_cyan().bold(), stream_id, req.method.unwrap_or("NONE"), req.path.unwrap_or("NONE"));
+                let response = match http_utils::find_header_in_request(&req, "host") {
+                    Some(host) => http_utils::redirect(https_location(&host, https_port, req.path.unwrap_or("/")))
+                        .or_else(|_| http_utils::bad_request_msg("invalid_host".into())),
+                    None => http_utils::bad_request_msg("missing_host".into()),
+                };
+                http_utils::send_response(response.unwrap(), &mut stream)
+            }
+            Err(why) => request_failed(why, &mut stream),
+        };
+
+        metrics::end(stream_id);
+    });
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => pool.dispatch(TimedStream::new(stream)),
+            Err(why) => eprintln!("redirect connection failed: {:?}", why),
+        }
+    }
+}
+
+//https_location(): where a plain HTTP request to the given host and path is sent, over HTTPS
+fn https_location(host: &str, https_port: u16, path: &str) -> String {
+    //drop the port the request came in on, leaving IPv6 addresses ([::1]) alone
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    if https_port == 443 {
+        format!("https://{}{}", name, path)
+    } else {
+        format!("https://{}:{}{}", name, https_port, path)
+    }
+}
+
 //generate_timeout_checks(): creates a looping timer, that sends a TimeoutCheck message
 //to the user manager thread every X seconds
-fn generate_timeout_checks(channel: mpsc::Sender<user_threads::UserManagerThreadMessage>) {
+fn generate_timeout_checks(channel: mpsc::Sender<user_threads::UserManagerThreadMessage>, interval: Duration) {
     eprintln!("\t\ttimeout thread spawned:\t{}", metrics::thread_name_display());
     loop {
-        thread::sleep(Duration::from_secs(TIMEOUT_INTERVAL));
+        thread::sleep(interval);
         //eprintln!("timeout check:");
         channel.send(user_threads::UserManagerThreadMessage::timeout_check());
     }
-}
\ No newline at end of file
+}
+
+//generate_schedule_checks(): creates a looping timer, that sends a ScheduleCheck message
+//to the user manager thread every X seconds (starting right away, to catch up on anything missed while offline)
+fn generate_schedule_checks(channel: mpsc::Sender<user_threads::UserManagerThreadMessage>, interval: Duration) {
+    eprintln!("\t\tscheduler thread spawned:\t{}", metrics::thread_name_display());
+    loop {
+        let _ = channel.send(user_threads::UserManagerThreadMessage::schedule_check());
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //PUT isn't routed, so it's answered with a 405 straight from the worker
+    const REQUEST: &str = "PUT / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    const LAST_REQUEST: &str = "PUT / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+    //serve(): answers connections on a port of the test's own, the way listen() does, keeping them alive for keep_alive
+    fn serve(keep_alive: Duration) -> TcpStream {
+        db::for_tests();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let connections = Connections {
+            router: Router::new(),
+            limits: Limits::default(),
+            auth_thread_sender: mpsc::channel().0,
+            users_thread_sender: mpsc::channel().0,
+        };
+        let pool = WorkerPool::new(2, 8, keep_alive, move |stream: TimedStream| {
+            let _ = stream.set_timeouts(Duration::from_secs(5), Duration::from_secs(5));
+            let _ = connections.handle_connection(stream);
+        });
+        thread::spawn(move || {
+            for socket in listener.incoming().flatten() {
+                pool.dispatch(TimedStream::new(socket));
+            }
+        });
+
+        let client = TcpStream::connect(address).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        client
+    }
+
+    //answers(): how many responses the server sent before closing the connection
+    fn answers(client: &mut TcpStream) -> usize {
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).unwrap();
+        responses.matches("HTTP/1.1 405").count()
+    }
+
+    #[test]
+    fn pipelined_requests_are_each_answered() {
+        let mut client = serve(Duration::from_secs(5));
+        client.write_all(format!("{}{}{}", REQUEST, REQUEST, LAST_REQUEST).as_bytes()).unwrap();
+        assert_eq!(answers(&mut client), 3);
+    }
+
+    #[test]
+    fn idle_connections_are_closed_after_the_keep_alive_timeout() {
+        let keep_alive = Duration::from_millis(300);
+        let mut client = serve(keep_alive);
+
+        //a request sent while the connection is idle, but before it times out, is still answered
+        client.write_all(REQUEST.as_bytes()).unwrap();
+        thread::sleep(keep_alive / 3);
+        client.write_all(REQUEST.as_bytes()).unwrap();
+
+        let since = Instant::now();
+        assert_eq!(answers(&mut client), 2);
+        assert!(since.elapsed() >= keep_alive);
+    }
+}
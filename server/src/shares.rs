@@ -0,0 +1,290 @@
+use chrono::{Local, TimeZone};
+use http_bytes::http::{self, StatusCode};
+use serde::{self, Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::endpoints::{database, users};
+use crate::http_utils;
+use crate::reports::{self, Report};
+
+//how long a share link lasts when no number of days is given
+const DEFAULT_SHARE_DAYS: u32 = 7;
+//the longest a share link can last
+const MAX_SHARE_DAYS: u32 = 90;
+
+//Scope: how much of a budget a share link shows
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    //the whole budget, its accounts and balances included
+    #[default]
+    Budget,
+    //only spending reports and the summary page, without any balances
+    Reports,
+}
+impl Scope {
+    //as_str(): the scope as it's stored in the shares table
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Budget => "budget",
+            Scope::Reports => "reports",
+        }
+    }
+
+    //parse(): reads a scope back out of the shares table
+    pub fn parse(scope: &str) -> Option<Scope> {
+        match scope {
+            "budget" => Some(Scope::Budget),
+            "reports" => Some(Scope::Reports),
+            _ => None,
+        }
+    }
+}
+
+//Share: a read-only link to a budget, for someone without a login
+#[derive(Debug, Serialize, Clone)]
+pub struct Share {
+    //the secret part of the link, /shared/{token}
+    pub token: String,
+    #[serde(skip)]
+    pub budget: Uuid,
+    pub scope: Scope,
+    //the username of the owner who made the link
+    pub created_by: String,
+    //when the link stops working, as a unix timestamp
+    pub expires: i64,
+}
+
+//SharesList: the json body sent back for /user/shares
+#[derive(Debug, Serialize)]
+pub struct SharesList {
+    pub shares: Vec<Share>,
+}
+
+//SharesRequest: what an owner can send to POST /user/shares, picked by its "action" field
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum SharesRequest {
+    //create: makes a new link, lasting the given number of days
+    Create { scope: Option<Scope>, days: Option<u32> },
+    //revoke: stops a link working before it expires
+    Revoke { token: String },
+}
+
+//manage(): runs an owner's shares request against their budget
+pub fn manage(budget: Uuid, username: &str, request: SharesRequest) -> Result<String, String> {
+    match request {
+        SharesRequest::Create { scope, days } => {
+            let days = days.unwrap_or(DEFAULT_SHARE_DAYS);
+            if !(1..=MAX_SHARE_DAYS).contains(&days) {
+                return Err(String::from("invalid_share_days"));
+            }
+            let share = Share {
+                //two random uuids, so the link can't be guessed
+                token: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+                budget,
+                scope: scope.unwrap_or_default(),
+                created_by: username.to_string(),
+                expires: (chrono::Utc::now() + chrono::Duration::days(i64::from(days))).timestamp(),
+            };
+            database::add_share(&share)?;
+            Ok(format!("Share link created for {} days: /shared/{}", days, share.token))
+        }
+        SharesRequest::Revoke { token } => {
+            if !database::remove_share(budget, &token)? {
+                return Err(String::from("share_not_found"));
+            }
+            Ok(String::from("Share link revoked"))
+        }
+    }
+}
+
+//shares_of(): every share link to a budget that hasn't expired
+pub fn shares_of(budget: Uuid) -> SharesList {
+    SharesList {
+        shares: database::get_shares(budget),
+    }
+}
+
+//serve(): answers a request to a share link, straight from the database so nothing can be changed through it
+//the rest of the path picks what's shown: /shared/{token}, /shared/{token}/reports/{from}/{to} or /shared/{token}/summary
+//unknown, expired and revoked links are all just not found
+pub fn serve(token: &str, view: &[&str]) -> Result<http::Response<Vec<u8>>, String> {
+    let Some(share) = database::get_share(token) else {
+        return http_utils::not_found();
+    };
+    let budget = users::get_user_data_from_uuid(share.budget);
+    let report = |segments: &[&str]| {
+        reports::parse_range(segments).map(|(from, to)| {
+            let ledger = database::get_ledger(share.budget, reports::since(from));
+            reports::build(&budget, &ledger, from, to)
+        })
+    };
+
+    match (share.scope, view) {
+        (Scope::Budget, []) => http_utils::ok_json(StatusCode::OK, budget.to_json().unwrap()),
+        (Scope::Reports, []) => http_utils::ok_json(StatusCode::OK, serde_json::to_string(&report(&[])?).unwrap()),
+        (_, ["reports", range @ ..]) => match report(range) {
+            Ok(report) => http_utils::ok_json(StatusCode::OK, serde_json::to_string(&report).unwrap()),
+            Err(msg) => http_utils::bad_request_msg(msg),
+        },
+        (_, ["summary"]) => {
+            let report = report(&[])?;
+            http_utils::ok_html(StatusCode::OK, summary_page(&share, &budget, &report))
+        }
+        _ => http_utils::not_found(),
+    }
+}
+
+//escape(): makes user text safe to put in a page
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+//summary_page(): a plain page showing this month's spending against the budget
+//(and the accounts, for links to the whole budget)
+fn summary_page(share: &Share, budget: &crate::budget::Budget, report: &Report) -> String {
+    let expires = Local
+        .timestamp_opt(share.expires, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+
+    let mut page = String::from(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n    <meta charset=\"UTF-8\">\n    <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n    <title>Budget summary</title>\n</head>\n<body>\n",
+    );
+    page.push_str(&format!("    <h1>{}'s budget</h1>\n", escape(budget.username())));
+    page.push_str(&format!(
+        "    <p>Shared read-only by {} until {}. {} to {}.</p>\n",
+        escape(&share.created_by),
+        expires,
+        report.from,
+        report.to
+    ));
+
+    page.push_str("    <h2>This month</h2>\n    <table>\n");
+    for (name, amount) in [("Income", report.totals.income), ("Spent", report.totals.spent), ("Saved", report.totals.saved)] {
+        page.push_str(&format!("        <tr><th>{}</th><td>{}</td></tr>\n", name, budget.money(amount)));
+    }
+    page.push_str("    </table>\n");
+
+    page.push_str("    <h2>Expenses this pay period</h2>\n    <table>\n        <tr><th>Category</th><th>Expected</th><th>Spent</th><th>Left</th></tr>\n");
+    for row in &report.variance {
+        page.push_str(&format!(
+            "        <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&row.category),
+            budget.money(row.expected),
+            budget.money(row.actual),
+            budget.money(row.variance)
+        ));
+    }
+    page.push_str("    </table>\n");
+
+    if share.scope == Scope::Budget {
+        page.push_str("    <h2>Accounts</h2>\n    <table>\n");
+        for name in budget.accounts().keys() {
+            page.push_str(&format!(
+                "        <tr><td>{}</td><td>{}</td></tr>\n",
+                escape(name),
+                budget.money_in(name, budget.balance_of(Some(name)))
+            ));
+        }
+        page.push_str("    </table>\n");
+    }
+
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use serde_json::json;
+
+    //budget(): a new user's budget to share
+    fn budget() -> Uuid {
+        db::for_tests();
+        let username = Uuid::new_v4().simple().to_string();
+        let Ok((user, _token)) = users::register(json!({"username": username, "password": "hunter2"}).to_string()) else {
+            panic!("couldn't register {}", username);
+        };
+        database::default_budget(user).unwrap()
+    }
+
+    //create(): makes a share link and gives back its token
+    fn create(budget: Uuid, scope: Scope) -> String {
+        manage(budget, "owner", SharesRequest::Create { scope: Some(scope), days: None }).unwrap();
+        let shares = shares_of(budget).shares;
+        shares.into_iter().find(|share| share.scope == scope).unwrap().token
+    }
+
+    fn body(response: &http::Response<Vec<u8>>) -> String {
+        String::from_utf8(response.body().clone()).unwrap()
+    }
+
+    #[test]
+    fn links_show_only_their_scope() {
+        let budget = budget();
+        let whole = create(budget, Scope::Budget);
+        let reports = create(budget, Scope::Reports);
+        assert_eq!(shares_of(budget).shares.len(), 2);
+
+        let response = serve(&whole, &[]).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body(&response).contains("\"accounts\""));
+        let response = serve(&reports, &[]).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!body(&response).contains("\"accounts\""));
+
+        //the summary page only lists accounts for links to the whole budget
+        assert!(body(&serve(&whole, &["summary"]).unwrap()).contains("<h2>Accounts</h2>"));
+        assert!(!body(&serve(&reports, &["summary"]).unwrap()).contains("<h2>Accounts</h2>"));
+        assert_eq!(serve(&reports, &["edit"]).unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn revoked_and_expired_links_are_not_found() {
+        let budget = budget();
+        let token = create(budget, Scope::Budget);
+
+        //only the budget's own owner can revoke it
+        let revoke = |budget| manage(budget, "owner", SharesRequest::Revoke { token: token.clone() });
+        assert_eq!(revoke(Uuid::new_v4()), Err(String::from("share_not_found")));
+        assert!(revoke(budget).is_ok());
+        assert_eq!(serve(&token, &[]).unwrap().status(), StatusCode::NOT_FOUND);
+        assert_eq!(revoke(budget), Err(String::from("share_not_found")));
+
+        let expired = Share {
+            token: Uuid::new_v4().simple().to_string(),
+            budget,
+            scope: Scope::Budget,
+            created_by: String::from("owner"),
+            expires: chrono::Utc::now().timestamp() - 1,
+        };
+        database::add_share(&expired).unwrap();
+        assert_eq!(serve(&expired.token, &[]).unwrap().status(), StatusCode::NOT_FOUND);
+        assert!(shares_of(budget).shares.is_empty());
+    }
+
+    #[test]
+    fn links_last_between_a_day_and_the_limit() {
+        let budget = budget();
+        for days in [0, MAX_SHARE_DAYS + 1] {
+            let request = SharesRequest::Create { scope: None, days: Some(days) };
+            assert_eq!(manage(budget, "owner", request), Err(String::from("invalid_share_days")));
+        }
+        let request = SharesRequest::Create { scope: None, days: Some(MAX_SHARE_DAYS) };
+        assert!(manage(budget, "owner", request).is_ok());
+        assert_eq!(shares_of(budget).shares[0].scope, Scope::Budget);
+    }
+
+    #[test]
+    fn summary_pages_escape_what_users_typed() {
+        assert_eq!(escape("<b>\"Tom & Jerry's\"</b>"), "&lt;b&gt;&quot;Tom &amp; Jerry&#39;s&quot;&lt;/b&gt;");
+    }
+}
@@ -1,4 +1,6 @@
 //used for handling authentication requests (register/login)
 pub mod auth;
 //used for handling logged in users
-pub mod user_threads;
\ No newline at end of file
+pub mod user_threads;
+//used for the pool of workers that read incoming connections
+pub mod workers;
\ No newline at end of file
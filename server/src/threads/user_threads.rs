@@ -1,19 +1,26 @@
-use std::collections::HashMap;
-use std::sync::mpsc;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{mpsc, Condvar, Mutex};
 
 use std::thread;
 use std::time::{Duration, Instant};
 
+use chrono::NaiveDate;
 use http_bytes::http::StatusCode;
 use uuid::Uuid;
 
-use crate::budget::{self, Budget};
-use crate::endpoints::{self, users};
+use crate::budget::Budget;
+use crate::commands::{self, CommandRequest};
+use crate::endpoints::{self, database, users};
+use crate::export::{CsvTable, ExportDocument};
+use crate::history::History;
+use crate::import::{self, StatementUpload};
+use crate::members::{self, BudgetsRequest, Member, MemberChange, MembersRequest, Role};
+use crate::shares::{self, SharesRequest};
+use crate::{config, forecast, reports};
 use crate::server::TimedStream;
+use crate::http_utils::ErrorBody;
 use crate::{http_utils, metrics};
 
-const SECONDS_TO_TIMEOUT_USER_THREAD: u64 = 30 * 60;
-
 pub struct UserManagerThreadMessage {
     pub id: Option<usize>,
     pub msg: UserManagerMessageType,
@@ -40,6 +47,21 @@ impl UserManagerThreadMessage {
             },
         }
     }
+    pub fn user_batch(
+        id: usize,
+        token: String,
+        jsondata: String,
+        stream: TimedStream,
+    ) -> UserManagerThreadMessage {
+        UserManagerThreadMessage {
+            id: Some(id),
+            msg: UserManagerMessageType::UserBatch {
+                token,
+                jsondata,
+                stream,
+            },
+        }
+    }
     pub fn user_data_request(
         id: usize,
         token: String,
@@ -50,6 +72,57 @@ impl UserManagerThreadMessage {
             msg: UserManagerMessageType::UserDataRequest { token, stream },
         }
     }
+    pub fn user_query(
+        id: usize,
+        token: String,
+        query: UserQuery,
+        stream: TimedStream,
+    ) -> UserManagerThreadMessage {
+        UserManagerThreadMessage {
+            id: Some(id),
+            msg: UserManagerMessageType::UserQuery { token, query, stream },
+        }
+    }
+    pub fn user_import(
+        id: usize,
+        token: String,
+        upload: Box<StatementUpload>,
+        stream: TimedStream,
+    ) -> UserManagerThreadMessage {
+        UserManagerThreadMessage {
+            id: Some(id),
+            msg: UserManagerMessageType::UserImport { token, upload, stream },
+        }
+    }
+    pub fn user_restore(
+        id: usize,
+        token: String,
+        body: String,
+        stream: TimedStream,
+    ) -> UserManagerThreadMessage {
+        UserManagerThreadMessage {
+            id: Some(id),
+            msg: UserManagerMessageType::UserRestore { token, body, stream },
+        }
+    }
+    pub fn members(id: usize, token: String, body: Option<String>, stream: TimedStream) -> UserManagerThreadMessage {
+        UserManagerThreadMessage {
+            id: Some(id),
+            msg: UserManagerMessageType::Members { token, body, stream },
+        }
+    }
+    pub fn budgets(id: usize, token: String, body: Option<String>, stream: TimedStream) -> UserManagerThreadMessage {
+        UserManagerThreadMessage {
+            id: Some(id),
+            msg: UserManagerMessageType::Budgets { token, body, stream },
+        }
+    }
+    pub fn shares(id: usize, token: String, body: Option<String>, stream: TimedStream) -> UserManagerThreadMessage {
+        UserManagerThreadMessage {
+            id: Some(id),
+            msg: UserManagerMessageType::Shares { token, body, stream },
+        }
+    }
     pub fn shutdown(id: usize, token: String, stream: TimedStream) -> UserManagerThreadMessage {
         UserManagerThreadMessage {
             id: Some(id),
@@ -62,6 +135,18 @@ impl UserManagerThreadMessage {
             msg: UserManagerMessageType::TimeoutCheck,
         }
     }
+    pub fn schedule_check() -> UserManagerThreadMessage {
+        UserManagerThreadMessage {
+            id: None,
+            msg: UserManagerMessageType::ScheduleCheck,
+        }
+    }
+    fn idle(budget: Uuid, ack: mpsc::Sender<()>) -> UserManagerThreadMessage {
+        UserManagerThreadMessage {
+            id: None,
+            msg: UserManagerMessageType::Idle { budget, ack },
+        }
+    }
 }
 
 pub enum UserManagerMessageType {
@@ -74,15 +159,71 @@ pub enum UserManagerMessageType {
         jsondata: String,
         stream: TimedStream,
     },
+    UserBatch {
+        token: String,
+        jsondata: String,
+        stream: TimedStream,
+    },
     UserDataRequest {
         token: String,
         stream: TimedStream,
     },
+    UserQuery {
+        token: String,
+        query: UserQuery,
+        stream: TimedStream,
+    },
+    UserImport {
+        token: String,
+        upload: Box<StatementUpload>,
+        stream: TimedStream,
+    },
+    UserRestore {
+        token: String,
+        body: String,
+        stream: TimedStream,
+    },
+    //Members: list (no body) or manage (owners only) who has access to the session's budget
+    Members {
+        token: String,
+        body: Option<String>,
+        stream: TimedStream,
+    },
+    //Budgets: list (no body) or act on the budgets the session's user belongs to, or is invited to
+    Budgets {
+        token: String,
+        body: Option<String>,
+        stream: TimedStream,
+    },
+    //Shares: list (no body), create or revoke the read-only share links to the session's budget
+    Shares {
+        token: String,
+        body: Option<String>,
+        stream: TimedStream,
+    },
     Shutdown {
         token: String,
         stream: TimedStream,
     },
     TimeoutCheck,
+    ScheduleCheck,
+    //Idle: a budget's thread has gone too long without a command, and waits on ack to hear it's been forgotten
+    Idle {
+        budget: Uuid,
+        ack: mpsc::Sender<()>,
+    },
+}
+
+//UserQuery: read-only requests for a user's data, beyond the Budget itself
+pub enum UserQuery {
+    History { page: usize },
+    Periods,
+    ComparePeriods { first: u32, second: u32 },
+    ExportJson,
+    ExportCsv { table: CsvTable },
+    Report { from: NaiveDate, to: NaiveDate },
+    Forecast { periods: u32 },
+    Notifications,
 }
 
 struct UserThreadMessage {
@@ -92,12 +233,24 @@ struct UserThreadMessage {
 impl UserThreadMessage {
     pub fn user_command(
         id: Option<usize>,
+        member: String,
         jsondata: String,
         stream: TimedStream,
     ) -> UserThreadMessage {
         UserThreadMessage {
             id,
-            cmd: UserThreadCommandType::UserCommand { jsondata, stream },
+            cmd: UserThreadCommandType::UserCommand { member, jsondata, stream },
+        }
+    }
+    pub fn user_batch(
+        id: Option<usize>,
+        member: String,
+        jsondata: String,
+        stream: TimedStream,
+    ) -> UserThreadMessage {
+        UserThreadMessage {
+            id,
+            cmd: UserThreadCommandType::UserBatch { member, jsondata, stream },
         }
     }
     pub fn user_data_request(id: Option<usize>, stream: TimedStream) -> UserThreadMessage {
@@ -106,6 +259,29 @@ impl UserThreadMessage {
             cmd: UserThreadCommandType::UserDataRequest { stream },
         }
     }
+    pub fn user_query(id: Option<usize>, query: UserQuery, stream: TimedStream) -> UserThreadMessage {
+        UserThreadMessage {
+            id,
+            cmd: UserThreadCommandType::UserQuery { query, stream },
+        }
+    }
+    pub fn user_import(
+        id: Option<usize>,
+        member: String,
+        upload: Box<StatementUpload>,
+        stream: TimedStream,
+    ) -> UserThreadMessage {
+        UserThreadMessage {
+            id,
+            cmd: UserThreadCommandType::UserImport { member, upload, stream },
+        }
+    }
+    pub fn user_restore(id: Option<usize>, member: String, body: String, stream: TimedStream) -> UserThreadMessage {
+        UserThreadMessage {
+            id,
+            cmd: UserThreadCommandType::UserRestore { member, body, stream },
+        }
+    }
     pub fn shutdown(id: Option<usize>) -> UserThreadMessage {
         UserThreadMessage {
             id,
@@ -118,35 +294,264 @@ impl UserThreadMessage {
             cmd: UserThreadCommandType::TimeoutCheck,
         }
     }
-    pub fn check(id: Option<usize>) -> UserThreadMessage {
+    pub fn schedule_check(today: NaiveDate) -> UserThreadMessage {
         UserThreadMessage {
-            id,
-            cmd: UserThreadCommandType::Check,
+            id: None,
+            cmd: UserThreadCommandType::ScheduleCheck { today },
         }
     }
 }
+//commands that change the budget carry the username of the member who sent them, for the ledger
 enum UserThreadCommandType {
     UserCommand {
+        member: String,
+        jsondata: String,
+        stream: TimedStream,
+    },
+    UserBatch {
+        member: String,
         jsondata: String,
         stream: TimedStream,
     },
     UserDataRequest {
         stream: TimedStream,
     },
+    UserQuery {
+        query: UserQuery,
+        stream: TimedStream,
+    },
+    UserImport {
+        member: String,
+        upload: Box<StatementUpload>,
+        stream: TimedStream,
+    },
+    UserRestore {
+        member: String,
+        body: String,
+        stream: TimedStream,
+    },
     Shutdown,
     TimeoutCheck,
-    Check,
+    ScheduleCheck {
+        today: NaiveDate,
+    },
+}
+
+//Session: a logged-in token, the member it belongs to, and the budget it's working on
+struct Session {
+    budget: Uuid,
+    member: Member,
+}
+
+//Sessions: every logged-in token, and the one thread each budget they have open runs on
+//every session on the same budget shares its thread, so their commands run one at a time
+struct Sessions {
+    tokens: HashMap<String, Session>,
+    threads: HashMap<Uuid, mpsc::Sender<UserThreadMessage>>,
+    //the manager's own channel, handed to each budget thread so it can say when it's gone idle
+    manager: mpsc::Sender<UserManagerThreadMessage>,
+}
+impl Sessions {
+    fn new(manager: mpsc::Sender<UserManagerThreadMessage>) -> Sessions {
+        Sessions { tokens: HashMap::new(), threads: HashMap::new(), manager }
+    }
+
+    //send(): passes a message on to the thread a budget runs on
+    fn send(&self, budget: Uuid, msg: UserThreadMessage) {
+        if let Some(sender) = self.threads.get(&budget) {
+            let _ = sender.send(msg);
+        }
+    }
+}
+
+//open_session(): points a token at a budget the user belongs to, starting the budget's thread if it isn't running
+fn open_session(sessions: &mut Sessions, token: String, user: Uuid, budget: Uuid) -> Result<(), String> {
+    let member = database::get_member(budget, user).ok_or_else(|| String::from("budget_not_found"))?;
+
+    //let go of whatever the token had open first, so moving within the same budget doesn't shut its thread down
+    close_session(sessions, &token);
+
+    if !sessions.threads.contains_key(&budget) {
+        let (host_sender, thread_receiver) = mpsc::channel::<UserThreadMessage>();
+        let manager = sessions.manager.clone();
+        thread::Builder::new()
+            .name(budget.to_string())
+            .spawn(move || handle_user(budget, thread_receiver, manager))
+            .map_err(|_err| format!("failure to create thread for budget {:?} !", budget))?;
+        sessions.threads.insert(budget, host_sender);
+    }
+
+    sessions.tokens.insert(token, Session { budget, member });
+    Ok(())
+}
+
+//close_session(): forgets a token, shutting its budget's thread down if no other session is using it
+fn close_session(sessions: &mut Sessions, token: &str) -> bool {
+    let Some(session) = sessions.tokens.remove(token) else {
+        return false;
+    };
+    if !sessions.tokens.values().any(|other| other.budget == session.budget) {
+        if let Some(sender) = sessions.threads.remove(&session.budget) {
+            let _ = sender.send(UserThreadMessage::shutdown(None));
+        }
+    }
+    true
+}
+
+//session_for(): the session a token belongs to, sending back 401 UNAUTHORIZED if it doesn't belong to one
+fn session_for<'a>(sessions: &'a Sessions, token: &str, stream: &mut TimedStream) -> Option<&'a Session> {
+    let session = sessions.tokens.get(token);
+    if session.is_none() {
+        let _ = http_utils::send_response(http_utils::unauthorized().unwrap(), stream);
+    }
+    session
+}
+
+//editor_for(): like session_for(), but also sending back 403 FORBIDDEN to members who can only view the budget
+fn editor_for<'a>(sessions: &'a Sessions, token: &str, stream: &mut TimedStream) -> Option<&'a Session> {
+    let session = session_for(sessions, token, stream)?;
+    if !session.member.role.can_edit() {
+        let _ = http_utils::send_response(http_utils::forbidden_msg("read_only_member".into()).unwrap(), stream);
+        return None;
+    }
+    Some(session)
+}
+
+//handle_members(): lists or manages who has access to a session's budget
+fn handle_members(sessions: &mut Sessions, token: &str, body: Option<String>, stream: &mut TimedStream) {
+    let Some(session) = session_for(sessions, token, stream) else {
+        return;
+    };
+    let budget = session.budget;
+
+    let Some(body) = body else {
+        let list = members::members_of(budget);
+        let _ = http_utils::send_response(http_utils::ok_json(StatusCode::OK, serde_json::to_string(&list).unwrap()).unwrap(), stream);
+        return;
+    };
+    if session.member.role != Role::Owner {
+        let _ = http_utils::send_response(http_utils::forbidden_msg("owner_only".into()).unwrap(), stream);
+        return;
+    }
+
+    let result = serde_json::from_str::<MembersRequest>(&body)
+        .map_err(|_err| String::from("invalid_members_request"))
+        .and_then(|request| members::manage(budget, request));
+    let response = match result {
+        Ok((message, change)) => {
+            //sessions follow the change straight away, rather than when they next log in
+            match change {
+                MemberChange::None => {}
+                MemberChange::Role(user, role) => {
+                    for session in sessions.tokens.values_mut() {
+                        if session.budget == budget && session.member.user == user {
+                            session.member.role = role;
+                        }
+                    }
+                }
+                MemberChange::Removed(user) => {
+                    let tokens: Vec<String> = sessions
+                        .tokens
+                        .iter()
+                        .filter(|(_, session)| session.budget == budget && session.member.user == user)
+                        .map(|(token, _)| token.clone())
+                        .collect();
+                    for token in tokens {
+                        let moved = database::default_budget(user)
+                            .and_then(|budget| open_session(sessions, token.clone(), user, budget));
+                        if moved.is_err() {
+                            close_session(sessions, &token);
+                        }
+                    }
+                }
+            }
+            let mut body = serde_json::to_value(members::members_of(budget)).unwrap();
+            body["message"] = message.into();
+            http_utils::ok_json(StatusCode::OK, body.to_string())
+        }
+        Err(msg) => http_utils::bad_request_msg(msg),
+    };
+    let _ = http_utils::send_response(response.unwrap(), stream);
+}
+
+//handle_budgets(): lists or acts on the budgets a session's user belongs to, or is invited to
+fn handle_budgets(sessions: &mut Sessions, token: &str, body: Option<String>, stream: &mut TimedStream) {
+    let Some(session) = session_for(sessions, token, stream) else {
+        return;
+    };
+    let (user, mut current) = (session.member.user, session.budget);
+
+    let mut message = None;
+    if let Some(body) = body {
+        let result = serde_json::from_str::<BudgetsRequest>(&body)
+            .map_err(|_err| String::from("invalid_budgets_request"))
+            .and_then(|request| members::respond(user, current, request))
+            .and_then(|(text, moved)| {
+                if let Some(budget) = moved {
+                    open_session(sessions, token.to_string(), user, budget)?;
+                    current = budget;
+                }
+                Ok(text)
+            });
+        match result {
+            Ok(text) => message = Some(text),
+            Err(msg) => {
+                let _ = http_utils::send_response(http_utils::bad_request_msg(msg).unwrap(), stream);
+                return;
+            }
+        }
+    }
+
+    let mut body = serde_json::to_value(members::budgets_of(user, current)).unwrap();
+    if let Some(message) = message {
+        body["message"] = message.into();
+    }
+    let _ = http_utils::send_response(http_utils::ok_json(StatusCode::OK, body.to_string()).unwrap(), stream);
+}
+
+//handle_shares(): lists, creates or revokes the share links to a session's budget, which only its owner can
+fn handle_shares(sessions: &Sessions, token: &str, body: Option<String>, stream: &mut TimedStream) {
+    let Some(session) = session_for(sessions, token, stream) else {
+        return;
+    };
+    if session.member.role != Role::Owner {
+        let _ = http_utils::send_response(http_utils::forbidden_msg("owner_only".into()).unwrap(), stream);
+        return;
+    }
+
+    let mut message = None;
+    if let Some(body) = body {
+        let result = serde_json::from_str::<SharesRequest>(&body)
+            .map_err(|_err| String::from("invalid_shares_request"))
+            .and_then(|request| shares::manage(session.budget, &session.member.username, request));
+        match result {
+            Ok(text) => message = Some(text),
+            Err(msg) => {
+                let _ = http_utils::send_response(http_utils::bad_request_msg(msg).unwrap(), stream);
+                return;
+            }
+        }
+    }
+
+    let mut body = serde_json::to_value(shares::shares_of(session.budget)).unwrap();
+    if let Some(message) = message {
+        body["message"] = message.into();
+    }
+    let _ = http_utils::send_response(http_utils::ok_json(StatusCode::OK, body.to_string()).unwrap(), stream);
 }
 
 //handle_user_threads(): manage all threads for logged-in users
 //serves to listen for messages from the main thread and create new user threads
 //or pass messages to existing ones.
+//thread_sender_to_self is a sender to the manager's own channel, which budget threads use to say they've gone idle
 pub fn handle_user_threads(
     thread_sender_to_main: mpsc::Sender<UserManagerThreadMessage>,
     thread_receiver_from_main: mpsc::Receiver<UserManagerThreadMessage>,
+    thread_sender_to_self: mpsc::Sender<UserManagerThreadMessage>,
 ) {
-    //create a map to link jsonwebtokens to users
-    let mut thread_map: HashMap<String, mpsc::Sender<UserThreadMessage>> = HashMap::new();
+    //link jsonwebtokens to sessions, and budgets to their threads
+    //several tokens share the same thread when several members have a budget open
+    let mut sessions = Sessions::new(thread_sender_to_self);
 
     eprintln!(
         "\t\tuser manager thread spawned:\t{}",
@@ -164,27 +569,19 @@ pub fn handle_user_threads(
 
         //check message type
         match msg.msg {
-            //Creation: create a new thread, linking a JSONWEBTOKEN to a UUID
+            //Creation: link a JSONWEBTOKEN to a user, on the budget they work on by default
             UserManagerMessageType::Creation { id, token } => {
-                //create the channel
-                let (host_sender, thread_receiver) = mpsc::channel::<UserThreadMessage>();
-                let thread_token = token.clone();
-
-                //spawn the thread
-                let Ok(handle) = thread::Builder::new().name(id.to_string()).spawn(move || {
-                    handle_user(id, thread_token, thread_receiver);
-                }) else {
-                    eprintln!("failure to create thread for user {:?} !", id);
+                let opened = database::default_budget(id).and_then(|budget| open_session(&mut sessions, token, id, budget));
+                if let Err(why) = opened {
+                    eprintln!("failure to open a session for user {:?}: {}", id, why);
                     continue;
-                };
-
-                //insert the thread link into the map
-                thread_map.insert(token, host_sender);
+                }
 
                 println!(
-                    "\t\t{} - currently managing {} threads",
+                    "\t\t{} - currently managing {} sessions on {} threads",
                     metrics::thread_name_display(),
-                    thread_map.len()
+                    sessions.tokens.len(),
+                    sessions.threads.len()
                 );
             }
 
@@ -194,64 +591,105 @@ pub fn handle_user_threads(
                 jsondata,
                 mut stream,
             } => {
-                //check the thread_map
-                match thread_map.get(&token) {
-                    //if it exists
-                    Some(sender) => {
-                        //send the data
-                        sender.send(UserThreadMessage::user_command(msg.id, jsondata, stream));
-                    }
-                    //otherwise,
-                    None => {
-                        //send an unauthorized response (token is invalid)
-                        http_utils::send_response(http_utils::unauthorized().unwrap(), &mut stream)
-                            .unwrap();
-                    }
+                if let Some(session) = editor_for(&sessions, &token, &mut stream) {
+                    let member = session.member.username.clone();
+                    sessions.send(session.budget, UserThreadMessage::user_command(msg.id, member, jsondata, stream));
+                }
+            }
+            //UserBatch: pass a batch of commands to an existing user thread
+            UserManagerMessageType::UserBatch {
+                token,
+                jsondata,
+                mut stream,
+            } => {
+                if let Some(session) = editor_for(&sessions, &token, &mut stream) {
+                    let member = session.member.username.clone();
+                    sessions.send(session.budget, UserThreadMessage::user_batch(msg.id, member, jsondata, stream));
                 }
             }
             //UserDataRequest: return requested loaded user data
             UserManagerMessageType::UserDataRequest { token, mut stream } => {
-                match thread_map.get(&token) {
-                    Some(sender) => {
-                        sender.send(UserThreadMessage::user_data_request(msg.id, stream));
-                    }
-                    None => {
-                        http_utils::send_response(http_utils::unauthorized().unwrap(), &mut stream)
-                            .unwrap();
+                if let Some(session) = session_for(&sessions, &token, &mut stream) {
+                    sessions.send(session.budget, UserThreadMessage::user_data_request(msg.id, stream));
+                }
+            }
+            //UserQuery: pass a read-only query to an existing user thread
+            UserManagerMessageType::UserQuery { token, query, mut stream } => {
+                if let Some(session) = session_for(&sessions, &token, &mut stream) {
+                    sessions.send(session.budget, UserThreadMessage::user_query(msg.id, query, stream));
+                }
+            }
+            //UserImport: pass a bank statement upload to an existing user thread
+            UserManagerMessageType::UserImport { token, upload, mut stream } => {
+                if let Some(session) = editor_for(&sessions, &token, &mut stream) {
+                    let member = session.member.username.clone();
+                    sessions.send(session.budget, UserThreadMessage::user_import(msg.id, member, upload, stream));
+                }
+            }
+            //UserRestore: pass an export document to be restored to an existing user thread
+            //restoring replaces the whole budget, so only its owner can
+            UserManagerMessageType::UserRestore { token, body, mut stream } => {
+                if let Some(session) = session_for(&sessions, &token, &mut stream) {
+                    if session.member.role == Role::Owner {
+                        let member = session.member.username.clone();
+                        sessions.send(session.budget, UserThreadMessage::user_restore(msg.id, member, body, stream));
+                    } else {
+                        let _ = http_utils::send_response(http_utils::forbidden_msg("owner_only".into()).unwrap(), &mut stream);
                     }
                 }
             }
-            //Shurdown: kill thread
+            //Members: list or manage the members of a session's budget
+            UserManagerMessageType::Members { token, body, mut stream } => {
+                handle_members(&mut sessions, &token, body, &mut stream);
+            }
+            //Budgets: list or act on the budgets a session's user belongs to
+            UserManagerMessageType::Budgets { token, body, mut stream } => {
+                handle_budgets(&mut sessions, &token, body, &mut stream);
+            }
+            //Shares: list or manage the share links to a session's budget
+            UserManagerMessageType::Shares { token, body, mut stream } => {
+                handle_shares(&sessions, &token, body, &mut stream);
+            }
+            //Shurdown: forget the token, and kill its thread if nobody else is using it
             UserManagerMessageType::Shutdown { token, mut stream } => {
-                match thread_map.get(&token) {
-                    Some(sender) => {
-                        sender.send(UserThreadMessage::shutdown(msg.id));
-                        thread_map.remove(&token);
-                        http_utils::send_response(
-                            http_utils::empty_response(StatusCode::OK).unwrap(),
-                            &mut stream,
-                        );
-                    }
-                    None => {
-                        //doesnt exist, do nothing lol
-                        http_utils::send_response(http_utils::not_found().unwrap(), &mut stream);
-                    }
+                if close_session(&mut sessions, &token) {
+                    let _ = http_utils::send_response(
+                        http_utils::empty_response(StatusCode::OK).unwrap(),
+                        &mut stream,
+                    );
+                } else {
+                    //doesnt exist, do nothing lol
+                    let _ = http_utils::send_response(http_utils::not_found().unwrap(), &mut stream);
                 }
             }
-            //TimeoutCheck: check all threads for timeout
+            //TimeoutCheck: have every thread check whether it's timed out, the ones that have will say so with Idle
             UserManagerMessageType::TimeoutCheck => {
-                /*let output = format!(
-                    "  [ user manager timeout check ] : {} -> ",
-                    thread_map.len()
-                );*/
-
-                //TODO: wait for response (of "all good!" or "im dead!") instead of looping twice!!!
-                for (k, v) in thread_map.iter() {
-                    v.send(UserThreadMessage::timeout_check());
+                for sender in sessions.threads.values() {
+                    let _ = sender.send(UserThreadMessage::timeout_check());
+                }
+            }
+            //Idle: forget a thread that's timed out, along with every session on its budget, then let it finish up
+            UserManagerMessageType::Idle { budget, ack } => {
+                sessions.threads.remove(&budget);
+                sessions.tokens.retain(|_, session| session.budget != budget);
+                let _ = ack.send(());
+            }
+            //ScheduleCheck: post every budget's scheduled incomes and expenses that have come due
+            UserManagerMessageType::ScheduleCheck => {
+                let today = chrono::Local::now().date_naive();
+
+                //open budgets are loaded in their own thread, so it handles theirs
+                for sender in sessions.threads.values() {
+                    let _ = sender.send(UserThreadMessage::schedule_check(today));
+                }
+
+                //everyone else is updated straight from the database, on a thread of its own so logins aren't held up
+                let swept = thread::Builder::new()
+                    .name("schedule_sweep".into())
+                    .spawn(move || sweep_schedules(today));
+                if swept.is_err() {
+                    eprintln!("failure to create a thread for the schedule sweep !");
                 }
-                thread::sleep(Duration::from_millis(50));
-                thread_map.retain(|k, v| v.send(UserThreadMessage::check(msg.id)).is_ok());
-                //println!("{}{} threads after timeout\n", output, thread_map.len());
             }
         }
 
@@ -262,17 +700,24 @@ pub fn handle_user_threads(
     }
 }
 
-fn handle_user(id: Uuid, token: String, receiver: mpsc::Receiver<UserThreadMessage>) {
+//manager is the user manager's channel, told when the thread has gone idle so it stops sending it anything
+fn handle_user(id: Uuid, receiver: mpsc::Receiver<UserThreadMessage>, manager: mpsc::Sender<UserManagerThreadMessage>) {
     println!(
         "\t\t\tuser thread spawned:\t{}",
         metrics::thread_name_display()
     );
 
+    //hold the budget for as long as the thread runs, once whoever had it last (a sweep, or its last thread) has saved it
+    let _claim = Claim::wait(id);
+
     //keep track of how long since last command, for timing out
     let mut time_of_last_command = Instant::now();
+    //whether the manager has forgotten the thread, which is left to finish what was sent before that and stop
+    let mut forgotten = false;
 
     //load user data from database TODO: MOVE CALL INTO db.rs INSTEAD OF users.rs
     let mut user_budget: Budget = users::get_user_data_from_uuid(id);
+    let mut user_history: History = users::get_user_history_from_uuid(id);
 
     //loop through messages from manager
     'thread_loop: for msg in receiver.iter() {
@@ -284,13 +729,122 @@ fn handle_user(id: Uuid, token: String, receiver: mpsc::Receiver<UserThreadMessa
             //UserDataRequest: json stringify the loaded budget data
             UserThreadCommandType::UserDataRequest { mut stream } => {
                 time_of_last_command = Instant::now();
-                let jsondata = serde_json::to_string(&user_budget).unwrap();
+                let jsondata = user_budget.to_json().unwrap();
                 http_utils::send_response(
                     http_utils::ok_json(StatusCode::OK, jsondata).unwrap(),
                     &mut stream,
                 );
                 continue 'thread_loop;
             }
+            //UserQuery: answer a read-only query about the user's data
+            UserThreadCommandType::UserQuery { query, mut stream } => {
+                time_of_last_command = Instant::now();
+                let response = match query {
+                    UserQuery::History { page } => {
+                        let page = endpoints::database::get_ledger_page(id, page);
+                        http_utils::ok_json(StatusCode::OK, serde_json::to_string(&page).unwrap())
+                    }
+                    UserQuery::Periods => {
+                        http_utils::ok_json(StatusCode::OK, serde_json::to_string(&user_history.periods()).unwrap())
+                    }
+                    UserQuery::ComparePeriods { first, second } => {
+                        match user_history.compare_periods(first, second) {
+                            Some(comparison) => http_utils::ok_json(StatusCode::OK, serde_json::to_string(&comparison).unwrap()),
+                            None => http_utils::bad_request_msg("period_not_found".into()),
+                        }
+                    }
+                    UserQuery::ExportJson => {
+                        let ledger = endpoints::database::get_ledger(id, None);
+                        let document = ExportDocument::new(&user_budget, &user_history, ledger);
+                        http_utils::ok_download(
+                            StatusCode::OK,
+                            "application/json",
+                            "budget.json",
+                            serde_json::to_string(&document).unwrap(),
+                        )
+                    }
+                    UserQuery::Report { from, to } => {
+                        let ledger = endpoints::database::get_ledger(id, reports::since(from));
+                        let report = reports::build(&user_budget, &ledger, from, to);
+                        http_utils::ok_json(StatusCode::OK, serde_json::to_string(&report).unwrap())
+                    }
+                    UserQuery::Notifications => {
+                        let notifications: Vec<_> = user_budget.notifications().iter().rev().collect();
+                        http_utils::ok_json(StatusCode::OK, serde_json::to_string(&notifications).unwrap())
+                    }
+                    UserQuery::Forecast { periods } => {
+                        let forecast = forecast::build(&user_budget, chrono::Local::now().date_naive(), periods);
+                        http_utils::ok_json(StatusCode::OK, serde_json::to_string(&forecast).unwrap())
+                    }
+                    UserQuery::ExportCsv { table } => {
+                        let ledger = match table {
+                            CsvTable::Ledger => endpoints::database::get_ledger(id, None),
+                            _ => Vec::new(),
+                        };
+                        http_utils::ok_download(
+                            StatusCode::OK,
+                            "text/csv",
+                            table.filename(),
+                            table.to_csv(&user_budget, &user_history, &ledger),
+                        )
+                    }
+                };
+                let _ = http_utils::send_response(response.unwrap(), &mut stream);
+            }
+            //UserImport: pay every new row of a bank statement into its expense category
+            UserThreadCommandType::UserImport { member, upload, mut stream } => {
+                time_of_last_command = Instant::now();
+                user_history.act_as(Some(&member));
+                let report = match import::parse_statement(upload.format, &upload.body, user_budget.csv_mapping()) {
+                    Ok(rows) => commands::import_statement(upload.account.as_deref(), rows, &mut user_budget, &mut user_history),
+                    Err(msg) => Err(ErrorBody::from_code(msg, None)),
+                };
+
+                //the rows are paid as one batch, so nothing was imported if any of them failed
+                let mut imported = false;
+                let response = match report {
+                    Ok(report) => {
+                        imported = !report.imported.is_empty() && report.failed.is_empty();
+                        let status = if report.failed.is_empty() { StatusCode::OK } else { StatusCode::BAD_REQUEST };
+                        http_utils::ok_json(status, serde_json::to_string(&report).unwrap())
+                    }
+                    Err(error) => http_utils::bad_request_error(&error),
+                };
+                let _ = http_utils::send_response(response.unwrap(), &mut stream);
+
+                if imported {
+                    user_history.record(user_budget.take_journal());
+                    let _ = endpoints::database::save_user_data(id, &user_budget);
+                    let _ = endpoints::database::save_user_history(id, &mut user_history);
+                }
+            }
+            //UserRestore: replace a fresh user's data with an exported document
+            UserThreadCommandType::UserRestore { member, body, mut stream } => {
+                time_of_last_command = Instant::now();
+                user_history.act_as(Some(&member));
+
+                //only budgets nothing has happened to yet can be restored into, so nothing gets overwritten
+                let document = if can_restore_into(id, &user_budget) {
+                    ExportDocument::parse(&body)
+                } else {
+                    Err(String::from("account_not_fresh"))
+                };
+
+                let response = match document {
+                    Ok(document) => {
+                        let username = user_budget.username().to_string();
+                        user_budget = document.budget;
+                        user_budget.set_username(&username);
+                        user_history.restore(document.history.ledger, document.history.periods);
+
+                        let _ = endpoints::database::save_user_data(id, &user_budget);
+                        let _ = endpoints::database::save_user_history(id, &mut user_history);
+                        http_utils::ok_json(StatusCode::OK, user_budget.to_json().unwrap())
+                    }
+                    Err(msg) => http_utils::bad_request_msg(msg),
+                };
+                let _ = http_utils::send_response(response.unwrap(), &mut stream);
+            }
             //Shutdown: exit thread loop
             UserThreadCommandType::Shutdown => {
                 println!(
@@ -301,215 +855,108 @@ fn handle_user(id: Uuid, token: String, receiver: mpsc::Receiver<UserThreadMessa
                 break 'thread_loop;
             }
             //TimeoutCheck: check how long since last command, and shut down if too long
+            //the manager is told first and waited on, so it can't hand over any more commands once the thread has gone
+            //whatever it sent before then is still answered, the loop ends when the manager drops its sender
             UserThreadCommandType::TimeoutCheck => {
-                if time_of_last_command.elapsed()
-                    > Duration::from_secs(SECONDS_TO_TIMEOUT_USER_THREAD)
+                if !forgotten
+                    && time_of_last_command.elapsed()
+                    > Duration::from_secs(config::get().session_timeout)
                 {
                     println!(
                         "shutting down thread {:?} : {:?} due to timeout",
                         thread::current().id(),
                         id
                     );
-                    break 'thread_loop;
+                    let (ack_sender, ack) = mpsc::channel();
+                    if manager.send(UserManagerThreadMessage::idle(id, ack_sender)).is_ok() {
+                        let _ = ack.recv();
+                    }
+                    forgotten = true;
                 }
                 continue 'thread_loop;
             }
-            //Check: do nothing, used for checking that channel still exists
-            UserThreadCommandType::Check => continue 'thread_loop,
-
-            //UserCommand: receive a command from the client, act accordingly
-            UserThreadCommandType::UserCommand {
+            //ScheduleCheck: post any scheduled incomes and expenses that have come due
+            UserThreadCommandType::ScheduleCheck { today } => {
+                //nobody in particular posted these
+                user_history.act_as(None);
+                user_budget.run_schedules(today);
+                let changes = user_budget.take_journal();
+                if !changes.is_empty() {
+                    user_history.record(changes);
+                    let _ = endpoints::database::save_user_data(id, &user_budget);
+                    let _ = endpoints::database::save_user_history(id, &mut user_history);
+                }
+                continue 'thread_loop;
+            }
+            //UserBatch: run a batch of commands, keeping their changes only if they all succeed
+            UserThreadCommandType::UserBatch {
+                member,
                 jsondata,
                 mut stream,
             } => {
                 time_of_last_command = Instant::now();
+                user_history.act_as(Some(&member));
 
-                //parse json message
-                let obj =
-                    serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&jsondata);
-
-                if let Err(_) = obj {
-                    http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream);
-                    continue 'thread_loop;
-                }
-
-                let obj = obj.unwrap();
-
-                //grab the command out of the object
-                let command = obj.get("command");
+                let mut committed = false;
+                let response = match commands::execute_batch(&jsondata, &mut user_budget, &mut user_history) {
+                    Ok(report) => {
+                        committed = report.committed;
+                        let mut body = serde_json::to_value(&report).unwrap();
+                        if report.committed {
+                            body["budget"] = serde_json::from_str(&user_budget.to_json().unwrap()).unwrap();
+                        }
+                        let status = if report.committed { StatusCode::OK } else { StatusCode::BAD_REQUEST };
+                        http_utils::ok_json(status, body.to_string())
+                    }
+                    Err(error) => http_utils::bad_request_error(&error),
+                };
+                let _ = http_utils::send_response(response.unwrap(), &mut stream);
 
-                //if the command isnt there, its invalid!
-                if let None = command {
-                    http_utils::send_response(http_utils::bad_request().unwrap(), &mut stream);
-                    continue 'thread_loop;
+                //a failed batch leaves the budget as it was, so there's only anything to save if it committed
+                if committed {
+                    user_history.record(user_budget.take_journal());
+                    let _ = endpoints::database::save_user_data(id, &user_budget);
+                    let _ = endpoints::database::save_user_history(id, &mut user_history);
                 }
+            }
+            //UserCommand: receive a command from the client, act accordingly
+            UserThreadCommandType::UserCommand {
+                member,
+                jsondata,
+                mut stream,
+            } => {
+                time_of_last_command = Instant::now();
+                //the ledger entries this makes are put down to whoever sent it
+                user_history.act_as(Some(&member));
 
-                let command = command.unwrap().as_str().unwrap_or("");
-
-                let result: Result<String, String> = 'command: {
-                    match command {
-                        "new" => {
-                            let Some(label) = obj.get("label") else {
-                                break 'command Err("missing_new_label_field".into());
-                            };
-                            let Some(label) = label.as_str() else {
-                                break 'command Err("invalid_new_label_field".into());
-                            };
-
-                            let Some(amount) = obj.get("amount") else {
-                                break 'command Err("missing_new_amount_field".into());
-                            };
-                            let Some(amount) = amount.as_str() else {
-                                break 'command Err("invalid_new_amount_field".into());
-                            };
-                            let Ok(amount) = amount.parse::<f64>() else {
-                                break 'command Err("invalid_new_amount_value".into());
-                            };
-
-                            user_budget.add_expense(label, budget::dollars_to_cents(amount));
-
-                            serde_json::to_string(&user_budget)
-                                .map_err(|_err| "failed_to_build_json".into())
-                        }
-                        "getpaid" => {
-                            match obj.get("amount") {
-                                Some(amount) => {
-                                    let Some(amount) = amount.as_str() else {
-                                        break 'command Err("invalid_paid_amount_field".into());
-                                    };
-                                    let Ok(amount) = amount.parse::<f64>() else {
-                                        break 'command Err("invalid_paid_amount_value".into());
-                                    };
-
-                                    user_budget.get_paid_value(budget::dollars_to_cents(amount));
-                                }
-                                None => {
-                                    user_budget.get_paid();
-                                }
-                            }
-
-                            serde_json::to_string(&user_budget)
-                                .map_err(|_err| "failed_to_build_json".into())
-                        }
-                        "setincome" => {
-                            let Some(amount) = obj.get("amount") else {
-                                break 'command Err("missing_income_amount_field".into());
-                            };
-                            let Some(amount) = amount.as_str() else {
-                                break 'command Err("invalid_income_amount_field".into());
-                            };
-                            let Ok(amount) = amount.parse::<f64>() else {
-                                break 'command Err("invalid_income_amount_value".into());
-                            };
-
-                            user_budget.set_income(budget::dollars_to_cents(amount));
-
-                            serde_json::to_string(&user_budget)
-                                .map_err(|_err| "failed_to_build_json".into())
-                        }
-                        "raiseincome" => {
-                            let Some(amount) = obj.get("amount") else {
-                                break 'command Err("missing_raise_amount_field".into());
-                            };
-                            let Some(amount) = amount.as_str() else {
-                                break 'command Err("invalid_raise_amount_field".into());
-                            };
-                            let Ok(amount) = amount.parse::<f64>() else {
-                                break 'command Err("invalid_raise_amount_value".into());
-                            };
-
-                            user_budget.add_income(budget::dollars_to_cents(amount));
-
-                            serde_json::to_string(&user_budget)
-                                .map_err(|_err| "failed_to_build_json".into())
-                        }
-                        "pay" => {
-                            let Some(label) = obj.get("label") else {
-                                break 'command Err("missing_payment_label_field".into());
-                            };
-                            let Some(label) = label.as_str() else {
-                                break 'command Err("invalid_payment_label_field".into());
-                            };
-
-                            let payment_result = match obj.get("amount") {
-                                Some(amount) => {
-                                    let Some(amount) = amount.as_str() else {
-                                        break 'command Err("invalid_payment_amount_field".into());
-                                    };
-                                    let Ok(amount) = amount.parse::<f64>() else {
-                                        break 'command Err("invalid_payment_amount_value".into());
-                                    };
-
-                                    user_budget.make_dynamic_payment(
-                                        label,
-                                        budget::dollars_to_cents(amount),
-                                    )
-                                }
-                                None => user_budget.make_static_payment(label),
-                            };
-
-                            if let Err(msg) = payment_result {
-                                break 'command Err(msg);
-                            }
-
-                            serde_json::to_string(&user_budget)
-                                .map_err(|_err| "failed_to_build_json".into())
-                        }
-                        "save" => {
-                            let Some(amount) = obj.get("amount") else {
-                                break 'command Err("missing_save_amount_field".into());
-                            };
-                            let Some(amount) = amount.as_str() else {
-                                break 'command Err("invalid_save_amount_field".into());
-                            };
-
-                            let saving_result = match amount.parse::<f64>() {
-                                Ok(amount) => user_budget.save(budget::dollars_to_cents(amount)),
-                                Err(_) => {
-                                    if amount == "all" {
-                                        user_budget.save_all()
-                                    } else {
-                                        break 'command Err("invalid_save_amount_value".into());
-                                    }
-                                }
-                            };
-
-                            if let Err(msg) = saving_result {
-                                break 'command Err(msg);
-                            }
-
-                            serde_json::to_string(&user_budget)
-                                .map_err(|_err| "failed_to_build_json".into())
-                        }
-
-                        _ => {
-                            Err("invalid-command".into())
-                            //unimplemented
-                        }
-                    }
-                }; //end command match
+                //read the command, then run it against the budget
+                let result = CommandRequest::parse(&jsondata)
+                    .and_then(|request| commands::execute(request, &mut user_budget, &mut user_history));
 
                 match result {
-                    Ok(output) => {
-                        http_utils::send_response(
-                            http_utils::ok_json(StatusCode::OK, output).unwrap(),
-                            &mut stream,
-                        );
+                    Ok(outcome) => {
+                        let response = match user_budget.to_json_with(outcome.message.as_deref(), &outcome.warnings) {
+                            Ok(output) => http_utils::ok_json(StatusCode::OK, output),
+                            Err(_err) => http_utils::bad_request_msg("failed_to_build_json".into()),
+                        };
+                        let _ = http_utils::send_response(response.unwrap(), &mut stream);
                     }
-                    Err(msg) => {
+                    Err(error) => {
                         eprintln!(
                             "thread for user {:?} failed command execution: {:?}",
-                            id, msg
+                            id, error
                         );
-                        http_utils::send_response(
-                            http_utils::bad_request_msg(msg).unwrap(),
+                        let _ = http_utils::send_response(
+                            http_utils::bad_request_error(&error).unwrap(),
                             &mut stream,
                         );
                     }
                 }
 
-                //save
+                //record whatever changed into the ledger, then save
+                user_history.record(user_budget.take_journal());
                 endpoints::database::save_user_data(id, &user_budget);
+                let _ = endpoints::database::save_user_history(id, &mut user_history);
             }
         }
 
@@ -519,4 +966,252 @@ fn handle_user(id: Uuid, token: String, receiver: mpsc::Receiver<UserThreadMessa
     }
 
     endpoints::database::save_user_data(id, &user_budget);
+    let _ = endpoints::database::save_user_history(id, &mut user_history);
+}
+
+//can_restore_into(): whether an export can be restored into the budget without overwriting anything,
+//judged by the budget and its ledger rather than its history, which budgets moved over from older versions start without
+fn can_restore_into(id: Uuid, budget: &Budget) -> bool {
+    budget.is_fresh() && database::get_ledger_len(id) == 0
+}
+
+//the budgets being worked on away from the user manager: a budget's thread holds its claim for as long as it runs,
+//and the schedule sweep holds each budget's while it updates it, so nobody loads a budget halfway through being saved
+static CLAIMED: Mutex<BTreeSet<Uuid>> = Mutex::new(BTreeSet::new());
+static RELEASED: Condvar = Condvar::new();
+
+//Claim: a hold on a budget, let go of when it's dropped
+struct Claim(Uuid);
+impl Claim {
+    //wait(): claims a budget, waiting for whoever has it to let go first
+    fn wait(budget: Uuid) -> Claim {
+        let mut claimed = CLAIMED.lock().unwrap();
+        while !claimed.insert(budget) {
+            claimed = RELEASED.wait(claimed).unwrap();
+        }
+        Claim(budget)
+    }
+
+    //take(): claims a budget, if nobody has it already
+    fn take(budget: Uuid) -> Option<Claim> {
+        let newly = CLAIMED.lock().unwrap().insert(budget);
+        newly.then(|| Claim(budget))
+    }
+}
+impl Drop for Claim {
+    fn drop(&mut self) {
+        CLAIMED.lock().unwrap().remove(&self.0);
+        RELEASED.notify_all();
+    }
+}
+
+//sweep_schedules(): posts the scheduled incomes and expenses that have come due for every budget nobody has open
+//budgets that are open are claimed by their thread, which gets a schedule check of its own, so they're skipped
+fn sweep_schedules(today: NaiveDate) {
+    for budget in database::get_budget_ids() {
+        if let Some(_claim) = Claim::take(budget) {
+            run_schedules_for(budget, today);
+        }
+    }
+}
+
+//run_schedules_for(): posts the scheduled incomes and expenses that have come due
+//for a user who isn't logged in, straight from and back into the database
+fn run_schedules_for(id: Uuid, today: NaiveDate) {
+    let mut user_budget: Budget = users::get_user_data_from_uuid(id);
+    user_budget.run_schedules(today);
+
+    let changes = user_budget.take_journal();
+    if changes.is_empty() {
+        return;
+    }
+
+    let mut user_history: History = users::get_user_history_from_uuid(id);
+    user_history.record(changes);
+    let _ = endpoints::database::save_user_data(id, &user_budget);
+    let _ = endpoints::database::save_user_history(id, &mut user_history);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::history::{EntryKind, LedgerEntry};
+    use serde_json::json;
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    //manager(): a user manager thread of the test's own, and its channel
+    fn manager() -> mpsc::Sender<UserManagerThreadMessage> {
+        db::for_tests();
+        let (sender, receiver) = mpsc::channel();
+        let (to_main, _) = mpsc::channel();
+        let to_self = sender.clone();
+        thread::spawn(move || handle_user_threads(to_main, receiver, to_self));
+        sender
+    }
+
+    //registered(): a new user with a budget of their own, and that budget
+    fn registered() -> (Uuid, Uuid) {
+        db::for_tests();
+        let username = Uuid::new_v4().simple().to_string();
+        let Ok((user, _token)) = users::register(json!({"username": username, "password": "hunter2"}).to_string()) else {
+            panic!("couldn't register {}", username);
+        };
+        (user, database::default_budget(user).unwrap())
+    }
+
+    //login(): opens a session for a user under the given token
+    fn login(manager: &mpsc::Sender<UserManagerThreadMessage>, user: Uuid, token: &str) {
+        manager.send(UserManagerThreadMessage::creation(metrics::start(), user, token.to_string())).unwrap();
+    }
+
+    //request(): sends the manager a message carrying the server's end of a new connection,
+    //and reads back the status and json body of the response it gets
+    fn request(
+        manager: &mpsc::Sender<UserManagerThreadMessage>,
+        message: impl FnOnce(usize, TimedStream) -> UserManagerThreadMessage,
+    ) -> (u16, serde_json::Value) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let stream = TimedStream::new(listener.accept().unwrap().0);
+        manager.send(message(stream.id, stream)).unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap_or_default())
+    }
+
+    //command(): sends a command under a token, giving back the response's status
+    fn command(manager: &mpsc::Sender<UserManagerThreadMessage>, token: &str, command: serde_json::Value) -> u16 {
+        request(manager, |id, stream| UserManagerThreadMessage::user_command(id, token.to_string(), command.to_string(), stream)).0
+    }
+
+    #[test]
+    fn sessions_on_one_budget_share_its_thread() {
+        let manager = manager();
+        let (owner, budget) = registered();
+        let (editor, _) = registered();
+        database::add_member(budget, editor, Role::Editor).unwrap();
+        let (owner_token, editor_token) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+        login(&manager, owner, &owner_token);
+        login(&manager, editor, &editor_token);
+        let switch = json!({"action": "switch", "budget": budget}).to_string();
+        let (status, _) = request(&manager, |id, stream| UserManagerThreadMessage::budgets(id, editor_token.clone(), Some(switch), stream));
+        assert_eq!(status, 200);
+
+        //commands from both sessions, one after the other, all go into the same budget
+        for (token, label) in [(&owner_token, "rent"), (&editor_token, "food"), (&owner_token, "gym"), (&editor_token, "fuel")] {
+            assert_eq!(command(&manager, token, json!({"command": "new", "label": label, "amount": 10})), 200);
+        }
+        for token in [&owner_token, &editor_token] {
+            let (_, data) = request(&manager, |id, stream| UserManagerThreadMessage::user_data_request(id, token.clone(), stream));
+            let mut labels: Vec<&String> = data["expected_expenses"].as_object().unwrap().keys().collect();
+            labels.sort();
+            assert_eq!(labels, ["food", "fuel", "gym", "rent"]);
+        }
+    }
+
+    //reopening the budget a session is already on keeps the budget's thread running
+    #[test]
+    fn switching_to_the_same_budget_keeps_its_thread() {
+        let manager = manager();
+        let (owner, budget) = registered();
+        let token = Uuid::new_v4().to_string();
+        login(&manager, owner, &token);
+        assert_eq!(command(&manager, &token, json!({"command": "new", "label": "rent", "amount": 10})), 200);
+
+        let switch = json!({"action": "switch", "budget": budget}).to_string();
+        let (status, _) = request(&manager, |id, stream| UserManagerThreadMessage::budgets(id, token.clone(), Some(switch), stream));
+        assert_eq!(status, 200);
+        assert_eq!(command(&manager, &token, json!({"command": "new", "label": "food", "amount": 10})), 200);
+    }
+
+    //username(): the username of someone on a budget
+    fn username(budget: Uuid, user: Uuid) -> String {
+        database::get_member(budget, user).unwrap().username
+    }
+
+    #[test]
+    fn invites_go_by_username_and_roles_decide_what_members_can_do() {
+        let manager = manager();
+        let (owner, budget) = registered();
+        let (member, own_budget) = registered();
+        let (owner_token, member_token) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+        login(&manager, owner, &owner_token);
+        login(&manager, member, &member_token);
+        let members = |token: &str, body: serde_json::Value| {
+            request(&manager, |id, stream| UserManagerThreadMessage::members(id, token.to_string(), Some(body.to_string()), stream))
+        };
+        let budgets = |token: &str, body: serde_json::Value| {
+            request(&manager, |id, stream| UserManagerThreadMessage::budgets(id, token.to_string(), Some(body.to_string()), stream))
+        };
+
+        //the owner invites by username, and the invited user has to accept before they can switch over
+        let name = username(own_budget, member);
+        assert_eq!(members(&owner_token, json!({"action": "invite", "username": "nobody-by-this-name", "role": "viewer"})).0, 400);
+        assert_eq!(members(&owner_token, json!({"action": "invite", "username": name, "role": "owner"})).0, 400);
+        assert_eq!(members(&owner_token, json!({"action": "invite", "username": name, "role": "viewer"})).0, 200);
+        assert_eq!(budgets(&member_token, json!({"action": "switch", "budget": budget})).0, 400);
+        assert_eq!(budgets(&member_token, json!({"action": "accept", "budget": budget})).0, 200);
+        assert_eq!(members(&owner_token, json!({"action": "invite", "username": name, "role": "editor"})).0, 400);
+        assert_eq!(budgets(&member_token, json!({"action": "switch", "budget": budget})).0, 200);
+
+        //a viewer can read the budget, but not change it or its members
+        let (status, data) = request(&manager, |id, stream| UserManagerThreadMessage::user_data_request(id, member_token.clone(), stream));
+        assert_eq!(status, 200);
+        assert!(data["expected_expenses"].is_object());
+        let (status, error) = request(&manager, |id, stream| {
+            let rent = json!({"command": "new", "label": "rent", "amount": 10}).to_string();
+            UserManagerThreadMessage::user_command(id, member_token.clone(), rent, stream)
+        });
+        assert_eq!((status, error["code"].as_str()), (403, Some("read_only_member")));
+        let (status, error) = members(&member_token, json!({"action": "remove", "username": username(budget, owner)}));
+        assert_eq!((status, error["code"].as_str()), (403, Some("owner_only")));
+
+        //made an editor, the same session can change it straight away
+        assert_eq!(members(&owner_token, json!({"action": "role", "username": name, "role": "editor"})).0, 200);
+        assert_eq!(command(&manager, &member_token, json!({"command": "new", "label": "rent", "amount": 10})), 200);
+    }
+
+    #[test]
+    fn only_untouched_budgets_can_be_restored_into() {
+        db::for_tests();
+        assert!(can_restore_into(Uuid::new_v4(), &Budget::new("pat".into())));
+
+        //a budget migrated from the users table has its data but no history or ledger to go with it
+        let mut migrated = Budget::new("pat".into());
+        migrated.set_income(250000).unwrap();
+        migrated.add_expense("rent", 120000, None);
+        assert!(!can_restore_into(Uuid::new_v4(), &migrated));
+
+        //and one that's been emptied back out still has its ledger
+        let id = Uuid::new_v4();
+        let mut history = History::default();
+        history.record(vec![LedgerEntry::new(EntryKind::Deposit, None, "income", 500, 0)]);
+        database::save_user_history(id, &mut history).unwrap();
+        assert!(!can_restore_into(id, &Budget::new("pat".into())));
+    }
+
+    #[test]
+    fn claimed_budgets_are_left_alone_until_let_go() {
+        let budget = Uuid::new_v4();
+        let claim = Claim::take(budget).unwrap();
+        assert!(Claim::take(budget).is_none());
+
+        //a thread opening the budget waits for the sweep (or its last thread) to be done with it
+        let (opened, waiting) = mpsc::channel();
+        let opener = thread::spawn(move || {
+            let _claim = Claim::wait(budget);
+            opened.send(()).unwrap();
+        });
+        assert!(waiting.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(claim);
+        waiting.recv_timeout(Duration::from_secs(10)).unwrap();
+        opener.join().unwrap();
+        assert!(Claim::take(budget).is_some());
+    }
 }
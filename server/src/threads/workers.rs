@@ -0,0 +1,194 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::metrics;
+use crate::server::TimedStream;
+
+//WorkerPool: a fixed number of worker threads that take turns reading and answering connections,
+//so one slow client only ties up one worker instead of the whole server
+//answered connections that are kept alive are queued up again, for a worker to wait on their next request
+//(tying that worker up for as long as keep_alive_timeout, so it's kept short)
+pub struct WorkerPool {
+    sender: mpsc::SyncSender<TimedStream>,
+    //connections open, whether waiting for a worker, being read by one, being answered, or idle
+    connections: Arc<AtomicUsize>,
+    max_connections: usize,
+}
+impl WorkerPool {
+    //new(): spawns the workers, which each run the given handler on every request they're handed
+    //a kept-alive connection gets keep_alive_timeout to start sending its next request before it's closed
+    pub fn new<F>(workers: usize, max_connections: usize, keep_alive_timeout: Duration, handler: F) -> WorkerPool
+    where
+        F: Fn(TimedStream) + Send + Sync + 'static,
+    {
+        //never more waiting in the queue than could be open at once
+        let (sender, receiver) = mpsc::sync_channel::<TimedStream>(max_connections);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+
+        for number in 0..workers.max(1) {
+            let receiver = Arc::clone(&receiver);
+            let handler = Arc::clone(&handler);
+            thread::Builder::new()
+                .name(format!("worker_{}", number))
+                .spawn(move || work(receiver, handler, keep_alive_timeout))
+                .expect("failed to create worker thread: OS error");
+        }
+
+        WorkerPool {
+            sender,
+            connections: Arc::new(AtomicUsize::new(0)),
+            max_connections,
+        }
+    }
+
+    //dispatch(): hands a new connection to the next free worker, or closes it straight away
+    //if max_connections are already open. never blocks or writes to the connection, so the accept loop keeps accepting
+    //(a 503 would mean writing, or for TLS a whole handshake, with no timeouts set yet)
+    pub fn dispatch(&self, mut stream: TimedStream) {
+        if self.connections.fetch_add(1, Ordering::SeqCst) >= self.max_connections {
+            self.connections.fetch_sub(1, Ordering::SeqCst);
+            eprintln!("connection {} turned away, {} already open", stream.id, self.max_connections);
+            return;
+        }
+        stream.attach(Slot {
+            connections: Arc::clone(&self.connections),
+            queue: self.sender.clone(),
+        });
+
+        //the queue holds max_connections, so this only fails if every worker is gone
+        if self.sender.try_send(stream).is_err() {
+            eprintln!("connection turned away, no workers left");
+        }
+    }
+}
+
+//Slot: a connection's place in the pool, counting towards max_connections until the connection closes
+#[derive(Debug)]
+pub struct Slot {
+    connections: Arc<AtomicUsize>,
+    queue: mpsc::SyncSender<TimedStream>,
+}
+impl Slot {
+    //queue(): where the connection goes once it's been answered, to wait for its next request
+    //the queue holds max_connections, and the connection counts towards them, so there's always room for it
+    pub fn queue(&self) -> mpsc::SyncSender<TimedStream> {
+        self.queue.clone()
+    }
+}
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+//work(): a worker thread's loop, taking requests off the queue one at a time until the pool is dropped
+//a kept-alive connection is waited on (blocking) for up to keep_alive_timeout, and closed if its next request doesn't start
+fn work<F>(receiver: Arc<Mutex<mpsc::Receiver<TimedStream>>>, handler: Arc<F>, keep_alive_timeout: Duration)
+where
+    F: Fn(TimedStream) + Send + Sync + 'static,
+{
+    eprintln!("\t\tworker thread spawned:\t{}", metrics::thread_name_display());
+
+    loop {
+        //the lock is only held while waiting, not while handling
+        let Ok(mut stream) = receiver.lock().unwrap().recv() else {
+            break;
+        };
+        if stream.waiting() {
+            if !stream.wait_for_request(keep_alive_timeout) {
+                continue;
+            }
+            stream.restart();
+        }
+        let id = stream.id;
+
+        //a panic answering one request shouldn't take the worker down with it
+        if panic::catch_unwind(AssertUnwindSafe(|| handler(stream))).is_err() {
+            eprintln!("{} panicked handling connection {}", metrics::thread_name_display(), id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    //connect(): a client connection, and the server's end of it
+    fn connect(listener: &TcpListener) -> (TcpStream, TimedStream) {
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        (client, TimedStream::new(listener.accept().unwrap().0))
+    }
+
+    //closed(): waits (for a while) until the pool has no connections open
+    fn closed(pool: &WorkerPool) -> bool {
+        for _ in 0..1000 {
+            if pool.connections.load(Ordering::SeqCst) == 0 {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn connections_past_the_limit_are_closed_straight_away() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (handled, handling) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let (handled, released) = (Mutex::new(handled), Mutex::new(released));
+        //two workers, so a free one is there for a connection over the limit if it were let through
+        let pool = WorkerPool::new(2, 1, Duration::from_secs(1), move |stream: TimedStream| {
+            handled.lock().unwrap().send(stream.id).unwrap();
+            let _ = released.lock().unwrap().recv();
+        });
+
+        let (_first, stream) = connect(&listener);
+        let first = stream.id;
+        pool.dispatch(stream);
+        assert_eq!(handling.recv_timeout(Duration::from_secs(10)), Ok(first));
+
+        //closed without a word, and never handed to a worker
+        let (mut second, stream) = connect(&listener);
+        pool.dispatch(stream);
+        let mut answer = Vec::new();
+        assert_eq!(second.read_to_end(&mut answer).unwrap(), 0);
+        assert!(handling.recv_timeout(Duration::from_millis(100)).is_err());
+
+        //once the first is done and closed there's room again
+        release.send(()).unwrap();
+        assert!(closed(&pool));
+        let (_third, stream) = connect(&listener);
+        let third = stream.id;
+        pool.dispatch(stream);
+        assert_eq!(handling.recv_timeout(Duration::from_secs(10)), Ok(third));
+        release.send(()).unwrap();
+    }
+
+    #[test]
+    fn a_panicking_handler_gives_its_connection_back() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (handled, handling) = mpsc::channel();
+        let handled = Mutex::new(handled);
+        let pool = WorkerPool::new(1, 1, Duration::from_secs(1), move |stream: TimedStream| {
+            handled.lock().unwrap().send(stream.id).unwrap();
+            panic!("handler failed");
+        });
+
+        //the one worker survives, and each connection's slot is freed for the next
+        for _ in 0..3 {
+            let (mut client, stream) = connect(&listener);
+            let id = stream.id;
+            pool.dispatch(stream);
+            assert_eq!(handling.recv_timeout(Duration::from_secs(10)), Ok(id));
+            assert_eq!(client.read_to_end(&mut Vec::new()).unwrap(), 0);
+            assert!(closed(&pool));
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::BTreeSet;
+
+use serde::{self, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::budget::Budget;
+use crate::history::LedgerEntry;
+use crate::period::PeriodSnapshot;
+
+//how many commands each member can undo
+pub const UNDO_LIMIT: usize = 20;
+
+//parts of the budget undo leaves alone: warnings stay until they're cleared
+const UNTRACKED: [&str; 1] = ["notifications"];
+
+//whole numbers in the budget that count or date things rather than hold amounts,
+//so they can't be merged with changes other members made since
+const NOT_AMOUNTS: [&[&str]; 2] = [&["period", "number"], &["period", "started"]];
+
+//Change: one part of the budget a command changed, found by its path through the budget's json,
+//with its value before and after the command (none when it didn't exist)
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Change {
+    pub path: Vec<String>,
+    #[serde(default)]
+    pub before: Option<Value>,
+    #[serde(default)]
+    pub after: Option<Value>,
+}
+
+//UndoStep: a command that can be undone (or redone), holding only what it changed
+//undoing it puts those parts of the budget back, leaving everything anyone else changed since alone
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UndoStep {
+    pub command: String,
+    pub changes: Vec<Change>,
+    //periods the command archived, taken off the history when it's undone and put back when it's redone
+    #[serde(default)]
+    pub archived: Vec<PeriodSnapshot>,
+    //the ledger entries the command made, reversed on the ledger when it's undone and repeated when it's redone
+    #[serde(default)]
+    pub entries: Vec<LedgerEntry>,
+}
+impl UndoStep {
+    //new(): the step for a command that turned the budget before into the budget after
+    pub fn new(command: &str, before: &Budget, after: &Budget, archived: Vec<PeriodSnapshot>, entries: Vec<LedgerEntry>) -> UndoStep {
+        let before = serde_json::to_value(before).unwrap();
+        let after = serde_json::to_value(after).unwrap();
+        let mut changes = Vec::new();
+        diff(&mut Vec::new(), Some(&before), Some(&after), &mut changes);
+        UndoStep {
+            command: command.to_string(),
+            changes,
+            archived,
+            entries,
+        }
+    }
+
+    //undo(): the budget with the command's changes taken back out of it
+    pub fn undo(&self, budget: &Budget) -> Result<Budget, String> {
+        shift(budget, &self.changes, true).ok_or_else(|| String::from("undo_conflict"))
+    }
+
+    //redo(): the budget with the command's changes made again
+    pub fn redo(&self, budget: &Budget) -> Result<Budget, String> {
+        shift(budget, &self.changes, false).ok_or_else(|| String::from("redo_conflict"))
+    }
+}
+
+//UndoStacks: one member's commands that can be undone, and undone commands that can be redone, newest last
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct UndoStacks {
+    pub undo: Vec<UndoStep>,
+    pub redo: Vec<UndoStep>,
+}
+impl UndoStacks {
+    //push_undo(): remembers a command that can be undone, forgetting the oldest one past UNDO_LIMIT
+    pub fn push_undo(&mut self, step: UndoStep) {
+        self.undo.push(step);
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.undo.is_empty() && self.redo.is_empty()
+    }
+}
+
+//diff(): notes down every part of the budget's json that differs between before and after, under path
+//objects are compared key by key, anything else (numbers, text, lists) as a whole. nulls count as missing
+fn diff(path: &mut Vec<String>, before: Option<&Value>, after: Option<&Value>, changes: &mut Vec<Change>) {
+    let before = before.filter(|value| !value.is_null());
+    let after = after.filter(|value| !value.is_null());
+    match (before, after) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => {
+            let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+            for key in keys {
+                if path.is_empty() && UNTRACKED.contains(&key.as_str()) {
+                    continue;
+                }
+                path.push(key.clone());
+                diff(path, before.get(key), after.get(key), changes);
+                path.pop();
+            }
+        }
+        (before, after) if before != after => changes.push(Change {
+            path: path.clone(),
+            before: before.cloned(),
+            after: after.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+//shift(): moves the budget back across the changes (or forward, when redoing), if nothing stands in the way
+//each part changed has to still be how the command left it, except amounts, which take the difference
+//so other members' payments and deposits since are kept. none if anything else changed in the meantime
+fn shift(budget: &Budget, changes: &[Change], backwards: bool) -> Option<Budget> {
+    let mut value = serde_json::to_value(budget).ok()?;
+    for change in changes {
+        let (from, to) = if backwards {
+            (change.after.as_ref(), change.before.as_ref())
+        } else {
+            (change.before.as_ref(), change.after.as_ref())
+        };
+        let current = lookup(&value, &change.path);
+
+        let target = if current == from {
+            to.cloned()
+        } else {
+            let is_amount = !NOT_AMOUNTS.iter().any(|path| path.iter().eq(change.path.iter()));
+            let (from, to, current) = (from?.as_i64()?, to?.as_i64()?, current?.as_i64()?);
+            if !is_amount {
+                return None;
+            }
+            Some(Value::from(current.checked_add(to.checked_sub(from)?)?))
+        };
+        place(&mut value, &change.path, target)?;
+    }
+
+    let budget: Budget = serde_json::from_value(value).ok()?;
+    budget.validate().ok()?;
+    Some(budget)
+}
+
+//lookup(): the value at path, none if it's missing or null
+fn lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(value, |value, key| value.get(key))
+        .filter(|value| !value.is_null())
+}
+
+//place(): sets (or, given none, removes) the value at path. none if the object it goes in is gone
+fn place(value: &mut Value, path: &[String], target: Option<Value>) -> Option<()> {
+    let (key, parents) = path.split_last()?;
+    let parent = parents
+        .iter()
+        .try_fold(value, |value, key| value.get_mut(key))?
+        .as_object_mut()?;
+    match target {
+        Some(target) => parent.insert(key.clone(), target),
+        None => parent.remove(key),
+    };
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{self, CommandRequest};
+    use crate::history::History;
+    use serde_json::json;
+
+    //send(): runs one command as the given member
+    fn send(budget: &mut Budget, history: &mut History, member: &str, value: serde_json::Value) -> Result<String, String> {
+        history.act_as(Some(member));
+        CommandRequest::parse(&value.to_string())
+            .and_then(|request| commands::execute(request, budget, history))
+            .map(|outcome| outcome.message.unwrap_or_default())
+            .map_err(|error| error.code)
+    }
+
+    //current(): how much has been spent on an expense this period
+    fn current(budget: &Budget, label: &str) -> i64 {
+        budget.expense_rows().into_iter().find(|(name, _, _)| name == label).map(|(_, _, current)| current).unwrap()
+    }
+
+    //shared(): a budget with a paycheck in it and two expenses, set up by ann
+    fn shared() -> (Budget, History) {
+        let mut budget = Budget::new(String::from("ann"));
+        let mut history = History::default();
+        send(&mut budget, &mut history, "ann", json!({"command": "setincome", "amount": 100})).unwrap();
+        send(&mut budget, &mut history, "ann", json!({"command": "getpaid"})).unwrap();
+        send(&mut budget, &mut history, "ann", json!({"command": "new", "label": "rent", "amount": 50})).unwrap();
+        send(&mut budget, &mut history, "ann", json!({"command": "new", "label": "food", "amount": 30})).unwrap();
+        (budget, history)
+    }
+
+    #[test]
+    fn steps_hold_only_what_changed() {
+        let (mut budget, mut history) = shared();
+        let before = budget.clone();
+        send(&mut budget, &mut history, "ann", json!({"command": "pay", "label": "rent", "amount": 20})).unwrap();
+
+        let step = UndoStep::new("pay", &before, &budget, Vec::new(), Vec::new());
+        let paths: Vec<String> = step.changes.iter().map(|change| change.path.join(".")).collect();
+        assert_eq!(paths, ["accounts.checking.balance", "current_expenses.rent"]);
+    }
+
+    #[test]
+    fn undo_takes_back_only_the_members_own_command() {
+        let (mut budget, mut history) = shared();
+        send(&mut budget, &mut history, "ann", json!({"command": "pay", "label": "rent", "amount": 20})).unwrap();
+        send(&mut budget, &mut history, "bob", json!({"command": "pay", "label": "food", "amount": 5})).unwrap();
+
+        assert_eq!(send(&mut budget, &mut history, "ann", json!({"command": "undo"})).unwrap(), "Undid pay");
+        assert_eq!(budget.primary_balance(), 9500);
+        assert_eq!((current(&budget, "rent"), current(&budget, "food")), (0, 500));
+
+        //bob's payment is still his to undo, and ann's next undo is her own expense
+        assert_eq!(send(&mut budget, &mut history, "bob", json!({"command": "undo"})).unwrap(), "Undid pay");
+        assert_eq!(budget.primary_balance(), 10000);
+        assert_eq!(send(&mut budget, &mut history, "bob", json!({"command": "undo"})), Err(String::from("nothing_to_undo")));
+        send(&mut budget, &mut history, "ann", json!({"command": "undo"})).unwrap();
+        assert!(budget.expense_rows().iter().all(|(name, _, _)| name != "food"));
+    }
+
+    #[test]
+    fn undo_refuses_to_overwrite_someone_elses_change() {
+        let (mut budget, mut history) = shared();
+        send(&mut budget, &mut history, "ann", json!({"command": "edit", "label": "rent", "automatic": true})).unwrap();
+        send(&mut budget, &mut history, "bob", json!({"command": "edit", "label": "rent", "automatic": false})).unwrap();
+        let before = serde_json::to_string(&budget).unwrap();
+
+        assert_eq!(send(&mut budget, &mut history, "ann", json!({"command": "undo"})), Err(String::from("undo_conflict")));
+        assert_eq!(serde_json::to_string(&budget).unwrap(), before);
+
+        //once bob takes his change back, ann's can be too
+        send(&mut budget, &mut history, "bob", json!({"command": "undo"})).unwrap();
+        send(&mut budget, &mut history, "ann", json!({"command": "undo"})).unwrap();
+    }
+
+    #[test]
+    fn redo_puts_back_archived_periods() {
+        let (mut budget, mut history) = shared();
+        send(&mut budget, &mut history, "ann", json!({"command": "getpaid"})).unwrap();
+        assert_eq!(history.periods().len(), 2);
+
+        send(&mut budget, &mut history, "ann", json!({"command": "undo"})).unwrap();
+        assert_eq!((history.periods().len(), budget.primary_balance()), (1, 10000));
+        send(&mut budget, &mut history, "ann", json!({"command": "redo"})).unwrap();
+        assert_eq!((history.periods().len(), budget.primary_balance()), (2, 20000));
+
+        //a new command means there's nothing left to redo
+        send(&mut budget, &mut history, "ann", json!({"command": "undo"})).unwrap();
+        send(&mut budget, &mut history, "ann", json!({"command": "pay", "label": "rent", "amount": 1})).unwrap();
+        assert_eq!(send(&mut budget, &mut history, "ann", json!({"command": "redo"})), Err(String::from("nothing_to_redo")));
+    }
+}